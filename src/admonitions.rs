@@ -0,0 +1,189 @@
+// Call-out boxes written either as GitHub style blockquotes starting with
+// [!NOTE] or as :::note ... ::: containers, rendered as asides with a title.
+// Only kinds present in the admonitions configuration map are recognized, the
+// map giving the CSS class to use for each.
+use std::collections::BTreeMap;
+
+use pulldown_cmark::{CowStr, Event, Tag};
+
+use crate::util::{capitalize, escape_html};
+
+const CONTAINER_FENCE: &str = ":::";
+
+// Turns container lines into raw HTML surrounded by blank lines, so that the
+// markdown between them is still parsed as usual. Also returns the indices of
+// the lines that were added, for mapping back to lines of the input.
+pub fn expand_containers(
+	markdown_content: &str,
+	classes: &BTreeMap<String, String>,
+) -> (String, Vec<usize>) {
+	let mut inserted_lines = Vec::new();
+	if !markdown_content.contains(CONTAINER_FENCE) {
+		return (markdown_content.to_string(), inserted_lines);
+	}
+
+	let mut result = String::with_capacity(markdown_content.len());
+	let mut open_containers = 0;
+	let mut code_fence: Option<(char, usize)> = None;
+	for (line_index, line) in markdown_content.split_inclusive('\n').enumerate()
+	{
+		let trimmed = line.trim();
+		let result_line_index = line_index + inserted_lines.len();
+
+		if let Some(fence) = parse_code_fence(trimmed) {
+			match code_fence {
+				None => code_fence = Some(fence),
+				Some((c, length)) if fence.0 == c && fence.1 >= length => {
+					code_fence = None
+				}
+				_ => {}
+			}
+			result.push_str(line);
+			continue;
+		}
+		if code_fence.is_some() {
+			result.push_str(line);
+			continue;
+		}
+
+		if trimmed == CONTAINER_FENCE && open_containers > 0 {
+			result.push_str("\n</aside>\n\n");
+			inserted_lines.push(result_line_index);
+			inserted_lines.push(result_line_index + 2);
+			open_containers -= 1;
+			continue;
+		}
+
+		if let Some(rest) = trimmed.strip_prefix(CONTAINER_FENCE) {
+			let rest = rest.trim_start();
+			let (kind, title) = match rest.find(char::is_whitespace) {
+				Some(end) => (&rest[..end], rest[end..].trim()),
+				None => (rest, ""),
+			};
+			if let Some(class) = classes.get(&kind.to_lowercase()) {
+				result.push('\n');
+				result.push_str(&make_start_html(kind, title, class));
+				result.push('\n');
+				inserted_lines.push(result_line_index);
+				inserted_lines.push(result_line_index + 2);
+				inserted_lines.push(result_line_index + 3);
+				open_containers += 1;
+				continue;
+			}
+		}
+
+		result.push_str(line);
+	}
+
+	if open_containers > 0 {
+		println!(
+			"WARNING: {} admonition container(s) missing closing {}.",
+			open_containers, CONTAINER_FENCE
+		);
+		for _ in 0..open_containers {
+			result.push_str("\n</aside>\n");
+		}
+	}
+
+	(result, inserted_lines)
+}
+
+fn parse_code_fence(line: &str) -> Option<(char, usize)> {
+	let c = line.chars().next()?;
+	if c != '`' && c != '~' {
+		return None;
+	}
+	let length = line.chars().take_while(|&d| d == c).count();
+	if length >= 3 {
+		Some((c, length))
+	} else {
+		None
+	}
+}
+
+pub fn convert_blockquotes<'a>(
+	events: Vec<Event<'a>>,
+	classes: &BTreeMap<String, String>,
+) -> Vec<Event<'a>> {
+	let mut result = Vec::with_capacity(events.len());
+	// Whether each currently open blockquote was turned into an aside.
+	let mut blockquotes = Vec::new();
+	let mut i = 0;
+	while i < events.len() {
+		match &events[i] {
+			Event::Start(Tag::BlockQuote) => {
+				if let Some((html, consumed)) =
+					parse_marker(&events[i..], classes)
+				{
+					result.push(Event::Html(CowStr::from(html)));
+					blockquotes.push(true);
+					i += consumed;
+					continue;
+				}
+				blockquotes.push(false);
+				result.push(events[i].clone());
+			}
+			Event::End(Tag::BlockQuote) => {
+				if blockquotes.pop().unwrap_or(false) {
+					result.push(Event::Html(CowStr::from("</aside>\n")));
+				} else {
+					result.push(events[i].clone());
+				}
+			}
+			_ => result.push(events[i].clone()),
+		}
+		i += 1;
+	}
+	result
+}
+
+// Looks for [!KIND] optionally followed by a title at the start of the
+// blockquote's first paragraph, returning the opening HTML and how many events
+// it replaces.
+fn parse_marker(
+	events: &[Event],
+	classes: &BTreeMap<String, String>,
+) -> Option<(String, usize)> {
+	if events.get(1) != Some(&Event::Start(Tag::Paragraph)) {
+		return None;
+	}
+
+	// The brackets typically end up as separate text events.
+	let mut first_line = String::new();
+	let mut consumed = 2;
+	while let Some(Event::Text(text)) = events.get(consumed) {
+		first_line.push_str(text);
+		consumed += 1;
+	}
+
+	let rest = first_line.trim_start().strip_prefix("[!")?;
+	let end = rest.find(']')?;
+	let kind = rest[..end].to_lowercase();
+	let class = classes.get(&kind)?;
+	let title = rest[end + 1..].trim();
+
+	let mut html = make_start_html(&kind, title, class);
+	match events.get(consumed) {
+		// Nothing but the marker in the paragraph.
+		Some(Event::End(Tag::Paragraph)) => consumed += 1,
+		Some(Event::SoftBreak) | Some(Event::HardBreak) => {
+			consumed += 1;
+			html.push_str("<p>");
+		}
+		_ => html.push_str("<p>"),
+	}
+	Some((html, consumed))
+}
+
+fn make_start_html(kind: &str, title: &str, class: &str) -> String {
+	let title = if title.is_empty() {
+		capitalize(&kind.to_lowercase())
+	} else {
+		title.to_string()
+	};
+	format!(
+		"<aside class=\"admonition {}\">\n<p class=\"admonition-title\">{}</p>\n",
+		escape_html(class),
+		escape_html(&title)
+	)
+}
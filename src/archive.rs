@@ -0,0 +1,152 @@
+// Year and month archive pages are virtual inputs, registered per group
+// directory in the same manner as tag pages. They are only generated if the site provides the
+// template to render them with.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::front_matter::FrontMatter;
+use crate::markdown::{GroupedOptionOutputFile, InputFile};
+use crate::util;
+use crate::util::strip_prefix;
+
+pub const TEMPLATE_PATH: &str = "_layouts/archive.html";
+
+pub fn enabled(input_dir: &Path) -> bool {
+	input_dir.join(TEMPLATE_PATH).exists()
+}
+
+// Returns the relative directories of the archive pages the file should be
+// listed on, such as "posts/2020" and "posts/2020/05". They start from the
+// directory of the file rather than the name of its group, keeping apart groups
+// of the same name such as a/posts and b/posts.
+pub fn get_periods(
+	input_file_path: &Path,
+	input_dir: &Path,
+	front_matter: &FrontMatter,
+) -> Vec<PathBuf> {
+	let date = if let Some(date) = &front_matter.date {
+		date
+	} else {
+		return Vec::new();
+	};
+
+	let bytes = date.as_bytes();
+	if bytes.len() < 7
+		|| !bytes[..4].iter().all(u8::is_ascii_digit)
+		|| bytes[4] != b'-'
+		|| !bytes[5..7].iter().all(u8::is_ascii_digit)
+	{
+		println!(
+			"Not archiving \"{}\" due to unrecognized date: {}",
+			front_matter.title, date
+		);
+		return Vec::new();
+	}
+
+	let directory = strip_prefix(
+		input_file_path.parent().unwrap_or_else(|| {
+			panic!("Failed to get parent from: {}", input_file_path.display())
+		}),
+		input_dir,
+	);
+	let year = directory.join(&date[..4]);
+	let month = year.join(&date[5..7]);
+	vec![year, month]
+}
+
+pub fn make_input_path(input_dir: &Path, period: &Path) -> PathBuf {
+	input_dir.join(period).join("index.html")
+}
+
+pub fn make_output_path(output_dir: &Path, period: &Path) -> PathBuf {
+	output_dir.join(period).join("index.html")
+}
+
+// Whether another page is generated in place of the archive page of the
+// period, which is then left out.
+pub fn collides(
+	period: &Path,
+	input_dir: &Path,
+	output_dir: &Path,
+	input_output_map: &HashMap<PathBuf, GroupedOptionOutputFile>,
+) -> bool {
+	let input_file_path = make_input_path(input_dir, period);
+	let output_file_path = make_output_path(output_dir, period);
+	let page = if input_output_map.contains_key(&input_file_path) {
+		Some(&input_file_path)
+	} else {
+		input_output_map
+			.iter()
+			.find(|(_, output)| output.file.path == output_file_path)
+			.map(|(input, _)| input)
+	};
+	if let Some(page) = page {
+		println!(
+			"WARNING: Not generating archive page {} from {}, as {} is generated there.",
+			output_file_path.display(),
+			input_dir.join(TEMPLATE_PATH).display(),
+			page.display()
+		);
+	}
+	page.is_some()
+}
+
+// Splits a period into the directory of its group, the year and the month,
+// which is empty for years. Months are told apart from years in directories
+// named like them by their length.
+pub fn split_period(period: &Path) -> (&Path, String, String) {
+	let name = |path: &Path| {
+		path.file_name()
+			.map_or_else(String::new, |name| name.to_string_lossy().to_string())
+	};
+	let parent = period.parent().unwrap_or_else(|| Path::new(""));
+	let last = name(period);
+	if last.len() == 2 {
+		let directory = parent.parent().unwrap_or_else(|| Path::new(""));
+		(directory, name(parent), last)
+	} else {
+		(parent, last, String::new())
+	}
+}
+
+pub fn make_front_matter(period: &Path) -> FrontMatter {
+	let (directory, year, month) = split_period(period);
+	let group = directory.file_name().map_or_else(
+		|| panic!("Expected group in archive period: {}", period.display()),
+		|group| group.to_string_lossy(),
+	);
+	let date = if month.is_empty() {
+		year
+	} else {
+		format!("{}-{}", year, month)
+	};
+	FrontMatter::with_title(format!("{}: {}", util::capitalize(&group), date))
+}
+
+// Previous is the closest older period of the same group and granularity, next
+// is the closest newer one.
+pub fn get_neighbours<'a>(
+	period: &Path,
+	archives: &'a HashMap<PathBuf, Vec<InputFile>>,
+) -> (Option<&'a PathBuf>, Option<&'a PathBuf>) {
+	let (directory, _, month) = split_period(period);
+	let mut previous: Option<&PathBuf> = None;
+	let mut next: Option<&PathBuf> = None;
+	for other in archives.keys() {
+		let (other_directory, _, other_month) = split_period(other);
+		if other_directory != directory
+			|| other_month.is_empty() != month.is_empty()
+		{
+			continue;
+		}
+
+		if other.as_path() < period {
+			if previous.is_none_or(|p| p < other) {
+				previous = Some(other)
+			}
+		} else if other.as_path() > period && next.is_none_or(|n| n > other) {
+			next = Some(other)
+		}
+	}
+	(previous, next)
+}
@@ -0,0 +1,216 @@
+// Opt-in fingerprinting of CSS files and files under _static/. Each gets a copy
+// with a hash of its content in the file name, such as style.3f2a9c0b1d.css,
+// which browsers can cache indefinitely since a changed file gets a new name.
+// Templates reach the current name through asset_url, and other tools through
+// assets-manifest.json. The plain names are still written so that references
+// not going through asset_url, such as images in markdown, keep working.
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::css;
+use crate::liquid;
+use crate::markdown::GroupedOptionOutputFile;
+use crate::util::{
+	escape_json, strip_prefix, suggest_closest, translate_input_to_output,
+};
+
+pub const MANIFEST_FILE_NAME: &str = "assets-manifest.json";
+
+// The output path of a raw file, fingerprinted if given the bundles to take
+// the content of CSS files from.
+pub fn make_output_path(
+	input_file_path: &Path,
+	input_dir: &Path,
+	output_dir: &Path,
+	fingerprinting: Option<&css::Bundles>,
+) -> PathBuf {
+	let output_file_path =
+		translate_input_to_output(input_file_path, input_dir, output_dir);
+	let bundles = match fingerprinting {
+		Some(bundles) => bundles,
+		None => return output_file_path,
+	};
+
+	let data = read_output_content(input_file_path, bundles);
+	insert_fingerprint(&output_file_path, &fingerprint(&data))
+}
+
+// Bundled CSS files are output as bundles, others as they are.
+fn read_output_content(
+	input_file_path: &Path,
+	bundles: &css::Bundles,
+) -> Vec<u8> {
+	if let Some(bundle) = bundles.get(input_file_path) {
+		bundle.content.clone().into_bytes()
+	} else {
+		fs::read(input_file_path).unwrap_or_else(|e| {
+			panic!("Failed reading {}: {}", input_file_path.display(), e)
+		})
+	}
+}
+
+// First 10 hex digits of the 64-bit FNV-1a hash, plenty to tell versions of a
+// file apart.
+pub fn fingerprint(data: &[u8]) -> String {
+	let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+	for &byte in data {
+		hash ^= byte as u64;
+		hash = hash.wrapping_mul(0x0100_0000_01b3);
+	}
+	format!("{:016x}", hash)[..10].to_string()
+}
+
+// Inserts the fingerprint before the extension of the file name.
+fn insert_fingerprint(path: &Path, fingerprint: &str) -> PathBuf {
+	let stem = path.file_stem().unwrap_or_else(|| {
+		panic!("Missing file stem in path: {}", path.display())
+	});
+	let mut file_name = stem.to_os_string();
+	file_name.push(".");
+	file_name.push(fingerprint);
+	if let Some(extension) = path.extension() {
+		file_name.push(".");
+		file_name.push(extension);
+	}
+	path.with_file_name(file_name)
+}
+
+// Writes raw files to their fingerprinted output paths, if they have any.
+pub fn write_fingerprinted(
+	files: &[PathBuf],
+	input_dir: &Path,
+	output_dir: &Path,
+	input_output_map: &HashMap<PathBuf, GroupedOptionOutputFile>,
+	bundles: &css::Bundles,
+) {
+	for file_name in files {
+		let target = match input_output_map.get(file_name) {
+			Some(entry) => &entry.file.path,
+			None => continue,
+		};
+		if *target
+			== translate_input_to_output(file_name, input_dir, output_dir)
+		{
+			continue;
+		}
+		if let Some(parent) = target.parent() {
+			fs::create_dir_all(parent).unwrap_or_else(|e| {
+				panic!(
+					"Failed creating directories in {}: {}",
+					parent.display(),
+					e
+				)
+			});
+		}
+		fs::write(target, read_output_content(file_name, bundles))
+			.unwrap_or_else(|e| {
+				panic!("Failed writing {}: {}", target.display(), e)
+			});
+	}
+}
+
+// Maps the plain names of fingerprinted files to their fingerprinted names,
+// both relative to the output directory.
+pub fn write_manifest(
+	input_dir: &Path,
+	output_dir: &Path,
+	input_output_map: &HashMap<PathBuf, GroupedOptionOutputFile>,
+) {
+	let mut entries = input_output_map
+		.iter()
+		.filter(|(input, output)| {
+			output.file.front_matter.is_none()
+				&& input.is_file()
+				&& output.file.path
+					!= translate_input_to_output(input, input_dir, output_dir)
+		})
+		.map(|(input, output)| {
+			(
+				strip_prefix(
+					&translate_input_to_output(input, input_dir, output_dir),
+					output_dir,
+				),
+				strip_prefix(&output.file.path, output_dir),
+			)
+		})
+		.collect::<Vec<_>>();
+	entries.sort();
+
+	let mut json = String::from("{");
+	for (i, (plain, fingerprinted)) in entries.iter().enumerate() {
+		json.push_str(if i == 0 { "\n" } else { ",\n" });
+		json.push_str(&format!(
+			"\t\"{}\": \"{}\"",
+			escape_json(&plain.to_string_lossy()),
+			escape_json(&fingerprinted.to_string_lossy())
+		));
+	}
+	json.push_str("\n}\n");
+
+	let file_name = output_dir.join(MANIFEST_FILE_NAME);
+	fs::write(&file_name, json).unwrap_or_else(|e| {
+		panic!("Failed writing {}: {}", file_name.display(), e)
+	});
+	println!("Wrote {}.", file_name.display());
+}
+
+// Relative URL from the current page to the file with the given absolute output
+// path, such as /style.css or /static/logo.png, fingerprinted if enabled.
+pub fn make_url(name: &str, context: &liquid::Context) -> String {
+	let relative = name.strip_prefix('/').unwrap_or_else(|| {
+		panic!(
+			"Only absolute paths are allowed in asset_url, but got: {} (file: {})",
+			name,
+			context.input_file_path.display()
+		)
+	});
+	// Files under _static/ end up under static/.
+	let input_file_path = if relative.starts_with("static/") {
+		context.root_input_dir.join(format!("_{}", relative))
+	} else {
+		context.root_input_dir.join(relative)
+	};
+
+	let entry = match context.input_output_map.get(&input_file_path) {
+		Some(entry) if entry.file.front_matter.is_none() => entry,
+		_ => {
+			let suggestion = suggest_closest(
+				name,
+				context
+					.input_output_map
+					.iter()
+					.filter(|(_, entry)| entry.file.front_matter.is_none())
+					.map(|(input, _)| {
+						format!(
+							"/{}",
+							strip_prefix(
+								&translate_input_to_output(
+									input,
+									context.root_input_dir,
+									context.root_output_dir
+								),
+								context.root_output_dir
+							)
+							.to_string_lossy()
+						)
+					}),
+			)
+			.map(|s| format!(" Did you mean \"{}\"?", s))
+			.unwrap_or_default();
+			panic!(
+				"Failed finding asset {} from {}.{}",
+				name,
+				context.input_file_path.display(),
+				suggestion
+			)
+		}
+	};
+
+	liquid::make_relative_link(
+		context.output_file_path,
+		&entry.file.path,
+		context.root_output_dir,
+		context.site_info.base_url,
+	)
+}
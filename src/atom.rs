@@ -1,116 +1,16 @@
-// Atom was chosen over RSS as the former has a saner date format.
-use std::cmp::Ordering;
-use std::collections::HashMap;
+// Atom was chosen over RSS as the default as the former has a saner date
+// format.
 use std::fs;
 use std::io::BufWriter;
-use std::path::PathBuf;
-use std::sync::Arc;
 
-use crate::front_matter;
-use crate::util;
-use crate::util::{strip_prefix, write_to_stream};
+use crate::feed::{complete_url, FeedEntry, FeedHeader};
+use crate::util::{escape_html, write_to_stream};
 
-pub struct FeedHeader {
-	pub title: String,
-	pub base_url: String,
-	pub latest_update: Option<String>,
-	pub author_name: String,
-	pub author_email: String,
-}
-
-pub struct FeedEntry {
-	pub front_matter: Arc<front_matter::FrontMatter>,
-	pub html_content: String,
-	pub permalink: PathBuf,
-}
-
-pub fn generate(
-	groups: HashMap<String, Vec<FeedEntry>>,
-	output_dir: &PathBuf,
-	base_url: &str,
-	author: &str,
-	email: &str,
-	title: &str,
-) {
-	for (group, mut entries) in groups {
-		entries.sort_by(|lhs, rhs| {
-			let date_ordering =
-				rhs.front_matter.date.cmp(&lhs.front_matter.date);
-			match date_ordering {
-				Ordering::Less | Ordering::Greater => date_ordering,
-				Ordering::Equal => lhs.permalink.cmp(&rhs.permalink),
-			}
-		});
-
-		// Since entries has already been sorted by now, we just need to find
-		// the first date.
-		let mut latest_update: Option<&String> = None;
-		for entry in &entries {
-			if let Some(date) = &entry.front_matter.date {
-				if let Some(latest) = latest_update {
-					if latest < date {
-						latest_update = Some(date);
-					}
-				} else {
-					latest_update = Some(date)
-				}
-			}
-			if let Some(date) = &entry.front_matter.edited {
-				if let Some(latest) = latest_update {
-					if latest < date {
-						latest_update = Some(date);
-					}
-				} else {
-					latest_update = Some(date)
-				}
-			}
-		}
-
-		let feed_name = output_dir
-			.join(PathBuf::from("feeds").join(PathBuf::from(&group)))
-			.with_extension(util::XML_EXTENSION);
-
-		let header = FeedHeader {
-			title: format!("{} - {}", title, util::capitalize(&group)),
-			base_url: base_url.to_string(),
-			latest_update: latest_update.cloned(),
-			author_name: author.to_string(),
-			author_email: email.to_string(),
-		};
-
-		generate_feed(&feed_name, &header, &entries, output_dir);
-	}
-}
-
-fn generate_feed(
-	file_path: &PathBuf,
+pub fn write_feed(
 	header: &FeedHeader,
-	entries: &[FeedEntry],
-	output_dir: &PathBuf,
+	entries: &[&FeedEntry],
+	mut output: &mut BufWriter<fs::File>,
 ) {
-	let parent_dir = file_path.parent().unwrap_or_else(|| {
-		panic!(
-			"Feed file path without a parent directory?: {}",
-			file_path.display()
-		)
-	});
-	fs::create_dir_all(parent_dir).unwrap_or_else(|e| {
-		panic!(
-			"Failed creating directories for {}: {}",
-			parent_dir.display(),
-			e
-		)
-	});
-
-	let feed = fs::File::create(file_path).unwrap_or_else(|e| {
-		panic!("Failed creating {}: {}", file_path.display(), e)
-	});
-
-	let mut output = BufWriter::new(feed);
-	let feed_url = complete_url(
-		&header.base_url,
-		&strip_prefix(file_path, output_dir).to_string_lossy(),
-	);
 	write_to_stream(
 		format!(
 			"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
@@ -118,7 +18,7 @@ fn generate_feed(
 			\t<title>{}</title>\n\
 			\t<link rel=\"self\" href=\"{}\"/>\n\
 			\t<id>{}</id>\n",
-			header.title, feed_url, feed_url,
+			header.title, header.feed_url, header.feed_url,
 		)
 		.as_bytes(),
 		&mut output,
@@ -144,27 +44,10 @@ fn generate_feed(
 	);
 
 	for entry in entries {
-		generate_entry(entry, header, &mut output);
+		generate_entry(entry, header, output);
 	}
 
 	write_to_stream(b"</feed>", &mut output);
-
-	let feed = output.into_inner().unwrap_or_else(|e| {
-		panic!(
-			"Failed flushing buffered data to feed \"{}\": {}.",
-			&file_path.display(),
-			e
-		)
-	});
-
-	// Avoiding sync_all() for now to be friendlier to disks.
-	feed.sync_data().unwrap_or_else(|e| {
-		panic!(
-			"Failed sync_data() for \"{}\": {}.",
-			&file_path.display(),
-			e
-		)
-	});
 }
 
 fn generate_entry(
@@ -201,22 +84,45 @@ fn generate_entry(
 			&mut output,
 		);
 	}
+	if let Some(enclosure) = &entry.enclosure {
+		write_to_stream(
+			format!(
+				"\t\t<link rel=\"enclosure\" type=\"{}\" length=\"{}\" href=\"{}\"/>\n",
+				enclosure.mime_type,
+				enclosure.length,
+				escape_html(&complete_url(
+					&header.base_url,
+					&enclosure.path.to_string_lossy()
+				))
+			)
+			.as_bytes(),
+			&mut output,
+		);
+	}
 
-	write_to_stream(
-		format!(
-			"\t\t<content type=\"html\"><![CDATA[\
-			{}\
-			]]></content>\n\
-			\t</entry>\n",
-			entry.html_content
-		)
-		.as_bytes(),
-		&mut output,
-	);
-}
+	if let Some(summary) = &entry.summary {
+		write_to_stream(
+			format!(
+				"\t\t<summary type=\"html\"><![CDATA[\
+				{}\
+				]]></summary>\n",
+				summary
+			)
+			.as_bytes(),
+			&mut output,
+		);
+	} else {
+		write_to_stream(
+			format!(
+				"\t\t<content type=\"html\"><![CDATA[\
+				{}\
+				]]></content>\n",
+				entry.html_content
+			)
+			.as_bytes(),
+			&mut output,
+		);
+	}
 
-fn complete_url(base_url: &str, path: &str) -> String {
-	let mut url = base_url.to_string();
-	url.push_str(path);
-	url
+	write_to_stream(b"\t</entry>\n", &mut output);
 }
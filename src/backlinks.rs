@@ -0,0 +1,210 @@
+// Reverse link graph behind page.backlinks. Links are recorded where they are
+// resolved while rendering, by {% link %} tags, [[wiki links]] and markdown
+// links to .md files. Pages are rendered in parallel, so the ones showing their
+// backlinks are rendered again once all links are known.
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::config::{make_site_info, Config};
+use crate::liquid;
+use crate::markdown;
+use crate::markdown::{GroupedOptionOutputFile, InputFile};
+use crate::util::{
+	get_front_matter_and_output_path, BuildState, HTML_EXTENSION,
+	MARKDOWN_EXTENSION, NOT_FOUND_PAGE,
+};
+
+#[derive(Default)]
+pub struct Graph {
+	links: Mutex<Links>,
+}
+
+#[derive(Default)]
+struct Links {
+	// Output paths of linking pages along with input paths of linked ones.
+	edges: BTreeSet<(PathBuf, PathBuf)>,
+	// Input paths linked from layouts and pages generated without a source.
+	from_templates: HashSet<PathBuf>,
+	// Output paths of pages accessing page.backlinks.
+	showing: HashSet<PathBuf>,
+}
+
+impl Graph {
+	pub fn record(&self, target: &Path, context: &liquid::Context) {
+		let mut links = self.lock();
+		if context.html_content.is_some() {
+			links.from_templates.insert(target.to_path_buf());
+			return;
+		}
+		// Excerpts are rendered in the context of the listing page, but their
+		// links belong to the entry.
+		let source = context
+			.input_output_map
+			.get(context.input_file_path)
+			.map_or(context.output_file_path, |entry| &entry.file.path);
+		links
+			.edges
+			.insert((source.to_path_buf(), target.to_path_buf()));
+	}
+
+	pub fn mark_showing(&self, output_file_path: &Path) {
+		self.lock().showing.insert(output_file_path.to_path_buf());
+	}
+
+	// Called before rendering a page, which records its links again.
+	pub fn clear_from(&self, output_file_path: &Path) {
+		let mut links = self.lock();
+		links.edges.retain(|(source, _)| source != output_file_path);
+		links.showing.remove(output_file_path);
+	}
+
+	// Stores the recorded links in the backlinks of the entries, returning the
+	// pages which show backlinks that have changed.
+	pub fn apply(
+		&self,
+		input_output_map: &mut HashMap<PathBuf, GroupedOptionOutputFile>,
+	) -> Vec<PathBuf> {
+		let mut links = self.lock();
+		let inputs = input_output_map
+			.iter()
+			.map(|(input, entry)| (entry.file.path.clone(), input.clone()))
+			.collect::<HashMap<_, _>>();
+
+		let mut backlinks = HashMap::<PathBuf, Vec<PathBuf>>::new();
+		let mut from_templates = Vec::new();
+		for (source, target) in &links.edges {
+			match inputs.get(source) {
+				// Tag and archive pages only list entries, and are generated
+				// without an input file.
+				Some(input) if input == target || !input.is_file() => {}
+				Some(input) => backlinks
+					.entry(target.clone())
+					.or_default()
+					.push(input.clone()),
+				None => from_templates.push(target.clone()),
+			}
+		}
+		links.from_templates.extend(from_templates);
+
+		let mut changed = Vec::new();
+		for (input, entry) in input_output_map.iter_mut() {
+			let mut sources = backlinks.remove(input).unwrap_or_default();
+			sources.sort();
+			sources.dedup();
+			if entry.backlinks != sources {
+				if links.showing.contains(&entry.file.path) {
+					changed.push(input.clone());
+				}
+				entry.backlinks = sources;
+			}
+		}
+		changed.sort();
+		changed
+	}
+
+	// Pages without any inbound links, not counting the front page and
+	// entries of groups, which are reachable through their listings. Links in
+	// layouts count as inbound links.
+	pub fn find_orphans(
+		&self,
+		input_output_map: &HashMap<PathBuf, GroupedOptionOutputFile>,
+		root_input_dir: &Path,
+	) -> Vec<PathBuf> {
+		let links = self.lock();
+		let mut orphans = input_output_map
+			.iter()
+			.filter(|(path, entry)| {
+				is_page(path)
+					&& entry.backlinks.is_empty()
+					&& entry.group.is_none()
+					&& !is_front_page(path, root_input_dir)
+					&& !is_not_found_page(path, root_input_dir)
+					&& !links.from_templates.contains(*path)
+			})
+			.map(|(path, _)| path.clone())
+			.collect::<Vec<_>>();
+		orphans.sort();
+		orphans
+	}
+
+	fn lock(&self) -> MutexGuard<'_, Links> {
+		self.links
+			.lock()
+			.unwrap_or_else(|e| panic!("Failed locking links: {}", e))
+	}
+}
+
+// Renders pages again to update the backlinks they show. Called before
+// anything reading the generated pages, such as precompression.
+pub fn rerender(
+	pages: &[PathBuf],
+	input_output_map: &HashMap<PathBuf, GroupedOptionOutputFile>,
+	groups: &HashMap<String, Vec<InputFile>>,
+	config: &Config,
+	state: &BuildState,
+) {
+	crossbeam_utils::thread::scope(|s| {
+		for page in pages {
+			let handle = s.spawn(move |_| {
+				if let Some((front_matter, output_file_path)) =
+					get_front_matter_and_output_path(
+						page,
+						input_output_map,
+						config.deploy,
+					) {
+					let site_info = make_site_info(config, state);
+					if page.extension() == Some(OsStr::new(MARKDOWN_EXTENSION))
+					{
+						markdown::process_file(
+							page,
+							output_file_path,
+							front_matter,
+							&config.input_dir,
+							&config.output_dir,
+							input_output_map,
+							groups,
+							&site_info,
+						);
+					} else {
+						markdown::process_template_file(
+							page,
+							output_file_path,
+							front_matter,
+							&config.input_dir,
+							&config.output_dir,
+							input_output_map,
+							groups,
+							&site_info,
+						);
+					}
+				}
+			});
+			if config.serial {
+				handle.join().unwrap_or_else(|e| {
+					panic!("Failed joining on thread: {:?}", e)
+				});
+			}
+		}
+	})
+	.unwrap_or_else(|e| panic!("Crossbeam scope failed: {:?}", e));
+}
+
+fn is_page(path: &Path) -> bool {
+	let extension = path.extension();
+	(extension == Some(OsStr::new(MARKDOWN_EXTENSION))
+		|| extension == Some(OsStr::new(HTML_EXTENSION)))
+		&& path.is_file()
+}
+
+fn is_front_page(path: &Path, root_input_dir: &Path) -> bool {
+	path.parent() == Some(root_input_dir)
+		&& path.file_stem() == Some(OsStr::new("index"))
+}
+
+fn is_not_found_page(path: &Path, root_input_dir: &Path) -> bool {
+	path.parent() == Some(root_input_dir)
+		&& path.with_extension(HTML_EXTENSION).file_name()
+			== Some(OsStr::new(NOT_FOUND_PAGE))
+}
@@ -0,0 +1,472 @@
+// Verifies the links, assets and #fragment anchors referenced by the generated
+// HTML files in the output directory. Internal URLs are resolved against the
+// output tree, also when written as absolute URLs under base_url. External
+// URLs are only listed, never fetched.
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::markdown::GroupedOptionOutputFile;
+use crate::util::{strip_prefix, HTML_EXTENSION};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProblemKind {
+	BrokenLink,
+	MissingAsset,
+	MissingAnchor,
+}
+
+pub struct Problem {
+	pub kind: ProblemKind,
+	pub page: PathBuf,
+	// The input file the page was generated from, when known.
+	pub source: Option<PathBuf>,
+	pub url: String,
+}
+
+impl fmt::Display for Problem {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let kind = match self.kind {
+			ProblemKind::BrokenLink => "Broken link",
+			ProblemKind::MissingAsset => "Missing asset",
+			ProblemKind::MissingAnchor => "Missing anchor",
+		};
+		write!(f, "{} in {}", kind, self.page.display())?;
+		if let Some(source) = &self.source {
+			write!(f, " (from {})", source.display())?;
+		}
+		write!(f, ": {}", self.url)
+	}
+}
+
+pub struct Report {
+	pub checked_pages: usize,
+	pub problems: Vec<Problem>,
+	pub external: BTreeSet<String>,
+}
+
+impl Report {
+	pub fn print(&self) {
+		if !self.external.is_empty() {
+			println!("External URLs (not checked):");
+			for url in &self.external {
+				println!("  {}", url);
+			}
+		}
+		for problem in &self.problems {
+			println!("{}", problem);
+		}
+		println!(
+			"Checked {} pages, found {} problem(s).",
+			self.checked_pages,
+			self.problems.len()
+		);
+	}
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ReferenceKind {
+	Link,
+	Asset,
+}
+
+struct Reference {
+	kind: ReferenceKind,
+	url: String,
+}
+
+struct Page {
+	ids: HashSet<String>,
+	references: Vec<Reference>,
+}
+
+pub fn run(
+	output_dir: &Path,
+	base_url: &str,
+	input_output_map: &HashMap<PathBuf, GroupedOptionOutputFile>,
+) -> Report {
+	let sources: HashMap<&PathBuf, &PathBuf> = input_output_map
+		.iter()
+		.map(|(input, output)| (&output.file.path, input))
+		.collect();
+
+	let mut page_paths = Vec::new();
+	find_html_files(output_dir, &mut page_paths);
+	page_paths.sort();
+	let pages: HashMap<PathBuf, Page> = page_paths
+		.iter()
+		.map(|path| (path.clone(), parse_page(path)))
+		.collect();
+
+	let (base_host, base_path) = split_base_url(base_url);
+	let mut report = Report {
+		checked_pages: pages.len(),
+		problems: Vec::new(),
+		external: BTreeSet::new(),
+	};
+	for page_path in &page_paths {
+		let page = &pages[page_path];
+		for reference in &page.references {
+			let target = match classify(&reference.url, &base_host, &base_path)
+			{
+				Target::Ignored => continue,
+				Target::External => {
+					report.external.insert(reference.url.clone());
+					continue;
+				}
+				Target::Internal(target) => target,
+			};
+
+			let (path, fragment) = match target.find('#') {
+				Some(hash) => (&target[..hash], Some(&target[hash + 1..])),
+				None => (target.as_str(), None),
+			};
+			let path = match path.find('?') {
+				Some(question) => &path[..question],
+				None => path,
+			};
+
+			let problem = if path.is_empty() {
+				check_fragment(page_path, fragment, &pages)
+			} else {
+				match resolve(output_dir, page_path, path) {
+					Some(resolved) => {
+						check_fragment(&resolved, fragment, &pages)
+					}
+					None => Some(match reference.kind {
+						ReferenceKind::Link => ProblemKind::BrokenLink,
+						ReferenceKind::Asset => ProblemKind::MissingAsset,
+					}),
+				}
+			};
+
+			if let Some(kind) = problem {
+				report.problems.push(Problem {
+					kind,
+					page: page_path.clone(),
+					source: sources.get(page_path).map(|s| (*s).clone()),
+					url: reference.url.clone(),
+				})
+			}
+		}
+	}
+	report
+}
+
+fn find_html_files(dir: &Path, result: &mut Vec<PathBuf>) {
+	let entries = fs::read_dir(dir).unwrap_or_else(|e| {
+		panic!("Failed reading paths from \"{}\": {}.", dir.display(), e)
+	});
+	for entry in entries {
+		let path = entry
+			.unwrap_or_else(|e| {
+				panic!("Invalid entry in \"{}\": {}", dir.display(), e)
+			})
+			.path();
+		if path.is_dir() {
+			find_html_files(&path, result)
+		} else if path.extension().and_then(|e| e.to_str())
+			== Some(HTML_EXTENSION)
+		{
+			result.push(path)
+		}
+	}
+}
+
+enum Target {
+	Ignored,
+	External,
+	// Relative to the current page, or to the output root if starting with /.
+	Internal(String),
+}
+
+// Splits http://host:port/path/ into the host part and the path.
+pub fn split_base_url(base_url: &str) -> (String, String) {
+	let without_scheme = base_url.split("://").nth(1).unwrap_or(base_url);
+	match without_scheme.find('/') {
+		Some(slash) => (
+			without_scheme[..slash].to_string(),
+			without_scheme[slash..].to_string(),
+		),
+		None => (without_scheme.to_string(), "/".to_string()),
+	}
+}
+
+fn classify(url: &str, base_host: &str, base_path: &str) -> Target {
+	let url = url.trim();
+	if url.is_empty() {
+		return Target::Ignored;
+	}
+
+	let without_scheme = if let Some(rest) = url.strip_prefix("//") {
+		rest
+	} else if let Some(rest) = url
+		.strip_prefix("http://")
+		.or_else(|| url.strip_prefix("https://"))
+	{
+		rest
+	} else if url.starts_with('#') || url.starts_with('/') {
+		return Target::Internal(strip_base_path(url, base_path));
+	} else if has_scheme(url) {
+		// mailto:, tel:, data: and the like.
+		return Target::Ignored;
+	} else {
+		return Target::Internal(url.to_string());
+	};
+
+	let host_end = without_scheme
+		.find(['/', '?', '#'])
+		.unwrap_or(without_scheme.len());
+	if &without_scheme[..host_end] == base_host {
+		let path = &without_scheme[host_end..];
+		Target::Internal(strip_base_path(
+			if path.is_empty() { "/" } else { path },
+			base_path,
+		))
+	} else {
+		Target::External
+	}
+}
+
+fn has_scheme(url: &str) -> bool {
+	match (url.find(':'), url.find(['/', '?', '#'])) {
+		(Some(colon), Some(delimiter)) => colon < delimiter,
+		(Some(_), None) => true,
+		_ => false,
+	}
+}
+
+// Root-relative URLs include the path of base_url when the site lives in a
+// sub-directory.
+fn strip_base_path(url: &str, base_path: &str) -> String {
+	if url.starts_with('#') {
+		return url.to_string();
+	}
+	match url.strip_prefix(base_path.trim_end_matches('/')) {
+		Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+			format!("/{}", rest.trim_start_matches('/'))
+		}
+		_ => url.to_string(),
+	}
+}
+
+// Returns the file a URL path refers to, if it exists.
+fn resolve(output_dir: &Path, page_path: &Path, path: &str) -> Option<PathBuf> {
+	let path = percent_decode(path);
+	let joined = if let Some(root_relative) = path.strip_prefix('/') {
+		PathBuf::from(root_relative)
+	} else {
+		strip_prefix(page_path.parent()?, output_dir).join(&path)
+	};
+
+	let mut normalized = PathBuf::new();
+	for component in joined.components() {
+		match component {
+			Component::ParentDir => normalized.pop().then_some(())?,
+			Component::Normal(name) => normalized.push(name),
+			_ => {}
+		}
+	}
+
+	let resolved = output_dir.join(normalized);
+	if resolved.is_dir() {
+		let index = resolved.join("index.html");
+		if index.is_file() {
+			Some(index)
+		} else {
+			None
+		}
+	} else if resolved.is_file() {
+		Some(resolved)
+	} else {
+		None
+	}
+}
+
+fn check_fragment(
+	target: &Path,
+	fragment: Option<&str>,
+	pages: &HashMap<PathBuf, Page>,
+) -> Option<ProblemKind> {
+	let fragment = match fragment {
+		Some(fragment) if !fragment.is_empty() && fragment != "top" => {
+			percent_decode(fragment)
+		}
+		_ => return None,
+	};
+	match pages.get(target) {
+		Some(page) if !page.ids.contains(&fragment) => {
+			Some(ProblemKind::MissingAnchor)
+		}
+		_ => None,
+	}
+}
+
+fn percent_decode(text: &str) -> String {
+	let bytes = text.as_bytes();
+	let mut decoded = Vec::with_capacity(bytes.len());
+	let mut i = 0;
+	while i < bytes.len() {
+		let hex = bytes
+			.get(i + 1..i + 3)
+			.and_then(|hex| std::str::from_utf8(hex).ok())
+			.and_then(|hex| u8::from_str_radix(hex, 16).ok());
+		if let (b'%', Some(byte)) = (bytes[i], hex) {
+			decoded.push(byte);
+			i += 3;
+			continue;
+		}
+		decoded.push(bytes[i]);
+		i += 1;
+	}
+	String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn parse_page(path: &Path) -> Page {
+	let content = fs::read_to_string(path)
+		.unwrap_or_else(|e| panic!("Failed reading {}: {}", path.display(), e));
+	let mut page = Page {
+		ids: HashSet::new(),
+		references: Vec::new(),
+	};
+	for tag in scan_tags(&content) {
+		for (name, value) in &tag.attributes {
+			match (tag.name.as_str(), name.as_str()) {
+				(_, "id") | ("a", "name") => {
+					page.ids.insert(value.clone());
+				}
+				("a", "href") | ("area", "href") => {
+					page.references.push(Reference {
+						kind: ReferenceKind::Link,
+						url: value.clone(),
+					})
+				}
+				("link", "href")
+				| (_, "src")
+				| ("video", "poster")
+				| ("object", "data") => page.references.push(Reference {
+					kind: ReferenceKind::Asset,
+					url: value.clone(),
+				}),
+				(_, "srcset") => {
+					for candidate in value.split(',') {
+						if let Some(url) = candidate.split_whitespace().next() {
+							page.references.push(Reference {
+								kind: ReferenceKind::Asset,
+								url: url.to_string(),
+							})
+						}
+					}
+				}
+				_ => {}
+			}
+		}
+	}
+	page
+}
+
+pub struct Tag {
+	pub name: String,
+	pub attributes: Vec<(String, String)>,
+}
+
+// A lenient scanner for start tags, skipping comments as well as the contents
+// of script and style elements.
+pub fn scan_tags(content: &str) -> Vec<Tag> {
+	let mut tags = Vec::new();
+	let mut rest = content;
+	while let Some(start) = rest.find('<') {
+		rest = &rest[start + 1..];
+		if let Some(comment) = rest.strip_prefix("!--") {
+			rest = match comment.find("-->") {
+				Some(end) => &comment[end + 3..],
+				None => "",
+			};
+			continue;
+		}
+
+		let name_end = rest
+			.find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+			.unwrap_or(rest.len());
+		let name = rest[..name_end].to_ascii_lowercase();
+		if name.is_empty()
+			|| !name.starts_with(|c: char| c.is_ascii_alphabetic())
+		{
+			continue;
+		}
+		rest = &rest[name_end..];
+
+		let mut attributes = Vec::new();
+		loop {
+			rest = rest
+				.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+			if rest.is_empty() {
+				break;
+			}
+			if let Some(after) = rest.strip_prefix('>') {
+				rest = after;
+				break;
+			}
+			let attribute_end = rest
+				.find(|c: char| {
+					c.is_whitespace() || c == '=' || c == '>' || c == '/'
+				})
+				.unwrap_or(rest.len());
+			let attribute = rest[..attribute_end].to_ascii_lowercase();
+			rest = rest[attribute_end..].trim_start();
+			let value = if let Some(after_equals) = rest.strip_prefix('=') {
+				let after_equals = after_equals.trim_start();
+				let (value, remaining) = match after_equals.chars().next() {
+					Some(quote) if quote == '"' || quote == '\'' => {
+						let inner = &after_equals[1..];
+						match inner.find(quote) {
+							Some(end) => (&inner[..end], &inner[end + 1..]),
+							None => (inner, ""),
+						}
+					}
+					_ => {
+						let end = after_equals
+							.find(|c: char| c.is_whitespace() || c == '>')
+							.unwrap_or(after_equals.len());
+						(&after_equals[..end], &after_equals[end..])
+					}
+				};
+				rest = remaining;
+				decode_entities(value)
+			} else {
+				String::new()
+			};
+			if attribute.is_empty() {
+				// Stray character, skip it to guarantee progress.
+				let mut chars = rest.chars();
+				chars.next();
+				rest = chars.as_str();
+				continue;
+			}
+			attributes.push((attribute, value));
+		}
+
+		if name == "script" || name == "style" {
+			let end_tag = format!("</{}", name);
+			rest = match rest.to_ascii_lowercase().find(&end_tag) {
+				Some(end) => &rest[end..],
+				None => "",
+			};
+		}
+		tags.push(Tag { name, attributes });
+	}
+	tags
+}
+
+pub fn decode_entities(value: &str) -> String {
+	if !value.contains('&') {
+		return value.to_string();
+	}
+	value
+		.replace("&quot;", "\"")
+		.replace("&#39;", "'")
+		.replace("&lt;", "<")
+		.replace("&gt;", ">")
+		.replace("&amp;", "&")
+}
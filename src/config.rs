@@ -1,10 +1,15 @@
+use std::collections::{BTreeMap, HashSet};
 use std::convert::TryInto;
 use std::path::PathBuf;
 use std::{env, fmt, fs};
 
 use yaml_rust::YamlLoader;
 
-use crate::util::SiteInfo;
+use crate::feed::FeedFormat;
+use crate::markdown::Extensions;
+use crate::robots;
+use crate::search;
+use crate::util::{BuildState, SiteInfo};
 
 pub struct BoolArg {
 	pub name: &'static str,
@@ -27,6 +32,15 @@ pub struct StringArg {
 	pub set: bool,
 }
 
+// Only settable through the configuration file, as a Hash of strings which is
+// merged over the defaults.
+pub struct MapArg {
+	pub name: &'static str,
+	pub help: &'static str,
+	pub value: BTreeMap<String, String>,
+	pub set: bool,
+}
+
 impl fmt::Display for BoolArg {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 		write!(f, "--{} {}", self.name, self.help)
@@ -45,37 +59,105 @@ impl fmt::Display for StringArg {
 	}
 }
 
+impl fmt::Display for MapArg {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}: (_config.yml only) {}", self.name, self.help)
+	}
+}
+
 // Not using the otherwise brilliant CLAP crate since I detest string matching
 // arg names by string across the code to get their values.
 // Could use the structopt crate, but it unfortunately pulls in CLAP and 2 other
 // dependencies (+2 dev-dependencies).
 pub struct Args {
+	pub admonitions: MapArg,
 	pub author: StringArg,
 	pub base_url: StringArg,
+	pub bundle_css: BoolArg,
+	pub check: BoolArg,
 	pub deploy: BoolArg,
 	pub email: StringArg,
+	pub excerpt_separator: StringArg,
+	pub feed_limit: I16Arg,
+	pub feed_summary: BoolArg,
+	pub feeds: MapArg,
+	pub fingerprint_assets: BoolArg,
+	pub footnotes: BoolArg,
+	pub heading_anchors: BoolArg,
 	pub help: BoolArg, // Command line-only, doesn't transfer into Config.
 	pub host: StringArg,
+	pub image_sizes: StringArg,
+	pub image_widths: StringArg,
 	pub input: StringArg,
+	pub math: BoolArg,
+	pub minify: BoolArg,
 	pub output: StringArg,
+	pub podcast: MapArg,
 	pub port: I16Arg,
+	pub precompress: BoolArg,
+	pub related_limit: I16Arg,
+	pub robots: MapArg,
+	pub search: MapArg,
+	pub search_body_words: I16Arg,
+	pub search_index: StringArg,
+	pub search_stop_words: StringArg,
+	pub seo_email: BoolArg,
 	pub serial: BoolArg,
 	pub single_file: StringArg,
+	pub smart_punctuation: BoolArg,
+	pub staging: BoolArg,
+	pub strikethrough: BoolArg,
+	pub tasklists: BoolArg,
 	pub title: StringArg,
 	pub watch: BoolArg,
 }
 
 pub struct Config {
+	pub admonitions: BTreeMap<String, String>,
 	pub author: String,
 	pub base_url: String,
+	pub bundle_css: bool,
+	pub check: bool,
 	pub deploy: bool,
 	pub email: String,
+	pub excerpt_separator: String,
+	// Maximum number of entries per feed, 0 for no limit.
+	pub feed_limit: usize,
+	pub feed_summary: bool,
+	pub feeds: BTreeMap<String, Vec<FeedFormat>>,
+	pub fingerprint_assets: bool,
+	pub footnotes: bool,
+	pub heading_anchors: bool,
 	pub host: String,
+	pub image_sizes: String,
+	pub image_widths: Vec<u32>,
 	pub input_dir: PathBuf,
+	pub math: bool,
+	pub minify: bool,
 	pub output_dir: PathBuf,
+	// Channel metadata of podcast feeds.
+	pub podcast: BTreeMap<String, String>,
 	pub port: i16,
+	pub precompress: bool,
+	// Maximum number of entries in page.related, 0 for no limit.
+	pub related_limit: usize,
+	// Rules of robots.txt by user agent.
+	pub robots: BTreeMap<String, Vec<robots::Rule>>,
+	// Whether to include the pages of each group in the search index.
+	pub search: BTreeMap<String, bool>,
+	// Maximum number of words of each page in the search index, 0 for no
+	// limit.
+	pub search_body_words: usize,
+	// Relative to the output directory, no index is written if None.
+	pub search_index: Option<PathBuf>,
+	pub search_stop_words: HashSet<String>,
+	pub seo_email: bool,
 	pub serial: bool,
 	pub single_file: Option<PathBuf>,
+	pub smart_punctuation: bool,
+	pub staging: bool,
+	pub strikethrough: bool,
+	pub tasklists: bool,
 	pub title: String,
 	pub watch: bool,
 }
@@ -83,6 +165,12 @@ pub struct Config {
 impl Args {
 	pub fn new() -> Self {
 		Self {
+			admonitions: MapArg {
+				name: "admonitions",
+				help: "Maps admonition kinds such as note or warning, written as > [!NOTE] blockquotes or :::note Title ... ::: containers, to the CSS classes of the generated asides (default recognizes note, tip, important, warning and caution).",
+				value: default_admonitions(),
+				set: false,
+			},
 			author: StringArg {
 				name: "author",
 				help: "Set the name of the author.",
@@ -95,6 +183,18 @@ impl Args {
 				value: String::from("http://127.0.0.1:8090/"),
 				set: false,
 			},
+			bundle_css: BoolArg {
+				name: "bundle_css",
+				help: "Bundle CSS files, inlining local @import rules into the importing file, stripping comments and whitespace and rewriting relative url() references. Files starting with _ or under _-prefixed directories are then only output through the files importing them, and changing them in watch mode rebuilds the bundles importing them.",
+				value: false,
+				set: false,
+			},
+			check: BoolArg {
+				name: "check",
+				help: "After generating, verify links, assets and #anchors in the generated HTML files and fail if any are broken. External URLs are listed but not fetched.",
+				value: false,
+				set: false,
+			},
 			deploy: BoolArg {
 				name: "deploy",
 				help: "Deploy site excluding unpublished pages.",
@@ -107,6 +207,48 @@ impl Args {
 				value: String::from("john.doe@test.com"),
 				set: false,
 			},
+			excerpt_separator: StringArg {
+				name: "excerpt_separator",
+				help: "Set marker ending the excerpt of markdown files, default is \"<!--more-->\". Files without it use their first paragraph.",
+				value: String::from("<!--more-->"),
+				set: false,
+			},
+			feed_limit: I16Arg {
+				name: "feed_limit",
+				help: "Only include the given number of newest entries in each feed (default 0, meaning all entries).",
+				value: 0,
+				set: false,
+			},
+			feed_summary: BoolArg {
+				name: "feed_summary",
+				help: "Use excerpts as summaries in feeds instead of including full content.",
+				value: false,
+				set: false,
+			},
+			feeds: MapArg {
+				name: "feeds",
+				help: "Maps groups to comma-separated formats of their feeds: atom, rss, json and podcast (groups not listed only get atom). The \"tags\" key adds per-tag feeds under feeds/tags/ and \"all\" a feed combining all groups.",
+				value: BTreeMap::new(),
+				set: false,
+			},
+			fingerprint_assets: BoolArg {
+				name: "fingerprint_assets",
+				help: "Also write CSS files and files under _static/ with a hash of their content in the file name, for use through {% asset_url \"/style.css\" %} or the asset_url filter, and list them in assets-manifest.json. Plain names are still written.",
+				value: false,
+				set: false,
+			},
+			footnotes: BoolArg {
+				name: "footnotes",
+				help: "Enable footnotes in markdown files, rendered as a footnote section at the end of the page (off by default, can also be set in front matter).",
+				value: false,
+				set: false,
+			},
+			heading_anchors: BoolArg {
+				name: "heading_anchors",
+				help: "Insert a link to itself in each heading of markdown files.",
+				value: false,
+				set: false,
+			},
 			help: BoolArg {
 				name: "help",
 				help: "Print this text.",
@@ -119,24 +261,102 @@ impl Args {
 				value: String::from("127.0.0.1"),
 				set: false,
 			},
+			image_sizes: StringArg {
+				name: "image_sizes",
+				help: "Set the sizes attribute of images offering resized variants, such as \"(max-width: 40em) 100vw, 40em\" for the width they are shown at. Browsers assume the full viewport width without it.",
+				value: String::from(""),
+				set: false,
+			},
+			image_widths: StringArg {
+				name: "image_widths",
+				help: "Set comma separated widths such as \"480,960\" of resized variants to generate of wider PNG and JPEG images under _static/, offered to browsers through srcset.",
+				value: String::from(""),
+				set: false,
+			},
 			input: StringArg {
 				name: "input",
 				help: "Set input directory to process.",
 				value: String::from("./input"),
 				set: false,
 			},
+			math: BoolArg {
+				name: "math",
+				help: "Convert $inline$ and $$display$$ TeX math in markdown files to MathML, failing the build on TeX outside the supported subset (off by default, can also be set in front matter).",
+				value: false,
+				set: false,
+			},
+			minify: BoolArg {
+				name: "minify",
+				help: "Minify generated HTML files, collapsing whitespace outside <pre>, <textarea> and <script>, dropping comments and unquoting attribute values where allowed (on by default in deploy mode, can be turned off in _config.yml).",
+				value: false,
+				set: false,
+			},
 			output: StringArg {
 				name: "output",
 				help: "Set output directory to write to.",
 				value: String::from("./output"),
 				set: false,
 			},
+			podcast: MapArg {
+				name: "podcast",
+				help: "Channel metadata of podcast feeds: category, description, explicit (\"true\" or \"false\"), image (relative to base_url if starting with /) and language.",
+				value: BTreeMap::new(),
+				set: false,
+			},
 			port: I16Arg {
 				name: "port",
 				help: "Set port to bind to for built-in HTTP server (default 8090).",
 				value: 8090,
 				set: false,
 			},
+			precompress: BoolArg {
+				name: "precompress",
+				help: "Write gzip and brotli compressed variants of HTML, CSS, JS, TXT and XML outputs when smaller, refreshed as files change in watch mode (on by default in deploy mode, can be turned off in _config.yml).",
+				value: false,
+				set: false,
+			},
+			related_limit: I16Arg {
+				name: "related_limit",
+				help: "Only list the given number of entries sharing the most tags with a page in page.related (default 5, 0 meaning all entries).",
+				value: 5,
+				set: false,
+			},
+			robots: MapArg {
+				name: "robots",
+				help: "Maps user agents such as \"*\" to comma-separated robots.txt rules like \"allow /, disallow /drafts/\" (default allows all agents everywhere).",
+				value: BTreeMap::new(),
+				set: false,
+			},
+			search: MapArg {
+				name: "search",
+				help: "Maps groups to \"true\" or \"false\" to include their pages in the search index or leave them out, with \"*\" applying to pages outside groups and groups not listed (default includes all pages).",
+				value: BTreeMap::new(),
+				set: false,
+			},
+			search_body_words: I16Arg {
+				name: "search_body_words",
+				help: "Only include the given number of words of each page in the search index (default 200, 0 meaning the whole page).",
+				value: 200,
+				set: false,
+			},
+			search_index: StringArg {
+				name: "search_index",
+				help: "Write a JSON index of the pages for client-side search to the given path in the output directory, such as \"search.json\" (default none).",
+				value: String::new(),
+				set: false,
+			},
+			search_stop_words: StringArg {
+				name: "search_stop_words",
+				help: "Set comma-separated words left out of the search index, default is common English words such as \"the, and, of\".",
+				value: String::from(DEFAULT_STOP_WORDS),
+				set: false,
+			},
+			seo_email: BoolArg {
+				name: "seo_email",
+				help: "Include the email of the author in the JSON-LD metadata written by {% seo %} (default off, keeping it away from scrapers).",
+				value: false,
+				set: false,
+			},
 			serial: BoolArg {
 				name: "serial",
 				help: "Run initial file processing in serial mode instead of concurrently.",
@@ -149,6 +369,30 @@ impl Args {
 				value: String::from(""),
 				set: false,
 			},
+			smart_punctuation: BoolArg {
+				name: "smart_punctuation",
+				help: "Enable typographic quotes, dashes and ellipses in markdown files (off by default, can also be set in front matter).",
+				value: false,
+				set: false,
+			},
+			staging: BoolArg {
+				name: "staging",
+				help: "Disallow all crawlers from the whole site in robots.txt, for staging deployments.",
+				value: false,
+				set: false,
+			},
+			strikethrough: BoolArg {
+				name: "strikethrough",
+				help: "Enable ~~strikethrough~~ in markdown files (off by default, can also be set in front matter).",
+				value: false,
+				set: false,
+			},
+			tasklists: BoolArg {
+				name: "tasklists",
+				help: "Enable - [ ] task lists in markdown files (off by default, can also be set in front matter).",
+				value: false,
+				set: false,
+			},
 			title: StringArg {
 				name: "title",
 				help: "Title of the site.",
@@ -167,36 +411,73 @@ impl Args {
 	pub fn parse(&mut self, args: env::Args) {
 		{
 			let bool_args = &mut [
+				&mut self.bundle_css,
+				&mut self.check,
 				&mut self.deploy,
+				&mut self.feed_summary,
+				&mut self.fingerprint_assets,
+				&mut self.footnotes,
+				&mut self.heading_anchors,
 				&mut self.help,
+				&mut self.math,
+				&mut self.minify,
+				&mut self.precompress,
+				&mut self.seo_email,
 				&mut self.serial,
+				&mut self.smart_punctuation,
+				&mut self.staging,
+				&mut self.strikethrough,
+				&mut self.tasklists,
 				&mut self.watch,
 			];
-			let i16_args = &mut [&mut self.port];
+			let i16_args = &mut [
+				&mut self.feed_limit,
+				&mut self.port,
+				&mut self.related_limit,
+				&mut self.search_body_words,
+			];
 			let string_args = &mut [
 				&mut self.author,
 				&mut self.base_url,
 				&mut self.email,
+				&mut self.excerpt_separator,
 				&mut self.host,
+				&mut self.image_sizes,
+				&mut self.image_widths,
 				&mut self.input,
 				&mut self.output,
+				&mut self.search_index,
+				&mut self.search_stop_words,
 				&mut self.single_file,
 				&mut self.title,
 			];
+			let map_args = &mut [
+				&mut self.admonitions,
+				&mut self.feeds,
+				&mut self.podcast,
+				&mut self.robots,
+				&mut self.search,
+			];
 
 			Self::parse_cli(args, bool_args, i16_args, string_args);
 
-			let help_index = 1;
+			let help_index = 7;
 			assert_eq!(bool_args[help_index].name, "help");
 			if bool_args[help_index].value {
 				return;
 			}
 
-			let input_index = 4;
+			let input_index = 7;
 			assert_eq!(string_args[input_index].name, "input");
 			let input_dir = PathBuf::from(&string_args[input_index].value);
 
-			Self::parse_file(&input_dir, bool_args, i16_args, string_args);
+			Self::parse_file(
+				&input_dir,
+				bool_args,
+				i16_args,
+				string_args,
+				map_args,
+			);
 		}
 
 		if !self.watch.value
@@ -297,6 +578,7 @@ impl Args {
 		bool_args: &mut [&mut BoolArg],
 		i16_args: &mut [&mut I16Arg],
 		string_args: &mut [&mut StringArg],
+		map_args: &mut [&mut MapArg],
 	) {
 		let file_path = input_dir.join("_config.yml");
 		if !file_path.exists() {
@@ -342,6 +624,7 @@ impl Args {
 						bool_args,
 						i16_args,
 						string_args,
+						map_args,
 					)
 				} else {
 					panic!("Expected string keys in YAML element in front matter of \"{}\" but got {:?}.", 
@@ -361,6 +644,7 @@ impl Args {
 		bool_args: &mut [&mut BoolArg],
 		i16_args: &mut [&mut I16Arg],
 		string_args: &mut [&mut StringArg],
+		map_args: &mut [&mut MapArg],
 	) {
 		for arg in &mut *bool_args {
 			if arg.name != key {
@@ -431,6 +715,42 @@ impl Args {
 			}
 		}
 
+		for arg in &mut *map_args {
+			if arg.name != key {
+				continue;
+			}
+
+			if let yaml_rust::Yaml::Hash(hash) = value {
+				for (map_key, map_value) in hash {
+					match (map_key, map_value) {
+						(
+							yaml_rust::Yaml::String(map_key),
+							yaml_rust::Yaml::String(map_value),
+						) => {
+							arg.value
+								.insert(map_key.clone(), map_value.clone());
+						}
+						_ => panic!(
+							"Expected string keys and values in {} in {} but got {:?}: {:?}",
+							key,
+							file_path.display(),
+							map_key,
+							map_value
+						),
+					}
+				}
+				arg.set = true;
+				return;
+			} else {
+				panic!(
+					"{} in {} has unexpected type {:?}",
+					key,
+					file_path.display(),
+					value
+				)
+			}
+		}
+
 		panic!(
 			"Unknown field {} in config file {}.",
 			key,
@@ -439,17 +759,44 @@ impl Args {
 	}
 
 	pub fn print_help(&self) {
+		println!("{}", self.admonitions);
 		println!("{}", self.author);
 		println!("{}", self.base_url);
+		println!("{}", self.bundle_css);
+		println!("{}", self.check);
 		println!("{}", self.deploy);
 		println!("{}", self.email);
+		println!("{}", self.excerpt_separator);
+		println!("{}", self.feed_limit);
+		println!("{}", self.feed_summary);
+		println!("{}", self.feeds);
+		println!("{}", self.fingerprint_assets);
+		println!("{}", self.footnotes);
+		println!("{}", self.heading_anchors);
 		println!("{}", self.help);
 		println!("{}", self.host);
+		println!("{}", self.image_sizes);
+		println!("{}", self.image_widths);
 		println!("{}", self.input);
+		println!("{}", self.math);
+		println!("{}", self.minify);
 		println!("{}", self.output);
+		println!("{}", self.podcast);
 		println!("{}", self.port);
+		println!("{}", self.precompress);
+		println!("{}", self.related_limit);
+		println!("{}", self.robots);
+		println!("{}", self.search);
+		println!("{}", self.search_body_words);
+		println!("{}", self.search_index);
+		println!("{}", self.search_stop_words);
+		println!("{}", self.seo_email);
 		println!("{}", self.serial);
 		println!("{}", self.single_file);
+		println!("{}", self.smart_punctuation);
+		println!("{}", self.staging);
+		println!("{}", self.strikethrough);
+		println!("{}", self.tasklists);
 		println!("{}", self.title);
 		println!("{}", self.watch);
 	}
@@ -467,25 +814,236 @@ impl Args {
 			Some(PathBuf::from(self.single_file.value))
 		};
 
+		let minify = if self.minify.set {
+			self.minify.value
+		} else {
+			self.deploy.value
+		};
+
+		let precompress = if self.precompress.set {
+			self.precompress.value
+		} else {
+			self.deploy.value
+		};
+
+		let feed_limit_name = self.feed_limit.name;
+		let feed_limit_value = self.feed_limit.value;
+		let feed_limit = feed_limit_value.try_into().unwrap_or_else(|e| {
+			panic!(
+				"Invalid negative {} {}: {}",
+				feed_limit_name, feed_limit_value, e
+			)
+		});
+
+		let related_limit_name = self.related_limit.name;
+		let related_limit_value = self.related_limit.value;
+		let related_limit =
+			related_limit_value.try_into().unwrap_or_else(|e| {
+				panic!(
+					"Invalid negative {} {}: {}",
+					related_limit_name, related_limit_value, e
+				)
+			});
+
+		let feeds = self
+			.feeds
+			.value
+			.into_iter()
+			.map(|(group, formats)| {
+				let formats = formats
+					.split(',')
+					.map(|format| {
+						FeedFormat::parse(format.trim()).unwrap_or_else(|| {
+							panic!(
+								"Unknown feed format \"{}\" for group {}, expected atom, rss, json or podcast.",
+								format.trim(),
+								group
+							)
+						})
+					})
+					.collect();
+				(group, formats)
+			})
+			.collect();
+
+		for (key, value) in &self.podcast.value {
+			if !PODCAST_KEYS.contains(&key.as_str()) {
+				panic!(
+					"Unknown key {} in {}, expected one of: {}.",
+					key,
+					self.podcast.name,
+					PODCAST_KEYS.join(", ")
+				)
+			}
+			if key == "explicit" && value != "true" && value != "false" {
+				panic!(
+					"Invalid explicit \"{}\" in {}, expected \"true\" or \"false\".",
+					value, self.podcast.name
+				)
+			}
+		}
+
+		let robots = self
+			.robots
+			.value
+			.iter()
+			.map(|(agent, rules)| {
+				(agent.clone(), robots::parse_rules(agent, rules))
+			})
+			.collect();
+
+		let search_name = self.search.name;
+		let search = self
+			.search
+			.value
+			.into_iter()
+			.map(|(group, included)| {
+				let included = match included.as_str() {
+					"true" => true,
+					"false" => false,
+					_ => panic!(
+						"Invalid value \"{}\" for group {} in {}, expected \"true\" or \"false\".",
+						included, group, search_name
+					),
+				};
+				(group, included)
+			})
+			.collect();
+
+		let search_body_words_name = self.search_body_words.name;
+		let search_body_words_value = self.search_body_words.value;
+		let search_body_words =
+			search_body_words_value.try_into().unwrap_or_else(|e| {
+				panic!(
+					"Invalid negative {} {}: {}",
+					search_body_words_name, search_body_words_value, e
+				)
+			});
+
+		let search_index = if self.search_index.value.is_empty() {
+			None
+		} else {
+			Some(PathBuf::from(self.search_index.value))
+		};
+
+		let search_stop_words = self
+			.search_stop_words
+			.value
+			.split(',')
+			.map(|word| word.trim().to_lowercase())
+			.filter(|word| !word.is_empty())
+			.collect();
+
+		let image_widths_name = self.image_widths.name;
+		let image_widths = self
+			.image_widths
+			.value
+			.split(',')
+			.filter(|width| !width.trim().is_empty())
+			.map(|width| {
+				width.trim().parse::<u32>().unwrap_or_else(|e| {
+					panic!(
+						"Invalid width \"{}\" in {}: {}",
+						width, image_widths_name, e
+					)
+				})
+			})
+			.collect();
+
 		Config {
+			admonitions: self.admonitions.value,
 			author: self.author.value,
 			base_url,
+			bundle_css: self.bundle_css.value,
+			check: self.check.value,
 			deploy: self.deploy.value,
 			email: self.email.value,
+			excerpt_separator: self.excerpt_separator.value,
+			feed_limit,
+			feed_summary: self.feed_summary.value,
+			feeds,
+			fingerprint_assets: self.fingerprint_assets.value,
+			footnotes: self.footnotes.value,
+			heading_anchors: self.heading_anchors.value,
 			host: self.host.value,
+			image_sizes: self.image_sizes.value,
+			image_widths,
 			input_dir: PathBuf::from(self.input.value),
+			math: self.math.value,
+			minify,
 			output_dir: PathBuf::from(self.output.value),
+			podcast: self.podcast.value,
 			port: self.port.value,
+			precompress,
+			related_limit,
+			robots,
+			search,
+			search_body_words,
+			search_index,
+			search_stop_words,
+			seo_email: self.seo_email.value,
 			serial: self.serial.value,
 			single_file,
+			smart_punctuation: self.smart_punctuation.value,
+			staging: self.staging.value,
+			strikethrough: self.strikethrough.value,
+			tasklists: self.tasklists.value,
 			title: self.title.value,
 			watch: self.watch.value,
 		}
 	}
 }
 
-pub fn make_site_info(config: &Config) -> SiteInfo {
+const DEFAULT_STOP_WORDS: &str = "a, an, and, are, as, at, be, but, by, for, from, has, have, in, is, it, its, of, on, or, that, the, this, to, was, were, will, with";
+
+const PODCAST_KEYS: [&str; 5] =
+	["category", "description", "explicit", "image", "language"];
+
+pub fn default_admonitions() -> BTreeMap<String, String> {
+	["note", "tip", "important", "warning", "caution"]
+		.iter()
+		.map(|kind| (kind.to_string(), kind.to_string()))
+		.collect()
+}
+
+pub fn make_site_info<'a>(
+	config: &'a Config,
+	state: &'a BuildState,
+) -> SiteInfo<'a> {
 	SiteInfo {
 		title: &config.title,
+		base_url: &config.base_url,
+		author: &config.author,
+		email: if config.seo_email {
+			Some(&config.email)
+		} else {
+			None
+		},
+		markdown_extensions: Extensions {
+			footnotes: config.footnotes,
+			heading_anchors: config.heading_anchors,
+			math: config.math,
+			smart_punctuation: config.smart_punctuation,
+			strikethrough: config.strikethrough,
+			tasklists: config.tasklists,
+		},
+		admonitions: &config.admonitions,
+		image_sizes: &config.image_sizes,
+		image_widths: &config.image_widths,
+		minify: if config.minify {
+			Some(&state.minified_bytes)
+		} else {
+			None
+		},
+		feeds: &config.feeds,
+		highlighted: &state.highlighted,
+		links: &state.links,
+		related_limit: config.related_limit,
+		search: config.search_index.as_ref().map(|_| search::Indexer {
+			rules: &config.search,
+			stop_words: &config.search_stop_words,
+			body_words: config.search_body_words,
+			entries: &state.search_entries,
+		}),
 	}
 }
@@ -0,0 +1,410 @@
+// With bundle_css, CSS files are bundled at generation time: local @import
+// rules are replaced by the imported files, comments and redundant whitespace
+// are stripped and relative url() references are rewritten to point at the
+// output locations from the bundle. Files whose names start with _ are partials
+// which are only output as part of the files importing them.
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::links;
+use crate::liquid;
+use crate::util::{strip_prefix, translate_input_to_output, CSS_EXTENSION};
+
+pub struct Bundle {
+	pub content: String,
+	// The bundled file and all files it imports, directly or indirectly.
+	pub sources: Vec<PathBuf>,
+}
+
+// Bundles by the input path of their entry point, empty when bundling is off so
+// that CSS files are output as they are.
+pub type Bundles = HashMap<PathBuf, Bundle>;
+
+// Bundles the CSS files among the given ones, which are then written from the
+// result instead of being bundled again.
+pub fn bundle_all(
+	files: &[PathBuf],
+	input_dir: &Path,
+	output_dir: &Path,
+) -> Bundles {
+	files
+		.iter()
+		.filter(|path| path.extension() == Some(OsStr::new(CSS_EXTENSION)))
+		.map(|path| (path.clone(), bundle(path, input_dir, output_dir)))
+		.collect()
+}
+
+// Entry points of the bundles including the given file, itself included.
+pub fn find_importing(
+	bundles: &Bundles,
+	input_file_path: &Path,
+) -> Vec<PathBuf> {
+	let mut importing = bundles
+		.iter()
+		.filter(|(_, bundle)| {
+			bundle
+				.sources
+				.iter()
+				.any(|source| source == input_file_path)
+		})
+		.map(|(path, _)| path.clone())
+		.collect::<Vec<_>>();
+	importing.sort();
+	importing
+}
+
+pub fn is_partial(input_file_path: &Path, input_dir: &Path) -> bool {
+	let relative = strip_prefix(input_file_path, input_dir);
+	relative.components().enumerate().any(|(i, component)| {
+		if let Component::Normal(name) = component {
+			let name = name.to_string_lossy();
+			name.starts_with('_') && !(i == 0 && name == "_static")
+		} else {
+			false
+		}
+	})
+}
+
+pub fn bundle(
+	input_file_path: &Path,
+	input_dir: &Path,
+	output_dir: &Path,
+) -> Bundle {
+	let mut bundler = Bundler {
+		input_dir,
+		output_dir,
+		output_file_path: translate_input_to_output(
+			input_file_path,
+			input_dir,
+			output_dir,
+		),
+		import_stack: Vec::new(),
+		sources: Vec::new(),
+		imports: String::new(),
+		rules: String::new(),
+	};
+	bundler.append_file(input_file_path);
+	Bundle {
+		content: bundler.imports + &bundler.rules,
+		sources: bundler.sources,
+	}
+}
+
+struct Bundler<'a> {
+	input_dir: &'a Path,
+	output_dir: &'a Path,
+	// Where the bundle ends up, which url() references are made relative to.
+	output_file_path: PathBuf,
+	import_stack: Vec<PathBuf>,
+	sources: Vec<PathBuf>,
+	// Imports which aren't inlined, kept first as required by CSS.
+	imports: String,
+	rules: String,
+}
+
+impl<'a> Bundler<'a> {
+	fn append_file(&mut self, input_file_path: &Path) {
+		if self.import_stack.iter().any(|path| path == input_file_path) {
+			panic!(
+				"Circular @import of {} from {}.",
+				input_file_path.display(),
+				self.import_stack
+					.last()
+					.map_or(input_file_path, |p| p)
+					.display()
+			)
+		}
+		let content = fs::read_to_string(input_file_path).unwrap_or_else(|e| {
+			panic!("Failed reading {}: {}", input_file_path.display(), e)
+		});
+		if !self.sources.iter().any(|path| path == input_file_path) {
+			self.sources.push(input_file_path.to_path_buf());
+		}
+		self.import_stack.push(input_file_path.to_path_buf());
+
+		let text = minify(&content);
+		let bytes = text.as_bytes();
+		let mut copied = 0;
+		let mut i = 0;
+		while i < bytes.len() {
+			match bytes[i] {
+				b'"' | b'\'' => i = find_string_end(bytes, i),
+				b'@' if starts_with_ignore_case(&text[i..], "@import") => {
+					let end = find_statement_end(bytes, i);
+					self.rules.push_str(&text[copied..i]);
+					self.append_import(
+						text[i + "@import".len()..end].trim(),
+						input_file_path,
+					);
+					i = (end + 1).min(bytes.len());
+					copied = i;
+				}
+				b'u' | b'U'
+					if starts_with_ignore_case(&text[i..], "url(")
+						&& (i == 0 || !is_name_byte(bytes[i - 1])) =>
+				{
+					let end = find_url_end(bytes, i + "url(".len());
+					self.rules.push_str(&text[copied..i]);
+					let url = self.rewrite_url(
+						&text[i + "url(".len()..end],
+						input_file_path,
+					);
+					self.rules.push_str(&url);
+					i = (end + 1).min(bytes.len());
+					copied = i;
+				}
+				_ => i += 1,
+			}
+		}
+		self.rules.push_str(&text[copied..]);
+
+		self.import_stack.pop();
+	}
+
+	// Inlines @import "file.css" media; rules referring to local files,
+	// wrapping the imported rules in @media if the import was conditional.
+	// Other imports are kept as they are.
+	fn append_import(&mut self, prelude: &str, input_file_path: &Path) {
+		let bytes = prelude.as_bytes();
+		let (target, media) = match bytes.first() {
+			// Unterminated strings are kept as they are.
+			Some(b'"') | Some(b'\'') => {
+				let end = find_string_end(bytes, 0);
+				if end >= 2 && bytes[end - 1] == bytes[0] {
+					(&prelude[1..end - 1], prelude[end..].trim())
+				} else {
+					("", "")
+				}
+			}
+			Some(b'u') | Some(b'U')
+				if starts_with_ignore_case(prelude, "url(") =>
+			{
+				let end = find_url_end(bytes, "url(".len());
+				(
+					prelude["url(".len()..end]
+						.trim()
+						.trim_matches(|c| c == '"' || c == '\''),
+					prelude[(end + 1).min(prelude.len())..].trim(),
+				)
+			}
+			_ => ("", ""),
+		};
+
+		if target.is_empty()
+			|| target.contains(':')
+			|| target.starts_with("//")
+			|| starts_with_ignore_case(media, "layer")
+			|| starts_with_ignore_case(media, "supports")
+		{
+			self.imports.push_str("@import ");
+			self.imports.push_str(prelude);
+			self.imports.push(';');
+			return;
+		}
+
+		let imported_path =
+			links::resolve_input_path(target, input_file_path, self.input_dir)
+				.unwrap_or_else(|| {
+					panic!(
+						"@import of {} in {} leads outside of the input directory.",
+						target,
+						input_file_path.display()
+					)
+				});
+		if !imported_path.is_file() {
+			panic!(
+				"Failed finding {} imported from {}.",
+				imported_path.display(),
+				input_file_path.display()
+			)
+		}
+
+		if media.is_empty() {
+			self.append_file(&imported_path);
+		} else {
+			self.rules.push_str("@media ");
+			self.rules.push_str(media);
+			self.rules.push('{');
+			self.append_file(&imported_path);
+			self.rules.push('}');
+		}
+	}
+
+	// Makes relative URLs relative to the location of the bundle instead of
+	// the file they were written in.
+	fn rewrite_url(&self, inner: &str, input_file_path: &Path) -> String {
+		let unchanged = format!("url({})", inner);
+		let inner = inner.trim();
+		let (quote, url) = match inner.chars().next() {
+			Some(c) if (c == '"' || c == '\'') && inner.len() >= 2 => {
+				(Some(c), &inner[1..inner.len() - 1])
+			}
+			_ => (None, inner),
+		};
+		if url.is_empty()
+			|| url.starts_with('/')
+			|| url.starts_with('#')
+			|| url.contains(':')
+		{
+			return unchanged;
+		}
+
+		let (path, suffix) = match url.find(['?', '#']) {
+			Some(index) => url.split_at(index),
+			None => (url, ""),
+		};
+		let linked_input_path = match links::resolve_input_path(
+			path,
+			input_file_path,
+			self.input_dir,
+		) {
+			Some(linked_input_path) => linked_input_path,
+			None => return unchanged,
+		};
+		let linked_output_path = translate_input_to_output(
+			&linked_input_path,
+			self.input_dir,
+			self.output_dir,
+		);
+		let relative = liquid::make_relative_link(
+			&self.output_file_path,
+			&linked_output_path,
+			&self.output_dir.to_path_buf(),
+			// Only used by the 404 page, which isn't a bundle.
+			"",
+		);
+		let relative = relative.strip_prefix("./").unwrap_or(&relative);
+		match quote {
+			Some(quote) => {
+				format!("url({}{}{}{})", quote, relative, suffix, quote)
+			}
+			None => format!("url({}{})", relative, suffix),
+		}
+	}
+}
+
+// Strips comments and whitespace which isn't needed to separate tokens.
+pub fn minify(content: &str) -> String {
+	// Whitespace next to these characters carries no meaning. Whitespace
+	// before : or ( can't be dropped, as in "a :hover" and "and (color)".
+	const NO_SPACE_AFTER: &str = "{};,>:(";
+	const NO_SPACE_BEFORE: &str = "{};,>)!";
+
+	let chars = content.chars().collect::<Vec<_>>();
+	let mut result = String::with_capacity(content.len());
+	let mut pending_space = false;
+	let mut i = 0;
+	while i < chars.len() {
+		let c = chars[i];
+		if c == '/' && chars.get(i + 1) == Some(&'*') {
+			i = (i + 2..chars.len().saturating_sub(1))
+				.find(|&j| chars[j] == '*' && chars[j + 1] == '/')
+				.map_or(chars.len(), |j| j + 2);
+			continue;
+		}
+		if c.is_whitespace() {
+			pending_space = true;
+			i += 1;
+			continue;
+		}
+		if pending_space {
+			pending_space = false;
+			if let Some(last) = result.chars().last() {
+				if !NO_SPACE_AFTER.contains(last)
+					&& !NO_SPACE_BEFORE.contains(c)
+				{
+					result.push(' ');
+				}
+			}
+		}
+
+		if c == '"' || c == '\'' {
+			let start = i;
+			i += 1;
+			while i < chars.len() && chars[i] != c && chars[i] != '\n' {
+				if chars[i] == '\\' {
+					i += 1;
+				}
+				i += 1;
+			}
+			i = (i + 1).min(chars.len());
+			result.extend(&chars[start..i]);
+			continue;
+		}
+		if c == '('
+			&& result.len() >= 3
+			&& result.as_bytes()[result.len() - 3..]
+				.eq_ignore_ascii_case(b"url")
+		{
+			// Unquoted URLs are copied as they are, apart from surrounding
+			// whitespace.
+			let end = (i..chars.len())
+				.find(|&j| chars[j] == ')')
+				.unwrap_or(chars.len());
+			let inner = chars[i + 1..end].iter().collect::<String>();
+			if !inner.trim_start().starts_with(['"', '\'']) {
+				result.push('(');
+				result.push_str(inner.trim());
+				i = end;
+				continue;
+			}
+		}
+		if c == '}' && result.ends_with(';') {
+			result.pop();
+		}
+		result.push(c);
+		i += 1;
+	}
+	result
+}
+
+fn starts_with_ignore_case(text: &str, prefix: &str) -> bool {
+	text.len() >= prefix.len()
+		&& text.as_bytes()[..prefix.len()]
+			.eq_ignore_ascii_case(prefix.as_bytes())
+}
+
+fn is_name_byte(byte: u8) -> bool {
+	byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_'
+}
+
+// Index after the closing quote of the string starting at start.
+fn find_string_end(bytes: &[u8], start: usize) -> usize {
+	let quote = bytes[start];
+	let mut i = start + 1;
+	while i < bytes.len() && bytes[i] != quote {
+		if bytes[i] == b'\\' {
+			i += 1;
+		}
+		i += 1;
+	}
+	(i + 1).min(bytes.len())
+}
+
+// Index of the ; ending the statement starting at start.
+fn find_statement_end(bytes: &[u8], start: usize) -> usize {
+	let mut i = start;
+	while i < bytes.len() {
+		match bytes[i] {
+			b'"' | b'\'' => i = find_string_end(bytes, i),
+			b';' => return i,
+			_ => i += 1,
+		}
+	}
+	bytes.len()
+}
+
+// Index of the ) ending the url( starting before start.
+fn find_url_end(bytes: &[u8], start: usize) -> usize {
+	let mut i = start;
+	while i < bytes.len() {
+		match bytes[i] {
+			b'"' | b'\'' => i = find_string_end(bytes, i),
+			b')' => return i,
+			_ => i += 1,
+		}
+	}
+	bytes.len()
+}
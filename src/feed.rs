@@ -0,0 +1,358 @@
+// Feeds of the pages in each group, written in the formats configured for the
+// group: Atom by default, RSS 2.0 for readers and other tools which only know
+// it, RSS 2.0 with iTunes tags for podcast directories, and JSON Feed 1.1 for
+// programmatic consumers.
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::atom;
+use crate::config::Config;
+use crate::front_matter;
+use crate::json_feed;
+use crate::links;
+use crate::markdown::{GroupedOptionOutputFile, InputFile};
+use crate::rss;
+use crate::util;
+
+pub const DIRECTORY: &str = "feeds";
+// Name of the feed combining all groups, and the directory of tag feeds, both
+// under DIRECTORY.
+pub const ALL_NAME: &str = "all";
+pub const TAGS_NAME: &str = "tags";
+
+pub struct FeedHeader {
+	pub title: String,
+	pub base_url: String,
+	pub feed_url: String,
+	pub latest_update: Option<String>,
+	pub author_name: String,
+	pub author_email: String,
+	// Channel metadata of podcast feeds, such as category and image.
+	pub podcast: BTreeMap<String, String>,
+}
+
+pub struct FeedEntry {
+	pub front_matter: Arc<front_matter::FrontMatter>,
+	pub html_content: String,
+	// When set, replaces the full content in the entry.
+	pub summary: Option<String>,
+	pub permalink: PathBuf,
+	pub enclosure: Option<Enclosure>,
+}
+
+// Media file attached to an entry, such as the audio of a podcast episode.
+pub struct Enclosure {
+	// Relative to the output directory.
+	pub path: PathBuf,
+	pub length: u64,
+	pub mime_type: &'static str,
+	pub duration_seconds: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FeedFormat {
+	Atom,
+	Json,
+	Podcast,
+	Rss,
+}
+
+impl FeedFormat {
+	pub fn parse(name: &str) -> Option<Self> {
+		match name {
+			"atom" => Some(Self::Atom),
+			"json" => Some(Self::Json),
+			"podcast" => Some(Self::Podcast),
+			"rss" => Some(Self::Rss),
+			_ => None,
+		}
+	}
+
+	// Path relative to the output directory of the feed with the given name.
+	pub fn file_path(self, name: &str) -> PathBuf {
+		let file_name = match self {
+			Self::Atom => format!("{}.{}", name, util::XML_EXTENSION),
+			Self::Json => format!("{}.{}", name, util::JSON_EXTENSION),
+			Self::Podcast => {
+				format!("{}.podcast.{}", name, util::XML_EXTENSION)
+			}
+			Self::Rss => format!("{}.rss.{}", name, util::XML_EXTENSION),
+		};
+		PathBuf::from(DIRECTORY).join(file_name)
+	}
+
+	pub fn mime_type(self) -> &'static str {
+		match self {
+			Self::Atom => "application/atom+xml",
+			Self::Json => "application/feed+json",
+			Self::Podcast | Self::Rss => "application/rss+xml",
+		}
+	}
+}
+
+// Groups without configured formats only get an Atom feed, as do the combined
+// and tag feeds unless configured through the all and tags keys.
+pub fn formats_for<'a>(
+	formats: &'a BTreeMap<String, Vec<FeedFormat>>,
+	group: &str,
+) -> &'a [FeedFormat] {
+	formats
+		.get(group)
+		.map_or(&[FeedFormat::Atom], Vec::as_slice)
+}
+
+// Writes a feed per group, per tag and one combining all groups.
+pub fn generate(
+	groups: HashMap<String, Vec<FeedEntry>>,
+	tags: &HashMap<String, Vec<InputFile>>,
+	config: &Config,
+) {
+	let mut all_entries: Vec<&FeedEntry> = Vec::new();
+	for (group, entries) in &groups {
+		let mut entries = entries.iter().collect::<Vec<_>>();
+		all_entries.extend(&entries);
+		generate_feeds(
+			group,
+			&format!("{} - {}", config.title, util::capitalize(group)),
+			formats_for(&config.feeds, group),
+			&mut entries,
+			config,
+		);
+	}
+
+	for tag in tags.keys() {
+		let mut entries = all_entries
+			.iter()
+			.copied()
+			.filter(|entry| entry.front_matter.tags.contains(tag))
+			.collect::<Vec<_>>();
+		generate_feeds(
+			&format!("{}/{}", TAGS_NAME, tag),
+			&format!("{} - Tag: {}", config.title, tag),
+			formats_for(&config.feeds, TAGS_NAME),
+			&mut entries,
+			config,
+		);
+	}
+
+	if !groups.is_empty() {
+		generate_feeds(
+			ALL_NAME,
+			&config.title,
+			formats_for(&config.feeds, ALL_NAME),
+			&mut all_entries,
+			config,
+		);
+	}
+}
+
+// Writes the newest entries, up to the configured limit, in each format.
+fn generate_feeds(
+	name: &str,
+	title: &str,
+	formats: &[FeedFormat],
+	entries: &mut Vec<&FeedEntry>,
+	config: &Config,
+) {
+	entries.sort_by(|lhs, rhs| {
+		let date_ordering = rhs.front_matter.date.cmp(&lhs.front_matter.date);
+		match date_ordering {
+			Ordering::Less | Ordering::Greater => date_ordering,
+			Ordering::Equal => lhs.permalink.cmp(&rhs.permalink),
+		}
+	});
+	if config.feed_limit > 0 {
+		entries.truncate(config.feed_limit);
+	}
+
+	let latest_update = entries
+		.iter()
+		.flat_map(|entry| {
+			[&entry.front_matter.date, &entry.front_matter.edited]
+		})
+		.flatten()
+		.max();
+
+	for &format in formats {
+		let file_path = config.output_dir.join(format.file_path(name));
+		let header = FeedHeader {
+			title: title.to_string(),
+			base_url: config.base_url.clone(),
+			feed_url: complete_url(
+				&config.base_url,
+				&util::strip_prefix(&file_path, &config.output_dir)
+					.to_string_lossy(),
+			),
+			latest_update: latest_update.cloned(),
+			author_name: config.author.clone(),
+			author_email: config.email.clone(),
+			podcast: config.podcast.clone(),
+		};
+		write_feed(&file_path, format, &header, entries);
+	}
+}
+
+fn write_feed(
+	file_path: &Path,
+	format: FeedFormat,
+	header: &FeedHeader,
+	entries: &[&FeedEntry],
+) {
+	let parent_dir = file_path.parent().unwrap_or_else(|| {
+		panic!(
+			"Feed file path without a parent directory?: {}",
+			file_path.display()
+		)
+	});
+	fs::create_dir_all(parent_dir).unwrap_or_else(|e| {
+		panic!(
+			"Failed creating directories for {}: {}",
+			parent_dir.display(),
+			e
+		)
+	});
+
+	let feed = fs::File::create(file_path).unwrap_or_else(|e| {
+		panic!("Failed creating {}: {}", file_path.display(), e)
+	});
+
+	let mut output = BufWriter::new(feed);
+	match format {
+		FeedFormat::Atom => atom::write_feed(header, entries, &mut output),
+		FeedFormat::Json => json_feed::write_feed(header, entries, &mut output),
+		FeedFormat::Podcast => {
+			rss::write_podcast_feed(header, entries, &mut output)
+		}
+		FeedFormat::Rss => rss::write_feed(header, entries, &mut output),
+	}
+
+	let feed = output.into_inner().unwrap_or_else(|e| {
+		panic!(
+			"Failed flushing buffered data to feed \"{}\": {}.",
+			&file_path.display(),
+			e
+		)
+	});
+
+	// Avoiding sync_all() for now to be friendlier to disks.
+	feed.sync_data().unwrap_or_else(|e| {
+		panic!(
+			"Failed sync_data() for \"{}\": {}.",
+			&file_path.display(),
+			e
+		)
+	});
+}
+
+pub fn complete_url(base_url: &str, path: &str) -> String {
+	let mut url = base_url.to_string();
+	url.push_str(path);
+	url
+}
+
+// The enclosure of the audio file in the front matter, if any. Its path is
+// relative to the page, or to the input directory if starting with /.
+pub fn make_enclosure(
+	input_file_path: &Path,
+	front_matter: &front_matter::FrontMatter,
+	root_input_dir: &Path,
+	root_output_dir: &Path,
+	input_output_map: &HashMap<PathBuf, GroupedOptionOutputFile>,
+) -> Option<Enclosure> {
+	let audio = front_matter.audio.as_ref()?;
+	let audio_input_path =
+		links::resolve_input_path(audio, input_file_path, root_input_dir)
+			.unwrap_or_else(|| {
+				panic!(
+					"audio {} in {} leads outside of the input directory.",
+					audio,
+					input_file_path.display()
+				)
+			});
+	let output_path = match input_output_map.get(&audio_input_path) {
+		Some(entry) if entry.file.front_matter.is_none() => &entry.file.path,
+		_ => panic!(
+			"Failed finding audio {} of {}, expected a file under _static/.",
+			audio_input_path.display(),
+			input_file_path.display()
+		),
+	};
+	let length = fs::metadata(&audio_input_path)
+		.unwrap_or_else(|e| {
+			panic!(
+				"Failed reading metadata of {}: {}",
+				audio_input_path.display(),
+				e
+			)
+		})
+		.len();
+	let extension = audio_input_path
+		.extension()
+		.map_or_else(String::new, |e| e.to_string_lossy().to_lowercase());
+	let mime_type = audio_mime_type(&extension).unwrap_or_else(|| {
+		panic!(
+			"Unsupported audio format of {} in {}, expected one of: {}.",
+			audio,
+			input_file_path.display(),
+			AUDIO_TYPES
+				.iter()
+				.map(|(extension, _)| *extension)
+				.collect::<Vec<_>>()
+				.join(", ")
+		)
+	});
+	let duration_seconds = front_matter.duration.as_ref().map(|duration| {
+		parse_duration(duration).unwrap_or_else(|| {
+			panic!(
+				"Invalid duration \"{}\" in {}, expected seconds or [HH:]MM:SS.",
+				duration,
+				input_file_path.display()
+			)
+		})
+	});
+
+	Some(Enclosure {
+		path: util::strip_prefix(output_path, root_output_dir),
+		length,
+		mime_type,
+		duration_seconds,
+	})
+}
+
+const AUDIO_TYPES: [(&str, &str); 8] = [
+	("aac", "audio/aac"),
+	("flac", "audio/flac"),
+	("m4a", "audio/x-m4a"),
+	("mp3", "audio/mpeg"),
+	("oga", "audio/ogg"),
+	("ogg", "audio/ogg"),
+	("opus", "audio/opus"),
+	("wav", "audio/wav"),
+];
+
+pub fn audio_mime_type(extension: &str) -> Option<&'static str> {
+	AUDIO_TYPES
+		.iter()
+		.find(|(known, _)| *known == extension)
+		.map(|(_, mime_type)| *mime_type)
+}
+
+// Seconds of a duration given as seconds, MM:SS or HH:MM:SS.
+pub fn parse_duration(duration: &str) -> Option<u64> {
+	let parts = duration.split(':').collect::<Vec<_>>();
+	if parts.len() > 3 {
+		return None;
+	}
+	parts.iter().enumerate().try_fold(0, |seconds, (i, part)| {
+		let value = part.trim().parse::<u64>().ok()?;
+		// Only the leading part may exceed 59.
+		if i > 0 && value > 59 {
+			return None;
+		}
+		Some(seconds * 60 + value)
+	})
+}
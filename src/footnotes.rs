@@ -0,0 +1,156 @@
+// Moves footnote definitions out of the event stream into a single section at
+// the end of the document, numbered in order of first reference and linked in
+// both directions, using the DPUB-ARIA roles so screen readers can announce
+// them as such.
+use std::collections::HashMap;
+
+use pulldown_cmark::{html, CowStr, Event, Tag};
+
+pub fn render<'a, I: Iterator<Item = Event<'a>>>(events: I) -> String {
+	let mut body = Vec::new();
+	let mut definitions: HashMap<CowStr, Vec<Event>> = HashMap::new();
+	let mut definition_order = Vec::new();
+	let mut current_definition: Option<(CowStr, Vec<Event>)> = None;
+	for event in events {
+		match event {
+			Event::Start(Tag::FootnoteDefinition(label)) => {
+				current_definition = Some((label, Vec::new()))
+			}
+			Event::End(Tag::FootnoteDefinition(_)) => {
+				if let Some((label, events)) = current_definition.take() {
+					if !definitions.contains_key(&label) {
+						definition_order.push(label.clone());
+					}
+					definitions.insert(label, events);
+				}
+			}
+			_ => {
+				if let Some((_, events)) = &mut current_definition {
+					events.push(event)
+				} else {
+					body.push(event)
+				}
+			}
+		}
+	}
+
+	let mut numbers = HashMap::new();
+	let mut reference_counts = HashMap::new();
+	let body = link_references(body, &mut numbers, &mut reference_counts);
+
+	let mut html_content = String::new();
+	html::push_html(&mut html_content, body.into_iter());
+
+	if definitions.is_empty() {
+		return html_content;
+	}
+
+	// Definitions may reference other footnotes, and those that are never
+	// referenced are still listed, after the referenced ones.
+	let mut rendered_definitions = HashMap::new();
+	for label in &definition_order {
+		let events = definitions.remove(label).unwrap_or_default();
+		let events =
+			link_references(events, &mut numbers, &mut reference_counts);
+		let mut definition_html = String::new();
+		html::push_html(&mut definition_html, events.into_iter());
+		rendered_definitions.insert(label.clone(), definition_html);
+	}
+	for label in &definition_order {
+		get_number(label, &mut numbers);
+	}
+
+	let mut ordered_labels = numbers.into_iter().collect::<Vec<_>>();
+	ordered_labels.sort_by_key(|(_, number)| *number);
+
+	html_content.push_str(
+		"<section class=\"footnotes\" role=\"doc-endnotes\">\n<hr />\n<ol>\n",
+	);
+	for (label, number) in ordered_labels {
+		let id = make_id(&label);
+		let definition_html = if let Some(definition_html) =
+			rendered_definitions.remove(&label)
+		{
+			definition_html
+		} else {
+			println!("WARNING: Missing definition of footnote {}.", label);
+			continue;
+		};
+
+		let backlink = if reference_counts.contains_key(&label) {
+			format!(
+				" <a href=\"#fnref-{}\" class=\"footnote-backref\" role=\"doc-backlink\" aria-label=\"Back to reference {}\">\u{21a9}\u{fe0e}</a>",
+				id, number
+			)
+		} else {
+			String::new()
+		};
+
+		html_content.push_str(&format!("<li id=\"fn-{}\">\n", id));
+		let trimmed = definition_html.trim_end();
+		if let Some(stripped) = trimmed.strip_suffix("</p>") {
+			html_content.push_str(stripped);
+			html_content.push_str(&backlink);
+			html_content.push_str("</p>\n");
+		} else {
+			html_content.push_str(&definition_html);
+			html_content.push_str(&backlink);
+			html_content.push('\n');
+		}
+		html_content.push_str("</li>\n");
+	}
+	html_content.push_str("</ol>\n</section>\n");
+
+	html_content
+}
+
+fn link_references<'a>(
+	events: Vec<Event<'a>>,
+	numbers: &mut HashMap<CowStr<'a>, usize>,
+	reference_counts: &mut HashMap<CowStr<'a>, usize>,
+) -> Vec<Event<'a>> {
+	events
+		.into_iter()
+		.map(|event| {
+			if let Event::FootnoteReference(label) = event {
+				let number = get_number(&label, numbers);
+				let count = reference_counts.entry(label.clone()).or_insert(0);
+				*count += 1;
+				let id = make_id(&label);
+				// Only the first reference is the target of the backlink.
+				let reference_id = if *count == 1 {
+					format!("fnref-{}", id)
+				} else {
+					format!("fnref-{}-{}", id, count)
+				};
+				Event::Html(CowStr::from(format!(
+					"<sup class=\"footnote-ref\"><a href=\"#fn-{}\" id=\"{}\" role=\"doc-noteref\">{}</a></sup>",
+					id, reference_id, number
+				)))
+			} else {
+				event
+			}
+		})
+		.collect()
+}
+
+fn get_number<'a>(
+	label: &CowStr<'a>,
+	numbers: &mut HashMap<CowStr<'a>, usize>,
+) -> usize {
+	let next = numbers.len() + 1;
+	*numbers.entry(label.clone()).or_insert(next)
+}
+
+fn make_id(label: &str) -> String {
+	label
+		.chars()
+		.map(|c| {
+			if c.is_alphanumeric() || c == '-' || c == '_' {
+				c
+			} else {
+				'-'
+			}
+		})
+		.collect()
+}
@@ -1,8 +1,10 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryInto;
 use std::ffi::OsStr;
 use std::fs;
 use std::io::{BufRead, BufReader, Seek};
 use std::path::PathBuf;
+use std::sync::Mutex;
 
 use yaml_rust::YamlLoader;
 
@@ -12,33 +14,81 @@ pub struct FrontMatter {
 	pub title: String,
 	pub date: Option<String>,
 	pub published: bool,
+	// Asks search engines not to index the page, which is also left out of
+	// the sitemap.
+	pub noindex: bool,
+	// Sitemap entry settings, sitemap being false leaving the page out.
+	pub sitemap: bool,
+	pub priority: Option<f32>,
+	pub changefreq: Option<String>,
 	pub edited: Option<String>,
 	pub categories: Vec<String>,
 	pub tags: Vec<String>,
 	pub layout: Option<String>,
+	// Markdown source, either from the front matter or extracted from the
+	// content.
+	pub excerpt: Option<String>,
+	// Rendered excerpt by the output directory of the pages showing it, as
+	// relative links differ between directories.
+	pub excerpt_html: Mutex<HashMap<PathBuf, String>>,
+	// Per-page overrides of the site-wide markdown extension settings.
+	pub footnotes: Option<bool>,
+	pub math: Option<bool>,
+	pub smart_punctuation: Option<bool>,
+	pub strikethrough: Option<bool>,
+	pub tasklists: Option<bool>,
+	// Podcast episode metadata, the audio file becoming an enclosure in feeds.
+	pub audio: Option<String>,
+	pub duration: Option<String>,
+	pub episode: Option<u32>,
 	pub custom_attributes: BTreeMap<String, String>,
 	pub end_position: u64,
 	pub subsequent_line: usize,
 }
 
+impl FrontMatter {
+	pub fn with_title(title: String) -> Self {
+		Self {
+			title,
+			date: None,
+			published: true,
+			noindex: false,
+			sitemap: true,
+			priority: None,
+			changefreq: None,
+			edited: None,
+			categories: Vec::new(),
+			tags: Vec::new(),
+			layout: None,
+			excerpt: None,
+			excerpt_html: Mutex::new(HashMap::new()),
+			footnotes: None,
+			math: None,
+			smart_punctuation: None,
+			strikethrough: None,
+			tasklists: None,
+			audio: None,
+			duration: None,
+			episode: None,
+			custom_attributes: BTreeMap::new(),
+			end_position: 0,
+			subsequent_line: 1,
+		}
+	}
+}
+
+// Values of changefreq allowed by the sitemap protocol.
+const CHANGE_FREQUENCIES: [&str; 7] = [
+	"always", "hourly", "daily", "weekly", "monthly", "yearly", "never",
+];
+
 pub fn parse(
 	input_file_path: &PathBuf,
 	reader: &mut BufReader<fs::File>,
 ) -> FrontMatter {
 	const MAX_FRONT_MATTER_LINES: u8 = 16;
 
-	let mut result = FrontMatter {
-		title: String::new(),
-		date: None,
-		published: true,
-		edited: None,
-		categories: Vec::new(),
-		tags: Vec::new(),
-		layout: None,
-		custom_attributes: BTreeMap::new(),
-		end_position: 0,
-		subsequent_line: 1,
-	};
+	let mut result = FrontMatter::with_title(String::new());
 
 	let mut line = String::new();
 	let first_line_len = reader.read_line(&mut line).unwrap_or_else(|e| {
@@ -295,6 +345,69 @@ fn parse_yaml_attribute(
 				)
 			}
 		}
+		"noindex" => {
+			if let yaml_rust::Yaml::Boolean(value) = value {
+				front_matter.noindex = *value;
+			} else {
+				panic!(
+					"noindex of \"{}\" has unexpected type {:?}",
+					input_file_path.display(),
+					value
+				)
+			}
+		}
+		"sitemap" => {
+			if let yaml_rust::Yaml::Boolean(value) = value {
+				front_matter.sitemap = *value;
+			} else {
+				panic!(
+					"sitemap of \"{}\" has unexpected type {:?}",
+					input_file_path.display(),
+					value
+				)
+			}
+		}
+		"priority" => {
+			let priority = match value {
+				yaml_rust::Yaml::Real(_) => value.as_f64(),
+				yaml_rust::Yaml::Integer(value) => Some(*value as f64),
+				_ => None,
+			}
+			.unwrap_or_else(|| {
+				panic!(
+					"priority of \"{}\" has unexpected type {:?}",
+					input_file_path.display(),
+					value
+				)
+			});
+			if !(0.0..=1.0).contains(&priority) {
+				panic!(
+					"priority of \"{}\" is {} but must be between 0.0 and 1.0.",
+					input_file_path.display(),
+					priority
+				)
+			}
+			front_matter.priority = Some(priority as f32);
+		}
+		"changefreq" => {
+			if let yaml_rust::Yaml::String(value) = value {
+				if !CHANGE_FREQUENCIES.contains(&value.as_str()) {
+					panic!(
+						"changefreq of \"{}\" is \"{}\", expected one of: {}.",
+						input_file_path.display(),
+						value,
+						CHANGE_FREQUENCIES.join(", ")
+					)
+				}
+				front_matter.changefreq = Some(value.clone());
+			} else {
+				panic!(
+					"changefreq of \"{}\" has unexpected type {:?}",
+					input_file_path.display(),
+					value
+				)
+			}
+		}
 		"edited" => {
 			if let yaml_rust::Yaml::String(value) = value {
 				front_matter.edited = Some(value.clone());
@@ -356,6 +469,117 @@ fn parse_yaml_attribute(
 				)
 			}
 		}
+		"excerpt" => {
+			if let yaml_rust::Yaml::String(value) = value {
+				front_matter.excerpt = Some(value.clone());
+			} else {
+				panic!(
+					"excerpt of \"{}\" has unexpected type {:?}",
+					input_file_path.display(),
+					value
+				)
+			}
+		}
+		"footnotes" => {
+			if let yaml_rust::Yaml::Boolean(value) = value {
+				front_matter.footnotes = Some(*value);
+			} else {
+				panic!(
+					"footnotes of \"{}\" has unexpected type {:?}",
+					input_file_path.display(),
+					value
+				)
+			}
+		}
+		"math" => {
+			if let yaml_rust::Yaml::Boolean(value) = value {
+				front_matter.math = Some(*value);
+			} else {
+				panic!(
+					"math of \"{}\" has unexpected type {:?}",
+					input_file_path.display(),
+					value
+				)
+			}
+		}
+		"smart_punctuation" => {
+			if let yaml_rust::Yaml::Boolean(value) = value {
+				front_matter.smart_punctuation = Some(*value);
+			} else {
+				panic!(
+					"smart_punctuation of \"{}\" has unexpected type {:?}",
+					input_file_path.display(),
+					value
+				)
+			}
+		}
+		"strikethrough" => {
+			if let yaml_rust::Yaml::Boolean(value) = value {
+				front_matter.strikethrough = Some(*value);
+			} else {
+				panic!(
+					"strikethrough of \"{}\" has unexpected type {:?}",
+					input_file_path.display(),
+					value
+				)
+			}
+		}
+		"tasklists" => {
+			if let yaml_rust::Yaml::Boolean(value) = value {
+				front_matter.tasklists = Some(*value);
+			} else {
+				panic!(
+					"tasklists of \"{}\" has unexpected type {:?}",
+					input_file_path.display(),
+					value
+				)
+			}
+		}
+		"audio" => {
+			if let yaml_rust::Yaml::String(value) = value {
+				front_matter.audio = Some(value.clone());
+			} else {
+				panic!(
+					"audio of \"{}\" has unexpected type {:?}",
+					input_file_path.display(),
+					value
+				)
+			}
+		}
+		"duration" => {
+			// Either seconds or [HH:]MM:SS.
+			match value {
+				yaml_rust::Yaml::String(value) => {
+					front_matter.duration = Some(value.clone())
+				}
+				yaml_rust::Yaml::Integer(value) => {
+					front_matter.duration = Some(value.to_string())
+				}
+				_ => panic!(
+					"duration of \"{}\" has unexpected type {:?}",
+					input_file_path.display(),
+					value
+				),
+			}
+		}
+		"episode" => {
+			if let yaml_rust::Yaml::Integer(value) = value {
+				front_matter.episode =
+					Some((*value).try_into().unwrap_or_else(|e| {
+						panic!(
+							"episode of \"{}\" is out of range: {}",
+							input_file_path.display(),
+							e
+						)
+					}));
+			} else {
+				panic!(
+					"episode of \"{}\" has unexpected type {:?}",
+					input_file_path.display(),
+					value
+				)
+			}
+		}
 		_ => {
 			if let yaml_rust::Yaml::String(value) = value {
 				front_matter
@@ -0,0 +1,144 @@
+// Gives headings unique slug IDs so they can be linked to, and collects them
+// for building a table of contents.
+use std::collections::HashSet;
+
+use pulldown_cmark::{CowStr, Event, Tag};
+
+use crate::util::escape_html;
+
+#[derive(Clone)]
+pub struct Heading {
+	pub level: u32,
+	pub title: String,
+	pub id: String,
+}
+
+pub fn assign_ids(
+	events: Vec<Event>,
+	insert_anchors: bool,
+) -> (Vec<Event>, Vec<Heading>) {
+	let mut result = Vec::with_capacity(events.len());
+	let mut headings = Vec::new();
+	let mut used_ids = HashSet::new();
+	let mut current: Option<(u32, Vec<Event>)> = None;
+	for event in events {
+		match event {
+			Event::Start(Tag::Heading(level)) => {
+				current = Some((level, Vec::new()))
+			}
+			Event::End(Tag::Heading(_)) => {
+				let (level, inner) = if let Some(current) = current.take() {
+					current
+				} else {
+					panic!("Heading ended without having started.")
+				};
+
+				let mut title = String::new();
+				for inner_event in &inner {
+					match inner_event {
+						Event::Text(text) | Event::Code(text) => {
+							title.push_str(text)
+						}
+						_ => {}
+					}
+				}
+				let id = make_unique_id(&slugify(&title), &mut used_ids);
+
+				result.push(Event::Html(CowStr::from(format!(
+					"<h{} id=\"{}\">",
+					level, id
+				))));
+				result.extend(inner);
+				if insert_anchors {
+					result.push(Event::Html(CowStr::from(format!(
+						" <a class=\"heading-anchor\" href=\"#{}\" aria-hidden=\"true\">#</a>",
+						id
+					))));
+				}
+				result.push(Event::Html(CowStr::from(format!(
+					"</h{}>\n",
+					level
+				))));
+
+				headings.push(Heading { level, title, id });
+			}
+			_ => {
+				if let Some((_, inner)) = &mut current {
+					inner.push(event)
+				} else {
+					result.push(event)
+				}
+			}
+		}
+	}
+	(result, headings)
+}
+
+// Lower case alphanumerics with runs of whitespace, dashes and underscores
+// turned into single dashes. Other characters are dropped.
+pub fn slugify(title: &str) -> String {
+	let mut slug = String::with_capacity(title.len());
+	for c in title.chars() {
+		if c.is_alphanumeric() {
+			slug.extend(c.to_lowercase());
+		} else if (c.is_whitespace() || c == '-' || c == '_')
+			&& !slug.is_empty()
+			&& !slug.ends_with('-')
+		{
+			slug.push('-');
+		}
+	}
+	while slug.ends_with('-') {
+		slug.pop();
+	}
+	slug
+}
+
+fn make_unique_id(slug: &str, used_ids: &mut HashSet<String>) -> String {
+	let base = if slug.is_empty() { "section" } else { slug };
+	let mut id = base.to_string();
+	let mut suffix = 1;
+	while used_ids.contains(&id) {
+		id = format!("{}-{}", base, suffix);
+		suffix += 1;
+	}
+	used_ids.insert(id.clone());
+	id
+}
+
+// Nested lists following the heading levels, skipped levels don't add extra
+// nesting.
+pub fn make_toc_html(headings: &[Heading]) -> String {
+	let mut toc = String::new();
+	let mut levels: Vec<u32> = Vec::new();
+	for heading in headings {
+		match levels.last() {
+			None => {
+				toc.push_str("<ul>\n<li>");
+				levels.push(heading.level);
+			}
+			Some(&last) if heading.level > last => {
+				toc.push_str("\n<ul>\n<li>");
+				levels.push(heading.level);
+			}
+			Some(_) => {
+				while levels.len() > 1
+					&& levels.last().is_some_and(|&last| heading.level < last)
+				{
+					toc.push_str("</li>\n</ul>\n");
+					levels.pop();
+				}
+				toc.push_str("</li>\n<li>");
+			}
+		}
+		toc.push_str(&format!(
+			"<a href=\"#{}\">{}</a>",
+			heading.id,
+			escape_html(&heading.title)
+		));
+	}
+	for _ in levels {
+		toc.push_str("</li>\n</ul>\n");
+	}
+	toc
+}
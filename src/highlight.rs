@@ -0,0 +1,736 @@
+// Class-based syntax highlighting of code blocks, done at generation time so
+// that it works without JavaScript and inside feeds. Rather than pulling in a
+// full grammar engine, each language is described by its comment and string
+// syntax plus keyword lists, which is good enough for the common cases.
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Tag};
+
+use crate::util::escape_html;
+
+pub const STYLESHEET_PATH: &str = "highlight.css";
+
+const STYLESHEET: &str = "\
+.highlight { background: #f6f8fa; color: #24292e; overflow-x: auto; padding: 0.8em; }
+.highlight .hl-comment { color: #6a737d; font-style: italic; }
+.highlight .hl-string { color: #032f62; }
+.highlight .hl-number, .highlight .hl-literal { color: #005cc5; }
+.highlight .hl-keyword, .highlight .hl-tag { color: #d73a49; }
+.highlight .hl-type { color: #6f42c1; }
+.highlight .hl-function, .highlight .hl-attr { color: #6f42c1; }
+.highlight .hl-lineno { color: #959da5; display: inline-block; margin-right: 1em; text-align: right; user-select: none; }
+@media (prefers-color-scheme: dark) {
+	.highlight { background: #1f2428; color: #e1e4e8; }
+	.highlight .hl-comment { color: #959da5; }
+	.highlight .hl-string { color: #9ecbff; }
+	.highlight .hl-number, .highlight .hl-literal { color: #79b8ff; }
+	.highlight .hl-keyword, .highlight .hl-tag { color: #f97583; }
+	.highlight .hl-type, .highlight .hl-function, .highlight .hl-attr { color: #b392f0; }
+	.highlight .hl-lineno { color: #6a737d; }
+}
+";
+
+struct Language {
+	names: &'static [&'static str],
+	keywords: &'static [&'static str],
+	literals: &'static [&'static str],
+	line_comments: &'static [&'static str],
+	block_comment: Option<(&'static str, &'static str)>,
+	string_delimiters: &'static [char],
+	// Only treat ' as a string delimiter when it looks like a character
+	// literal, so that Rust lifetimes are left alone.
+	char_literals: bool,
+	// Capitalized identifiers are types by convention.
+	capitalized_types: bool,
+	case_insensitive: bool,
+}
+
+const C_KEYWORDS: &[&str] = &[
+	"auto",
+	"break",
+	"case",
+	"char",
+	"const",
+	"continue",
+	"default",
+	"do",
+	"double",
+	"else",
+	"enum",
+	"extern",
+	"float",
+	"for",
+	"goto",
+	"if",
+	"inline",
+	"int",
+	"long",
+	"register",
+	"return",
+	"short",
+	"signed",
+	"sizeof",
+	"static",
+	"struct",
+	"switch",
+	"typedef",
+	"union",
+	"unsigned",
+	"void",
+	"volatile",
+	"while",
+	"class",
+	"namespace",
+	"template",
+	"typename",
+	"public",
+	"private",
+	"protected",
+	"virtual",
+	"override",
+	"new",
+	"delete",
+	"using",
+	"constexpr",
+	"bool",
+	"#include",
+	"#define",
+	"#if",
+	"#ifdef",
+	"#ifndef",
+	"#endif",
+	"#else",
+	"#pragma",
+];
+
+const LANGUAGES: &[Language] = &[
+	Language {
+		names: &["rust", "rs"],
+		keywords: &[
+			"as", "async", "await", "break", "const", "continue", "crate",
+			"dyn", "else", "enum", "extern", "fn", "for", "if", "impl", "in",
+			"let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+			"return", "self", "Self", "static", "struct", "super", "trait",
+			"type", "unsafe", "use", "where", "while",
+		],
+		literals: &["true", "false", "None", "Some", "Ok", "Err"],
+		line_comments: &["//"],
+		block_comment: Some(("/*", "*/")),
+		string_delimiters: &['"'],
+		char_literals: true,
+		capitalized_types: true,
+		case_insensitive: false,
+	},
+	Language {
+		names: &["c", "h", "cpp", "c++", "cc", "hpp"],
+		keywords: C_KEYWORDS,
+		literals: &["true", "false", "NULL", "nullptr"],
+		line_comments: &["//"],
+		block_comment: Some(("/*", "*/")),
+		string_delimiters: &['"', '\''],
+		char_literals: false,
+		capitalized_types: true,
+		case_insensitive: false,
+	},
+	Language {
+		names: &["java", "kotlin", "cs", "csharp", "swift"],
+		keywords: &[
+			"abstract",
+			"break",
+			"case",
+			"catch",
+			"class",
+			"continue",
+			"default",
+			"do",
+			"else",
+			"enum",
+			"extends",
+			"final",
+			"finally",
+			"for",
+			"fun",
+			"func",
+			"if",
+			"implements",
+			"import",
+			"interface",
+			"let",
+			"namespace",
+			"new",
+			"override",
+			"package",
+			"private",
+			"protected",
+			"public",
+			"return",
+			"static",
+			"struct",
+			"super",
+			"switch",
+			"this",
+			"throw",
+			"throws",
+			"try",
+			"val",
+			"var",
+			"void",
+			"while",
+			"using",
+			"int",
+			"long",
+			"boolean",
+			"bool",
+			"char",
+			"double",
+			"float",
+			"string",
+		],
+		literals: &["true", "false", "null", "nil"],
+		line_comments: &["//"],
+		block_comment: Some(("/*", "*/")),
+		string_delimiters: &['"', '\''],
+		char_literals: false,
+		capitalized_types: true,
+		case_insensitive: false,
+	},
+	Language {
+		names: &["javascript", "js", "typescript", "ts", "jsx", "tsx"],
+		keywords: &[
+			"async",
+			"await",
+			"break",
+			"case",
+			"catch",
+			"class",
+			"const",
+			"continue",
+			"default",
+			"delete",
+			"do",
+			"else",
+			"export",
+			"extends",
+			"finally",
+			"for",
+			"from",
+			"function",
+			"if",
+			"import",
+			"in",
+			"instanceof",
+			"interface",
+			"let",
+			"new",
+			"of",
+			"return",
+			"static",
+			"super",
+			"switch",
+			"this",
+			"throw",
+			"try",
+			"type",
+			"typeof",
+			"var",
+			"void",
+			"while",
+			"yield",
+		],
+		literals: &["true", "false", "null", "undefined", "NaN"],
+		line_comments: &["//"],
+		block_comment: Some(("/*", "*/")),
+		string_delimiters: &['"', '\'', '`'],
+		char_literals: false,
+		capitalized_types: true,
+		case_insensitive: false,
+	},
+	Language {
+		names: &["go", "golang"],
+		keywords: &[
+			"break",
+			"case",
+			"chan",
+			"const",
+			"continue",
+			"default",
+			"defer",
+			"else",
+			"fallthrough",
+			"for",
+			"func",
+			"go",
+			"goto",
+			"if",
+			"import",
+			"interface",
+			"map",
+			"package",
+			"range",
+			"return",
+			"select",
+			"struct",
+			"switch",
+			"type",
+			"var",
+		],
+		literals: &["true", "false", "nil", "iota"],
+		line_comments: &["//"],
+		block_comment: Some(("/*", "*/")),
+		string_delimiters: &['"', '\'', '`'],
+		char_literals: false,
+		capitalized_types: false,
+		case_insensitive: false,
+	},
+	Language {
+		names: &["python", "py"],
+		keywords: &[
+			"and", "as", "assert", "async", "await", "break", "class",
+			"continue", "def", "del", "elif", "else", "except", "finally",
+			"for", "from", "global", "if", "import", "in", "is", "lambda",
+			"nonlocal", "not", "or", "pass", "raise", "return", "try", "while",
+			"with", "yield",
+		],
+		literals: &["True", "False", "None"],
+		line_comments: &["#"],
+		block_comment: None,
+		string_delimiters: &['"', '\''],
+		char_literals: false,
+		capitalized_types: true,
+		case_insensitive: false,
+	},
+	Language {
+		names: &["ruby", "rb"],
+		keywords: &[
+			"begin", "class", "def", "do", "else", "elsif", "end", "ensure",
+			"for", "if", "in", "module", "next", "require", "rescue", "return",
+			"self", "then", "unless", "until", "when", "while", "yield",
+		],
+		literals: &["true", "false", "nil"],
+		line_comments: &["#"],
+		block_comment: None,
+		string_delimiters: &['"', '\''],
+		char_literals: false,
+		capitalized_types: true,
+		case_insensitive: false,
+	},
+	Language {
+		names: &["shell", "sh", "bash", "zsh", "console"],
+		keywords: &[
+			"case", "do", "done", "elif", "else", "esac", "export", "fi",
+			"for", "function", "if", "in", "local", "return", "then", "until",
+			"while",
+		],
+		literals: &["true", "false"],
+		line_comments: &["#"],
+		block_comment: None,
+		string_delimiters: &['"', '\''],
+		char_literals: false,
+		capitalized_types: false,
+		case_insensitive: false,
+	},
+	Language {
+		names: &["json"],
+		keywords: &[],
+		literals: &["true", "false", "null"],
+		line_comments: &[],
+		block_comment: None,
+		string_delimiters: &['"'],
+		char_literals: false,
+		capitalized_types: false,
+		case_insensitive: false,
+	},
+	Language {
+		names: &["toml", "yaml", "yml", "ini"],
+		keywords: &[],
+		literals: &["true", "false", "null", "yes", "no"],
+		line_comments: &["#"],
+		block_comment: None,
+		string_delimiters: &['"', '\''],
+		char_literals: false,
+		capitalized_types: false,
+		case_insensitive: false,
+	},
+	Language {
+		names: &["css", "scss"],
+		keywords: &[
+			"@media",
+			"@import",
+			"@font-face",
+			"@keyframes",
+			"!important",
+		],
+		literals: &[],
+		line_comments: &[],
+		block_comment: Some(("/*", "*/")),
+		string_delimiters: &['"', '\''],
+		char_literals: false,
+		capitalized_types: false,
+		case_insensitive: false,
+	},
+	Language {
+		names: &["sql"],
+		keywords: &[
+			"select",
+			"from",
+			"where",
+			"insert",
+			"into",
+			"values",
+			"update",
+			"set",
+			"delete",
+			"create",
+			"table",
+			"drop",
+			"alter",
+			"index",
+			"join",
+			"left",
+			"right",
+			"inner",
+			"outer",
+			"on",
+			"group",
+			"by",
+			"order",
+			"having",
+			"limit",
+			"and",
+			"or",
+			"not",
+			"as",
+			"primary",
+			"key",
+			"foreign",
+			"references",
+			"distinct",
+			"union",
+		],
+		literals: &["null", "true", "false"],
+		line_comments: &["--"],
+		block_comment: Some(("/*", "*/")),
+		string_delimiters: &['\'', '"'],
+		char_literals: false,
+		capitalized_types: false,
+		case_insensitive: true,
+	},
+];
+
+const MARKUP_NAMES: &[&str] = &["html", "xml", "svg"];
+
+pub fn is_supported(language: &str) -> bool {
+	find_language(language).is_some() || is_markup(language)
+}
+
+// Returns the code as a complete <pre> element, falling back to escaping only
+// for unsupported languages.
+pub fn highlight(code: &str, language: &str, line_numbers: bool) -> String {
+	let code = code.strip_suffix('\n').unwrap_or(code);
+	let highlighted = if let Some(definition) = find_language(language) {
+		highlight_code(code, definition)
+	} else if is_markup(language) {
+		highlight_markup(code)
+	} else {
+		escape_html(code)
+	};
+
+	let mut result = String::from("<pre class=\"highlight\"><code");
+	if !language.is_empty() {
+		result.push_str(&format!(
+			" class=\"language-{}\"",
+			escape_html(language)
+		));
+	}
+	result.push('>');
+	if line_numbers {
+		let lines = highlighted.split('\n').collect::<Vec<_>>();
+		let width = lines.len().to_string().len();
+		for (index, line) in lines.iter().enumerate() {
+			result.push_str(&format!(
+				"<span class=\"hl-lineno\">{:>width$}</span>{}\n",
+				index + 1,
+				line,
+				width = width
+			));
+		}
+	} else {
+		result.push_str(&highlighted);
+		result.push('\n');
+	}
+	result.push_str("</code></pre>\n");
+	result
+}
+
+// Replaces fenced code blocks of supported languages with highlighted HTML,
+// raising the highlighted flag if there were any. Adding "linenos" after the
+// language in the info string turns on line numbers.
+pub fn highlight_code_blocks<'a>(
+	events: Vec<Event<'a>>,
+	highlighted: &AtomicBool,
+) -> Vec<Event<'a>> {
+	let mut result = Vec::with_capacity(events.len());
+	let mut current: Option<(CowStr, String)> = None;
+	for event in events {
+		match event {
+			Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info)))
+				if is_supported(
+					info.split_whitespace().next().unwrap_or(""),
+				) =>
+			{
+				current = Some((info, String::new()))
+			}
+			Event::End(Tag::CodeBlock(_)) if current.is_some() => {
+				if let Some((info, code)) = current.take() {
+					let mut words = info.split_whitespace();
+					let language = words.next().unwrap_or("");
+					let line_numbers = words.any(|w| w == "linenos");
+					highlighted.store(true, Ordering::Relaxed);
+					result.push(Event::Html(CowStr::from(highlight(
+						&code,
+						language,
+						line_numbers,
+					))));
+				}
+			}
+			Event::Text(text) if current.is_some() => {
+				if let Some((_, code)) = &mut current {
+					code.push_str(&text)
+				}
+			}
+			_ => result.push(event),
+		}
+	}
+	result
+}
+
+// Only called once something has been highlighted, so that sites without code
+// don't get the stylesheet.
+pub fn write_stylesheet(input_dir: &Path, output_dir: &Path) {
+	// Sites can provide their own version.
+	if input_dir.join(STYLESHEET_PATH).exists() {
+		return;
+	}
+
+	let path = output_dir.join(STYLESHEET_PATH);
+	fs::write(&path, STYLESHEET).unwrap_or_else(|e| {
+		panic!("Failed writing \"{}\": {}", path.display(), e)
+	});
+	println!("Wrote {}.", path.display());
+}
+
+fn find_language(language: &str) -> Option<&'static Language> {
+	let language = language.to_lowercase();
+	LANGUAGES
+		.iter()
+		.find(|definition| definition.names.contains(&language.as_str()))
+}
+
+fn is_markup(language: &str) -> bool {
+	MARKUP_NAMES.contains(&language.to_lowercase().as_str())
+}
+
+// Wraps each line of the token separately so that spans never cross line
+// breaks, which keeps line numbering simple.
+fn push_token(output: &mut String, class: &str, text: &str) {
+	let mut first = true;
+	for line in text.split('\n') {
+		if !first {
+			output.push('\n');
+		}
+		first = false;
+		if !line.is_empty() {
+			output.push_str(&format!(
+				"<span class=\"hl-{}\">{}</span>",
+				class,
+				escape_html(line)
+			));
+		}
+	}
+}
+
+fn highlight_code(code: &str, language: &Language) -> String {
+	let chars = code.char_indices().collect::<Vec<_>>();
+	let byte_at = |i: usize| chars.get(i).map_or(code.len(), |(b, _)| *b);
+	let is_identifier_char = |c: char| c.is_alphanumeric() || c == '_';
+
+	let mut output = String::with_capacity(code.len() * 2);
+	let mut i = 0;
+	while i < chars.len() {
+		let (start, c) = chars[i];
+		let rest = &code[start..];
+
+		if let Some((open, close)) = language.block_comment {
+			if let Some(comment) = rest.strip_prefix(open) {
+				let end = comment.find(close).map_or(code.len(), |e| {
+					start + open.len() + e + close.len()
+				});
+				push_token(&mut output, "comment", &code[start..end]);
+				while i < chars.len() && chars[i].0 < end {
+					i += 1;
+				}
+				continue;
+			}
+		}
+
+		if language.line_comments.iter().any(|l| rest.starts_with(l)) {
+			let end = rest.find('\n').map_or(code.len(), |e| start + e);
+			push_token(&mut output, "comment", &code[start..end]);
+			while i < chars.len() && chars[i].0 < end {
+				i += 1;
+			}
+			continue;
+		}
+
+		let is_string_start = language.string_delimiters.contains(&c)
+			|| (language.char_literals && c == '\'' && is_char_literal(rest));
+		if is_string_start {
+			let mut j = i + 1;
+			while j < chars.len() {
+				let (_, d) = chars[j];
+				if d == '\\' {
+					j += 2;
+					continue;
+				}
+				j += 1;
+				if d == c {
+					break;
+				}
+			}
+			let j = j.min(chars.len());
+			push_token(&mut output, "string", &code[start..byte_at(j)]);
+			i = j;
+			continue;
+		}
+
+		let previous_is_identifier =
+			i > 0 && is_identifier_char(chars[i - 1].1);
+		if c.is_ascii_digit() && !previous_is_identifier {
+			let mut j = i + 1;
+			while j < chars.len()
+				&& (chars[j].1.is_alphanumeric()
+					|| chars[j].1 == '_'
+					|| (chars[j].1 == '.'
+						&& chars
+							.get(j + 1)
+							.is_some_and(|(_, n)| n.is_ascii_digit())))
+			{
+				j += 1;
+			}
+			push_token(&mut output, "number", &code[start..byte_at(j)]);
+			i = j;
+			continue;
+		}
+
+		// Keywords such as #include and @media start with punctuation.
+		if (c.is_alphabetic() || c == '_' || c == '#' || c == '@' || c == '!')
+			&& !previous_is_identifier
+		{
+			let mut j = i + 1;
+			while j < chars.len()
+				&& (is_identifier_char(chars[j].1)
+					|| (c == '@' && chars[j].1 == '-'))
+			{
+				j += 1;
+			}
+			let word = &code[start..byte_at(j)];
+			let matches = |list: &[&str]| {
+				if language.case_insensitive {
+					list.iter().any(|k| k.eq_ignore_ascii_case(word))
+				} else {
+					list.contains(&word)
+				}
+			};
+			if matches(language.keywords) {
+				push_token(&mut output, "keyword", word);
+			} else if !(c.is_alphabetic() || c == '_') {
+				output.push(c);
+				i += 1;
+				continue;
+			} else if matches(language.literals) {
+				push_token(&mut output, "literal", word);
+			} else if language.capitalized_types
+				&& word.starts_with(|f: char| f.is_uppercase())
+			{
+				push_token(&mut output, "type", word);
+			} else if code[byte_at(j)..].starts_with('(') {
+				push_token(&mut output, "function", word);
+			} else {
+				output.push_str(&escape_html(word));
+			}
+			i = j;
+			continue;
+		}
+
+		output.push_str(&escape_html(&c.to_string()));
+		i += 1;
+	}
+	output
+}
+
+fn is_char_literal(rest: &str) -> bool {
+	let mut chars = rest.chars().skip(1);
+	match chars.next() {
+		Some('\\') => rest.chars().skip(2).take(10).any(|c| c == '\''),
+		Some(_) => chars.next() == Some('\''),
+		None => false,
+	}
+}
+
+fn highlight_markup(code: &str) -> String {
+	let mut output = String::with_capacity(code.len() * 2);
+	let mut rest = code;
+	while !rest.is_empty() {
+		if rest.starts_with("<!--") {
+			let end = rest.find("-->").map_or(rest.len(), |e| e + 3);
+			push_token(&mut output, "comment", &rest[..end]);
+			rest = &rest[end..];
+		} else if rest.starts_with('<') {
+			let end = rest.find('>').map_or(rest.len(), |e| e + 1);
+			highlight_tag(&rest[..end], &mut output);
+			rest = &rest[end..];
+		} else {
+			let end = rest.find('<').unwrap_or(rest.len());
+			output.push_str(&escape_html(&rest[..end]));
+			rest = &rest[end..];
+		}
+	}
+	output
+}
+
+fn highlight_tag(tag: &str, output: &mut String) {
+	// Covers closing tags as well as <!DOCTYPE> and <?xml?>.
+	let prefix_end = if tag[1..].starts_with(&['/', '!', '?'][..]) {
+		2
+	} else {
+		1
+	};
+	let name_end = tag[prefix_end..]
+		.find(|c: char| !(c.is_alphanumeric() || c == '-' || c == ':'))
+		.map_or(tag.len(), |e| e + prefix_end);
+	push_token(output, "tag", &tag[..name_end]);
+
+	let mut rest = &tag[name_end..];
+	while !rest.is_empty() {
+		let c = rest.chars().next().unwrap_or(' ');
+		if c == '"' || c == '\'' {
+			let end = rest[1..].find(c).map_or(rest.len(), |e| e + 2);
+			push_token(output, "string", &rest[..end]);
+			rest = &rest[end..];
+		} else if c.is_alphabetic() {
+			let end = rest
+				.find(|c: char| !(c.is_alphanumeric() || c == '-' || c == ':'))
+				.unwrap_or(rest.len());
+			push_token(output, "attr", &rest[..end]);
+			rest = &rest[end..];
+		} else if c == '>' || c == '/' {
+			push_token(output, "tag", rest);
+			rest = "";
+		} else {
+			output.push_str(&escape_html(&c.to_string()));
+			rest = &rest[c.len_utf8()..];
+		}
+	}
+}
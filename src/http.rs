@@ -1,12 +1,14 @@
 use std::fs;
 use std::io::{ErrorKind, Read};
 use std::net::{TcpListener, TcpStream};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::sync::Condvar;
 use std::sync::Mutex;
 use std::thread;
 
+use crate::feed;
+use crate::precompress;
 use crate::util;
 use crate::util::{write_to_stream_log_count, Refresh};
 use crate::websocket;
@@ -62,7 +64,8 @@ pub fn spawn_listening_thread(
 }
 
 enum ReadResult {
-	GetRequest(PathBuf),
+	// Path and the value of the Accept-Encoding header.
+	GetRequest(PathBuf, String),
 	WebSocket(String),
 }
 
@@ -75,9 +78,10 @@ fn handle_client(
 	let sockets_enabled = fs_cond.is_some();
 	if let Some(result) = handle_read(&mut stream) {
 		match result {
-			ReadResult::GetRequest(path) => handle_write(
+			ReadResult::GetRequest(path, accept_encoding) => handle_write(
 				stream,
 				&path,
+				&accept_encoding,
 				root_dir,
 				start_file,
 				sockets_enabled,
@@ -134,7 +138,14 @@ fn handle_read(stream: &mut TcpStream) -> Option<ReadResult> {
 		.unwrap_or_else(|| panic!("Missing path in: {}", first_line));
 
 	let mut websocket_key = None;
+	let mut accept_encoding = "";
 	for line in lines {
+		if let Some((name, value)) = line.split_once(':') {
+			if name.eq_ignore_ascii_case("Accept-Encoding") {
+				accept_encoding = value.trim();
+			}
+		}
+
 		let mut components = line.split(' ');
 		if let Some(component) = components.next() {
 			if component == "Sec-WebSocket-Key:" {
@@ -156,10 +167,11 @@ fn handle_read(stream: &mut TcpStream) -> Option<ReadResult> {
 			path
 		)
 	}
-	Some(ReadResult::GetRequest(PathBuf::from(
+	Some(ReadResult::GetRequest(
 		// Strip leading root slash.
-		&path[1..],
-	)))
+		PathBuf::from(&path[1..]),
+		accept_encoding.to_string(),
+	))
 }
 
 const DEV_PAGE_HEADER: &[u8; 1244] = b"HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=UTF-8\r\n\r\n\
@@ -216,8 +228,9 @@ const DEV_PAGE_FOOTER: &[u8; 17] = b"</body>\n</html>\r\n";
 
 fn handle_write(
 	mut stream: TcpStream,
-	path: &PathBuf,
-	root_dir: &PathBuf,
+	path: &Path,
+	accept_encoding: &str,
+	root_dir: &Path,
 	start_file: Option<PathBuf>,
 	sockets_enabled: bool,
 ) {
@@ -269,16 +282,25 @@ fn handle_write(
 		return;
 	}
 
-	let mut full_path = root_dir.join(path);
-	if !full_path.is_file() {
-		let with_index = full_path.join("index.html");
-		if with_index.is_file() {
-			full_path = with_index;
+	let (full_path, status) = resolve_path(path, root_dir);
+
+	// Precompressed variants are only served while up to date, as the output
+	// may change before they are rewritten.
+	let compressible = precompress::is_compressible(&full_path);
+	let encoding = if compressible {
+		find_encoding(&full_path, accept_encoding)
+	} else {
+		None
+	};
+	let served_path = match encoding {
+		Some((_, extension)) => {
+			precompress::sibling_path(&full_path, extension)
 		}
-	}
+		None => full_path.clone(),
+	};
 
-	println!("Attempting to open: {}", full_path.display());
-	let mut input_file = match fs::File::open(&full_path) {
+	println!("Attempting to open: {}", served_path.display());
+	let mut input_file = match fs::File::open(&served_path) {
 		Ok(input) => input,
 		Err(e) => {
 			match e.kind() {
@@ -308,8 +330,14 @@ fn handle_write(
 			.any(|&ext| ext == extension)
 		{
 			String::from("text/plain")
+		} else if extension == util::JS_EXTENSION {
+			String::from("text/javascript")
+		} else if extension == util::JSON_EXTENSION {
+			String::from("application/json")
 		} else if IMAGE_OUTPUT_EXTENSIONS.iter().any(|&ext| ext == extension) {
 			format!("image/{}", extension)
+		} else if let Some(mime_type) = feed::audio_mime_type(&extension) {
+			String::from(mime_type)
 		} else {
 			let message =
 				format!("Unrecognized extension: {}", full_path.display());
@@ -320,18 +348,22 @@ fn handle_write(
 			);
 			return;
 		};
-		write_to_stream_log_count(
-			format!(
-				"HTTP/1.1 200 OK\r\nContent-Type: {}; charset=UTF-8\r\n\r\n",
-				content_type
-			)
-			.as_bytes(),
-			&mut stream,
+		let mut headers = format!(
+			"HTTP/1.1 {}\r\nContent-Type: {}; charset=UTF-8\r\n",
+			status, content_type
 		);
+		if let Some((encoding, _)) = encoding {
+			headers.push_str(&format!("Content-Encoding: {}\r\n", encoding));
+		}
+		if compressible {
+			headers.push_str("Vary: Accept-Encoding\r\n");
+		}
+		headers.push_str("\r\n");
+		write_to_stream_log_count(headers.as_bytes(), &mut stream);
 		let mut buf = [0_u8; 64 * 1024];
 		loop {
 			let size = input_file.read(&mut buf).unwrap_or_else(|e| {
-				panic!("Failed reading from {}: {}", full_path.display(), e);
+				panic!("Failed reading from {}: {}", served_path.display(), e);
 			});
 			if size < 1 {
 				break;
@@ -348,3 +380,63 @@ fn handle_write(
 		)
 	}
 }
+
+// Returns the file served for the requested path along with the status.
+pub fn resolve_path(path: &Path, root_dir: &Path) -> (PathBuf, &'static str) {
+	let mut full_path = root_dir.join(path);
+	if !full_path.is_file() {
+		let with_index = full_path.join("index.html");
+		if with_index.is_file() {
+			full_path = with_index;
+		}
+	}
+
+	// Missing files get the 404 page of the site if it has one, as on hosts.
+	let not_found_page = root_dir.join(util::NOT_FOUND_PAGE);
+	if !full_path.is_file() && not_found_page.is_file() {
+		println!(
+			"Couldn't find {}, serving {}.",
+			full_path.display(),
+			not_found_page.display()
+		);
+		(not_found_page, "404 Not Found")
+	} else {
+		(full_path, "200 OK")
+	}
+}
+
+// The preferred encoding accepted by the client which has an up to date
+// precompressed variant of the file, along with the extension of the variant.
+fn find_encoding(
+	path: &Path,
+	accept_encoding: &str,
+) -> Option<(&'static str, &'static str)> {
+	let modified = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+	precompress::ENCODINGS
+		.iter()
+		.copied()
+		.filter(|(encoding, _)| accepts_encoding(accept_encoding, encoding))
+		.find(|(_, extension)| {
+			fs::metadata(precompress::sibling_path(path, extension))
+				.and_then(|m| m.modified())
+				.is_ok_and(|variant_modified| variant_modified >= modified)
+		})
+}
+
+// Whether the Accept-Encoding header value, such as "gzip, br;q=0.5", lists
+// the encoding with a non-zero quality.
+pub fn accepts_encoding(accept_encoding: &str, encoding: &str) -> bool {
+	accept_encoding.split(',').any(|item| {
+		let mut parameters = item.split(';');
+		let name = parameters.next().unwrap_or("").trim();
+		(name.eq_ignore_ascii_case(encoding) || name == "*")
+			&& parameters.all(|parameter| {
+				match parameter.trim().split_once('=') {
+					Some((key, value)) if key.trim() == "q" => {
+						value.trim().parse::<f32>().map_or(true, |q| q > 0.0)
+					}
+					_ => true,
+				}
+			})
+	})
+}
@@ -0,0 +1,301 @@
+// Images in markdown and through the image filter get their width and height,
+// avoiding layout shifts while loading, and are loaded lazily. PNG and JPEG
+// images wider than the configured image widths get resized variants named
+// like cat-480w.jpg next to them, listed in srcset so that browsers can pick
+// the smallest one that suffices.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ::image::codecs::jpeg::JpegEncoder;
+use ::image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use ::image::imageops;
+use ::image::metadata::Orientation;
+use ::image::{
+	DynamicImage, ImageDecoder, ImageEncoder, ImageFormat, ImageReader,
+	ImageResult,
+};
+use pulldown_cmark::{CowStr, Event, Tag};
+
+use crate::links;
+use crate::liquid;
+use crate::util::{escape_html, strip_prefix, translate_input_to_output};
+
+const JPEG_QUALITY: u8 = 82;
+
+struct Info {
+	width: u32,
+	height: u32,
+	resizable: bool,
+}
+
+// Reads the dimensions as displayed, turned upright according to the Exif
+// orientation, from the header of the image.
+fn read_info(path: &Path) -> Option<Info> {
+	let (format, mut decoder) = open(path).ok()?;
+	let (width, height) = decoder.dimensions();
+	let (width, height) = match decoder.orientation().ok()? {
+		Orientation::Rotate90
+		| Orientation::Rotate270
+		| Orientation::Rotate90FlipH
+		| Orientation::Rotate270FlipH => (height, width),
+		_ => (width, height),
+	};
+	Some(Info {
+		width,
+		height,
+		// Animations would be lost when resizing.
+		resizable: format != Some(ImageFormat::Gif),
+	})
+}
+
+// Only reads as far as needed for the format, which is known once the decoder
+// could be created.
+fn open(path: &Path) -> ImageResult<(Option<ImageFormat>, impl ImageDecoder)> {
+	let reader = ImageReader::open(path)?.with_guessed_format()?;
+	Ok((reader.format(), reader.into_decoder()?))
+}
+
+fn variant_widths(info: &Info, image_widths: &[u32]) -> Vec<u32> {
+	if !info.resizable {
+		return Vec::new();
+	}
+	let mut widths = image_widths
+		.iter()
+		.copied()
+		.filter(|&width| width > 0 && width < info.width)
+		.collect::<Vec<_>>();
+	widths.sort_unstable();
+	widths.dedup();
+	widths
+}
+
+// Inserts -<width>w before the extension of the file name.
+fn variant_name(name: &str, width: u32) -> String {
+	let file_start = name.rfind('/').map_or(0, |slash| slash + 1);
+	match name[file_start..].rfind('.') {
+		Some(dot) if dot > 0 => format!(
+			"{}-{}w{}",
+			&name[..file_start + dot],
+			width,
+			&name[file_start + dot..]
+		),
+		_ => format!("{}-{}w", name, width),
+	}
+}
+
+pub fn write_variants(
+	input_file_path: &Path,
+	input_dir: &Path,
+	output_dir: &Path,
+	image_widths: &[u32],
+) {
+	let info = match read_info(input_file_path) {
+		Some(info) => info,
+		None => return,
+	};
+	let widths = variant_widths(&info, image_widths);
+	if widths.is_empty() {
+		return;
+	}
+
+	let output_file_path =
+		translate_input_to_output(input_file_path, input_dir, output_dir);
+	if let Some(parent) = output_file_path.parent() {
+		fs::create_dir_all(parent).unwrap_or_else(|e| {
+			panic!("Failed creating directories in {}: {}", parent.display(), e)
+		});
+	}
+	let variants = widths
+		.into_iter()
+		.map(|width| {
+			let name = variant_name(&output_file_path.to_string_lossy(), width);
+			(width, PathBuf::from(name))
+		})
+		.collect::<Vec<_>>();
+	if let Err(e) = resize(input_file_path, &variants) {
+		println!(
+			"WARNING: Not generating resized variants of {}: {}",
+			input_file_path.display(),
+			e
+		);
+		// Pages only offer the variants found next to the image, which
+		// could be left from an earlier version of it.
+		for (_, path) in &variants {
+			if path.exists() {
+				fs::remove_file(path).unwrap_or_else(|e| {
+					panic!("Failed removing {}: {}", path.display(), e)
+				});
+			}
+		}
+	}
+}
+
+// Writes the image resized to each of the widths, turned upright according
+// to its Exif orientation and keeping its color profile.
+fn resize(
+	input_file_path: &Path,
+	variants: &[(u32, PathBuf)],
+) -> ImageResult<()> {
+	let (format, mut decoder) = open(input_file_path)?;
+	let orientation = decoder.orientation()?;
+	let icc_profile = decoder.icc_profile()?;
+	let mut image = DynamicImage::from_decoder(decoder)?;
+	image.apply_orientation(orientation);
+
+	for (width, variant_path) in variants {
+		let height = (image.height() as u64 * *width as u64
+			+ image.width() as u64 / 2)
+			/ image.width() as u64;
+		let resized = image.resize_exact(
+			*width,
+			height.max(1) as u32,
+			imageops::FilterType::Lanczos3,
+		);
+		let mut encoded = Vec::new();
+		if format == Some(ImageFormat::Jpeg) {
+			encode(
+				&resized,
+				JpegEncoder::new_with_quality(&mut encoded, JPEG_QUALITY),
+				&icc_profile,
+			)?;
+		} else {
+			encode(
+				&resized,
+				PngEncoder::new_with_quality(
+					&mut encoded,
+					CompressionType::Best,
+					FilterType::Adaptive,
+				),
+				&icc_profile,
+			)?;
+		}
+		fs::write(variant_path, encoded).unwrap_or_else(|e| {
+			panic!("Failed writing {}: {}", variant_path.display(), e)
+		});
+	}
+	Ok(())
+}
+
+fn encode(
+	image: &DynamicImage,
+	mut encoder: impl ImageEncoder,
+	icc_profile: &Option<Vec<u8>>,
+) -> ImageResult<()> {
+	if let Some(icc_profile) = icc_profile {
+		// Both encoders support color profiles.
+		encoder.set_icc_profile(icc_profile.clone()).ok();
+	}
+	image.write_with_encoder(encoder)
+}
+
+pub fn convert_markdown_images<'a>(
+	events: Vec<Event<'a>>,
+	context: &liquid::Context,
+) -> Vec<Event<'a>> {
+	let mut result = Vec::with_capacity(events.len());
+	let mut image: Option<(CowStr, CowStr, String)> = None;
+	for event in events {
+		match (event, &mut image) {
+			(Event::Start(Tag::Image(_, src, title)), None) => {
+				image = Some((src, title, String::new()))
+			}
+			(Event::End(Tag::Image(..)), Some(_)) => {
+				if let Some((src, title, alt)) = image.take() {
+					result.push(Event::Html(CowStr::from(make_img_html(
+						&src, &alt, &title, context,
+					))));
+				}
+			}
+			(Event::Text(text), Some((_, _, alt)))
+			| (Event::Code(text), Some((_, _, alt))) => alt.push_str(&text),
+			// Alt text is plain, dropping any formatting.
+			(_, Some(_)) => {}
+			(event, None) => result.push(event),
+		}
+	}
+	result
+}
+
+pub fn make_img_html(
+	src: &str,
+	alt: &str,
+	title: &str,
+	context: &liquid::Context,
+) -> String {
+	let mut html = format!(
+		"<img src=\"{}\" alt=\"{}\"",
+		escape_html(src),
+		escape_html(alt)
+	);
+	if !title.is_empty() {
+		html.push_str(&format!(" title=\"{}\"", escape_html(title)));
+	}
+	let input = find_input_path(src, context)
+		.and_then(|path| read_info(&path).map(|info| (path, info)));
+	if let Some((input_path, info)) = input {
+		html.push_str(&format!(
+			" width=\"{}\" height=\"{}\"",
+			info.width, info.height
+		));
+		// Variants are written before pages, leaving out any that failed.
+		let output_file_path = translate_input_to_output(
+			&input_path,
+			context.root_input_dir,
+			context.root_output_dir,
+		);
+		let widths = variant_widths(&info, context.site_info.image_widths)
+			.into_iter()
+			.filter(|&width| {
+				Path::new(&variant_name(
+					&output_file_path.to_string_lossy(),
+					width,
+				))
+				.exists()
+			})
+			.collect::<Vec<_>>();
+		if !widths.is_empty() {
+			let candidates = widths
+				.iter()
+				.map(|&width| {
+					format!("{} {}w", variant_name(src, width), width)
+				})
+				.chain(std::iter::once(format!("{} {}w", src, info.width)))
+				.collect::<Vec<_>>();
+			html.push_str(&format!(
+				" srcset=\"{}\"",
+				escape_html(&candidates.join(", "))
+			));
+			let sizes = context.site_info.image_sizes;
+			if !sizes.is_empty() {
+				html.push_str(&format!(" sizes=\"{}\"", escape_html(sizes)));
+			}
+		}
+	}
+	html.push_str(" loading=\"lazy\" />");
+	html
+}
+
+// The input file of an image URL relative to the generated page, or None for
+// external images.
+fn find_input_path(src: &str, context: &liquid::Context) -> Option<PathBuf> {
+	if src.contains(':') || src.starts_with("//") {
+		return None;
+	}
+	let path = src.split(['?', '#']).next()?;
+	// Generated pages mirror the layout of the input directory, except for
+	// files under _static/ which are found under static/.
+	let page_path = context.root_input_dir.join(strip_prefix(
+		context.output_file_path,
+		context.root_output_dir,
+	));
+	let input_path =
+		links::resolve_input_path(path, &page_path, context.root_input_dir)?;
+	match strip_prefix(&input_path, context.root_input_dir)
+		.strip_prefix("static")
+	{
+		Ok(static_path) => {
+			Some(context.root_input_dir.join("_static").join(static_path))
+		}
+		Err(_) => Some(input_path),
+	}
+}
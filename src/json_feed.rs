@@ -0,0 +1,129 @@
+// JSON Feed 1.1 (https://jsonfeed.org/version/1.1), for consumers which would
+// rather not parse XML.
+use std::fs;
+use std::io::BufWriter;
+
+use crate::feed::{complete_url, FeedEntry, FeedHeader};
+use crate::util::{escape_json, write_to_stream};
+
+pub fn write_feed(
+	header: &FeedHeader,
+	entries: &[&FeedEntry],
+	mut output: &mut BufWriter<fs::File>,
+) {
+	write_to_stream(
+		format!(
+			"{{\n\
+			\t\"version\": \"https://jsonfeed.org/version/1.1\",\n\
+			\t\"title\": \"{}\",\n\
+			\t\"home_page_url\": \"{}\",\n\
+			\t\"feed_url\": \"{}\",\n",
+			escape_json(&header.title),
+			escape_json(&header.base_url),
+			escape_json(&header.feed_url),
+		)
+		.as_bytes(),
+		&mut output,
+	);
+
+	let mut author =
+		format!("\"name\": \"{}\"", escape_json(&header.author_name));
+	if !header.author_email.is_empty() {
+		author.push_str(&format!(
+			", \"url\": \"mailto:{}\"",
+			escape_json(&header.author_email)
+		));
+	}
+	write_to_stream(
+		format!("\t\"authors\": [{{ {} }}],\n\t\"items\": [", author)
+			.as_bytes(),
+		&mut output,
+	);
+
+	for (i, entry) in entries.iter().enumerate() {
+		if i > 0 {
+			write_to_stream(b",", &mut output);
+		}
+		generate_item(entry, header, output);
+	}
+
+	write_to_stream(b"\n\t]\n}\n", &mut output);
+}
+
+fn generate_item(
+	entry: &FeedEntry,
+	header: &FeedHeader,
+	mut output: &mut BufWriter<fs::File>,
+) {
+	let entry_url = escape_json(&complete_url(
+		&header.base_url,
+		&entry.permalink.to_string_lossy(),
+	));
+
+	write_to_stream(
+		format!(
+			"\n\
+			\t\t{{\n\
+			\t\t\t\"id\": \"{}\",\n\
+			\t\t\t\"url\": \"{}\",\n\
+			\t\t\t\"title\": \"{}\",\n",
+			entry_url,
+			entry_url,
+			escape_json(&entry.front_matter.title)
+		)
+		.as_bytes(),
+		&mut output,
+	);
+
+	if let Some(published_date) = &entry.front_matter.date {
+		write_to_stream(
+			format!(
+				"\t\t\t\"date_published\": \"{}\",\n",
+				escape_json(published_date)
+			)
+			.as_bytes(),
+			&mut output,
+		);
+	}
+	if let Some(updated_date) = &entry.front_matter.edited {
+		write_to_stream(
+			format!(
+				"\t\t\t\"date_modified\": \"{}\",\n",
+				escape_json(updated_date)
+			)
+			.as_bytes(),
+			&mut output,
+		);
+	}
+
+	if let Some(enclosure) = &entry.enclosure {
+		let duration = enclosure
+			.duration_seconds
+			.map_or_else(String::new, |duration| {
+				format!(", \"duration_in_seconds\": {}", duration)
+			});
+		write_to_stream(
+			format!(
+				"\t\t\t\"attachments\": [{{ \"url\": \"{}\", \"mime_type\": \"{}\", \"size_in_bytes\": {}{} }}],\n",
+				escape_json(&complete_url(
+					&header.base_url,
+					&enclosure.path.to_string_lossy()
+				)),
+				enclosure.mime_type,
+				enclosure.length,
+				duration
+			)
+			.as_bytes(),
+			&mut output,
+		);
+	}
+
+	write_to_stream(
+		format!(
+			"\t\t\t\"content_html\": \"{}\"\n\t\t}}",
+			escape_json(entry.summary.as_ref().unwrap_or(&entry.html_content))
+		)
+		.as_bytes(),
+		&mut output,
+	);
+}
@@ -0,0 +1,312 @@
+// Resolves links between markdown files at generation time: [[Page Title]],
+// [[path|label]] and [[Page Title#Section]] wiki links, and plain markdown
+// links to .md files, which are rewritten to the relative URL of the generated
+// page. Targets that don't exist fail the build.
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use pulldown_cmark::{CowStr, Event, LinkType, Tag};
+
+use crate::headings::slugify;
+use crate::liquid;
+use crate::markdown::GroupedOptionOutputFile;
+use crate::util::{strip_prefix, suggest_closest, MARKDOWN_EXTENSION};
+
+pub fn resolve<'a>(
+	events: Vec<Event<'a>>,
+	context: &liquid::Context,
+) -> Vec<Event<'a>> {
+	let mut result = Vec::with_capacity(events.len());
+	let mut text = String::new();
+	let mut in_code_block = false;
+	for event in events {
+		let event = match event {
+			Event::Text(t) if !in_code_block => {
+				text.push_str(&t);
+				continue;
+			}
+			Event::Start(Tag::Link(link_type, destination, title)) => {
+				Event::Start(Tag::Link(
+					link_type,
+					rewrite_destination(destination, context),
+					title,
+				))
+			}
+			Event::Start(Tag::CodeBlock(_)) => {
+				in_code_block = true;
+				event
+			}
+			Event::End(Tag::CodeBlock(_)) => {
+				in_code_block = false;
+				event
+			}
+			_ => event,
+		};
+		expand_wiki_links(&mut text, &mut result, context);
+		result.push(event);
+	}
+	expand_wiki_links(&mut text, &mut result, context);
+	result
+}
+
+// The markdown parser splits text up around brackets, so wiki links are
+// searched for in the joined text.
+fn expand_wiki_links<'a>(
+	text: &mut String,
+	result: &mut Vec<Event<'a>>,
+	context: &liquid::Context,
+) {
+	let mut rest = text.as_str();
+	while let Some(start) = rest.find("[[") {
+		let end = if let Some(end) = rest[start..].find("]]") {
+			start + end
+		} else {
+			break;
+		};
+		let inner = &rest[start + 2..end];
+		if inner.is_empty() || inner.contains('[') || inner.contains('\n') {
+			result
+				.push(Event::Text(CowStr::from(rest[..start + 2].to_string())));
+			rest = &rest[start + 2..];
+			continue;
+		}
+
+		if start > 0 {
+			result.push(Event::Text(CowStr::from(rest[..start].to_string())));
+		}
+		let (target, label) = match inner.find('|') {
+			Some(bar) => (inner[..bar].trim(), Some(inner[bar + 1..].trim())),
+			None => (inner.trim(), None),
+		};
+		let (url, title) = resolve_wiki_target(target, context);
+		let label = label.map_or(title, |label| label.to_string());
+		let link = Tag::Link(LinkType::Inline, CowStr::from(url), "".into());
+		result.push(Event::Start(link.clone()));
+		result.push(Event::Text(CowStr::from(label)));
+		result.push(Event::End(link));
+		rest = &rest[end + 2..];
+	}
+	if !rest.is_empty() {
+		result.push(Event::Text(CowStr::from(rest.to_string())));
+	}
+	text.clear();
+}
+
+// Returns the URL along with the default label.
+fn resolve_wiki_target(
+	target: &str,
+	context: &liquid::Context,
+) -> (String, String) {
+	let (target, fragment) = split_fragment(target);
+	let fragment = fragment.map(|f| format!("#{}", slugify(f)));
+
+	let mut input_paths = find_wiki_targets(
+		target,
+		context.input_file_path,
+		context.root_input_dir,
+		context.input_output_map,
+	);
+	let input_path = match input_paths.len() {
+		0 => panic_unresolved(target, context),
+		1 => input_paths.remove(0),
+		_ => panic!(
+			"Wiki link [[{}]] in {} is ambiguous, matching: {:?}. Use [[path|label]] instead.",
+			target,
+			context.input_file_path.display(),
+			input_paths
+		),
+	};
+
+	let linked = lookup(&input_path, context);
+	let title = match &linked.file.front_matter {
+		Some(front_matter) if !front_matter.title.is_empty() => {
+			front_matter.title.clone()
+		}
+		_ => target.to_string(),
+	};
+	let url = if target.is_empty() {
+		String::new()
+	} else {
+		make_url(&linked.file.path, context)
+	};
+	(url + fragment.as_deref().unwrap_or(""), title)
+}
+
+// Input paths a wiki link target without fragment may refer to, more than one
+// meaning that it's ambiguous.
+fn find_wiki_targets(
+	target: &str,
+	input_file_path: &Path,
+	root_input_dir: &Path,
+	input_output_map: &HashMap<PathBuf, GroupedOptionOutputFile>,
+) -> Vec<PathBuf> {
+	let is_path = target.contains('/')
+		|| Path::new(target).extension()
+			== Some(std::ffi::OsStr::new(MARKDOWN_EXTENSION));
+	if target.is_empty() {
+		vec![input_file_path.to_path_buf()]
+	} else if is_path {
+		let mut path = PathBuf::from(target);
+		if path.extension().is_none() {
+			path.set_extension(MARKDOWN_EXTENSION);
+		}
+		resolve_input_path(
+			&path.to_string_lossy(),
+			input_file_path,
+			root_input_dir,
+		)
+		.into_iter()
+		.collect()
+	} else {
+		find_by_title(target, input_output_map)
+	}
+}
+
+fn find_by_title(
+	title: &str,
+	input_output_map: &HashMap<PathBuf, GroupedOptionOutputFile>,
+) -> Vec<PathBuf> {
+	let mut matches = input_output_map
+		.iter()
+		.filter(|(path, linked)| {
+			let stem_matches = path.extension()
+				== Some(std::ffi::OsStr::new(MARKDOWN_EXTENSION))
+				&& path
+					.file_stem()
+					.is_some_and(|stem| stem.to_string_lossy() == title);
+			stem_matches
+				|| linked.file.front_matter.as_ref().is_some_and(
+					|front_matter| {
+						front_matter.title.eq_ignore_ascii_case(title)
+					},
+				)
+		})
+		.map(|(path, _)| path.clone())
+		.collect::<Vec<_>>();
+	matches.sort();
+	matches
+}
+
+fn rewrite_destination<'a>(
+	destination: CowStr<'a>,
+	context: &liquid::Context,
+) -> CowStr<'a> {
+	let (path, fragment) = split_fragment(&destination);
+	if destination.contains(':')
+		|| Path::new(path).extension()
+			!= Some(std::ffi::OsStr::new(MARKDOWN_EXTENSION))
+	{
+		return destination;
+	}
+
+	let input_path = resolve_input_path(
+		path,
+		context.input_file_path,
+		context.root_input_dir,
+	)
+	.unwrap_or_else(|| {
+		panic!(
+			"Link {} in {} points outside of the input directory.",
+			path,
+			context.input_file_path.display()
+		)
+	});
+	let linked = lookup(&input_path, context);
+	let mut url = make_url(&linked.file.path, context);
+	if let Some(fragment) = fragment {
+		url.push('#');
+		url.push_str(fragment);
+	}
+	CowStr::from(url)
+}
+
+fn split_fragment(target: &str) -> (&str, Option<&str>) {
+	match target.find('#') {
+		Some(hash) => (&target[..hash], Some(&target[hash + 1..])),
+		None => (target, None),
+	}
+}
+
+fn make_url(linked_output_path: &PathBuf, context: &liquid::Context) -> String {
+	liquid::make_relative_link(
+		context.output_file_path,
+		linked_output_path,
+		context.root_output_dir,
+		context.site_info.base_url,
+	)
+}
+
+// Paths starting with / are relative to the input directory, others to the
+// directory of the current file. Returns None for paths leading outside of the
+// input directory.
+pub fn resolve_input_path(
+	path: &str,
+	input_file_path: &Path,
+	root_input_dir: &Path,
+) -> Option<PathBuf> {
+	let relative = if let Some(stripped) = path.strip_prefix('/') {
+		PathBuf::from(stripped)
+	} else {
+		let own_dir = strip_prefix(
+			input_file_path.parent().unwrap_or_else(|| {
+				panic!("Failed getting parent of {}", input_file_path.display())
+			}),
+			root_input_dir,
+		);
+		own_dir.join(path)
+	};
+
+	let mut normalized = PathBuf::new();
+	for component in relative.components() {
+		match component {
+			Component::ParentDir => normalized.pop().then_some(())?,
+			Component::Normal(name) => normalized.push(name),
+			_ => {}
+		}
+	}
+	Some(root_input_dir.join(normalized))
+}
+
+fn lookup<'b>(
+	input_path: &Path,
+	context: &liquid::Context<'b>,
+) -> &'b GroupedOptionOutputFile {
+	let linked =
+		context.input_output_map.get(input_path).unwrap_or_else(|| {
+			panic_unresolved(
+				&format!(
+					"/{}",
+					strip_prefix(input_path, context.root_input_dir)
+						.to_string_lossy()
+				),
+				context,
+			)
+		});
+	context.site_info.links.record(input_path, context);
+	linked
+}
+
+fn panic_unresolved(target: &str, context: &liquid::Context) -> ! {
+	let candidates =
+		context.input_output_map.iter().flat_map(|(path, linked)| {
+			let path = format!(
+				"/{}",
+				strip_prefix(path, context.root_input_dir).to_string_lossy()
+			);
+			let title = linked
+				.file
+				.front_matter
+				.as_ref()
+				.map(|front_matter| front_matter.title.clone());
+			std::iter::once(path).chain(title)
+		});
+	let suggestion = suggest_closest(target, candidates)
+		.map(|s| format!(" Did you mean \"{}\"?", s))
+		.unwrap_or_default();
+	panic!(
+		"Failed resolving link to \"{}\" in {}.{}",
+		target,
+		context.input_file_path.display(),
+		suggestion
+	)
+}
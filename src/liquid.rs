@@ -5,17 +5,34 @@ use std::convert::TryInto;
 use std::ffi::OsStr;
 use std::fs;
 use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::slice;
+use std::sync::atomic::Ordering;
 
+use crate::assets;
+use crate::check;
+use crate::feed;
 use crate::front_matter::FrontMatter;
+use crate::headings;
+use crate::headings::Heading;
+use crate::highlight;
+use crate::image;
+use crate::markdown;
 use crate::markdown::{GroupedOptionOutputFile, InputFile};
-use crate::util::{strip_prefix, write_to_stream, SiteInfo};
+use crate::search;
+use crate::shortcode;
+use crate::util::{
+	capitalize, escape_html, escape_json, strip_prefix, suggest_closest,
+	write_to_stream, SiteInfo, HTML_EXTENSION, MARKDOWN_EXTENSION,
+	NOT_FOUND_PAGE,
+};
 
 pub struct Context<'a> {
 	pub input_file_path: &'a PathBuf,
 	pub output_file_path: &'a PathBuf,
 	pub front_matter: &'a FrontMatter,
 	pub html_content: Option<&'a str>,
+	pub headings: &'a [Heading],
 	pub root_input_dir: &'a PathBuf,
 	pub root_output_dir: &'a PathBuf,
 	pub input_output_map: &'a HashMap<PathBuf, GroupedOptionOutputFile>,
@@ -95,11 +112,25 @@ enum ControlFlow {
 		local_variables: HashMap<String, Value>,
 	},
 	Capture {
-		variable: String,
+		target: CaptureTarget,
 		content: Vec<u8>,
 	},
 }
 
+#[derive(Debug)]
+enum CaptureTarget {
+	Variable(String),
+	Highlight {
+		language: String,
+		line_numbers: bool,
+	},
+	Shortcode {
+		name: String,
+		path: PathBuf,
+		variables: HashMap<String, Value>,
+	},
+}
+
 impl ControlFlow {
 	fn if_new(condition: bool) -> Self {
 		Self::If {
@@ -215,7 +246,9 @@ pub fn process<T: Read + Seek>(
 						}
 					}
 					ControlFlow::Capture { .. } => {
-						ci = cf_stack.len() - index - 1
+						if ci == usize::MAX {
+							ci = cf_stack.len() - index - 1
+						}
 					}
 				}
 			}
@@ -680,7 +713,10 @@ pub fn process<T: Read + Seek>(
 				}
 			}
 			State::TagInParameter => if parsing_literal {
-				if current_identifier.len() > 1 && current_identifier.last() == Some(&b'"') {
+				// Literals usually start the identifier, but follow the = of
+				// shortcode keyword arguments (key="value").
+				let literal_start = current_identifier.iter().position(|b| *b == b'"').unwrap_or(0);
+				if current_identifier.len() > literal_start + 1 && current_identifier.last() == Some(&b'"') {
 					match c {
 						Char::Whitespace | Char::Newline => {
 							queued_identifiers.push(String::from_utf8_lossy(&current_identifier).to_string());
@@ -729,11 +765,19 @@ pub fn process<T: Read + Seek>(
 
 						state = State::TagEnd
 					}
-					Char::Quote => panic_at_location(
-						"Unexpected quote (\") in the middle of non-literal.",
-						&position,
-						context,
-					),
+					Char::Quote => {
+						// Keyword argument of shortcode: key="value".
+						if current_identifier.last() == Some(&b'=') {
+							current_identifier.push(byte);
+							parsing_literal = true;
+						} else {
+							panic_at_location(
+								"Unexpected quote (\") in the middle of non-literal.",
+								&position,
+								context,
+							)
+						}
+					}
 					Char::OpenCurly
 					| Char::CloseCurly
 					| Char::Percent
@@ -817,6 +861,26 @@ pub fn process<T: Read + Seek>(
 					capture_index, cf_stack[capture_index]
 				),
 			}
+		} else if capture_index != usize::MAX {
+			// The capture ended during this byte, forward anything written
+			// while ending it (such as highlighted code) to the enclosing
+			// output.
+			let written = capture_buf.into_inner().unwrap_or_else(|e| {
+				panic!("Failed unwrapping capture BufWriter: {}", e)
+			});
+			capture_buf = BufWriter::new(Vec::new());
+			let enclosing = cf_stack.iter_mut().rev().find_map(|cf| {
+				if let ControlFlow::Capture { content, .. } = cf {
+					Some(content)
+				} else {
+					None
+				}
+			});
+			if let Some(content) = enclosing {
+				content.extend(written)
+			} else {
+				write_to_stream(&written, parent_output_buf)
+			}
 		}
 
 		// This does not account for back-seeks due to for loops.
@@ -839,6 +903,13 @@ pub fn process<T: Read + Seek>(
 			state
 		),
 	}
+	if let Some(cf) = cf_stack.last() {
+		panic!(
+			"Content of {} ended inside of unclosed block: {:?}",
+			context.input_file_path.display(),
+			cf
+		)
+	}
 }
 
 fn panic_at_location(
@@ -997,6 +1068,28 @@ fn output_template_value(
 				value = value.to_uppercase();
 				offset += 2
 			}
+			"asset_url" => {
+				value = assets::make_url(&value, context);
+				offset += 2
+			}
+			"image" => {
+				// Optional alt text parameter.
+				let alt = match identifiers.get(offset + 2) {
+					Some(alt) if alt != "|" => {
+						offset += 1;
+						fetch_template_value(
+							alt,
+							outer_variables,
+							cf_stack,
+							context,
+						)
+						.string_content()
+					}
+					_ => String::new(),
+				};
+				value = image::make_img_html(&value, &alt, "", context);
+				offset += 2
+			}
 			_ => panic!("Unhandled filter function: {}", filter_function),
 		}
 	}
@@ -1086,7 +1179,18 @@ fn fetch_field(
 					.map_or_else(String::new, String::clone),
 			),
 			"title" => Value::String(context.front_matter.title.clone()),
+			"excerpt" => Value::String(markdown::render_excerpt(
+				context.input_file_path,
+				context.front_matter,
+				context,
+			)),
 			"published" => Value::Boolean(context.front_matter.published),
+			"noindex" => Value::Boolean(context.front_matter.noindex),
+			"robots_meta" => Value::String(if context.front_matter.noindex {
+				String::from("<meta name=\"robots\" content=\"noindex\">")
+			} else {
+				String::new()
+			}),
 			"edited" => Value::String(
 				context
 					.front_matter
@@ -1094,6 +1198,28 @@ fn fetch_field(
 					.as_ref()
 					.map_or_else(String::new, String::clone),
 			),
+			"audio" => Value::String(
+				context
+					.front_matter
+					.audio
+					.as_ref()
+					.map_or_else(String::new, String::clone),
+			),
+			"duration" => Value::String(
+				context
+					.front_matter
+					.duration
+					.as_ref()
+					.map_or_else(String::new, String::clone),
+			),
+			"episode" => context.front_matter.episode.map_or_else(
+				|| Value::String(String::new()),
+				|episode| {
+					Value::Integer(episode.try_into().unwrap_or_else(|e| {
+						panic!("Episode {} is too large: {}", episode, e)
+					}))
+				},
+			),
 			"categories" => Value::List {
 				values: context
 					.front_matter
@@ -1110,6 +1236,53 @@ fn fetch_field(
 					.map(|tag| Value::String(tag.clone()))
 					.collect(),
 			},
+			"collection" => Value::String(
+				find_current_input(context)
+					.and_then(|(_, output_file)| output_file.group.clone())
+					.unwrap_or_default(),
+			),
+			"previous" | "next" => find_current_input(context)
+				.and_then(|(_, output_file)| {
+					if field == "previous" {
+						output_file.neighbours.previous.as_ref()
+					} else {
+						output_file.neighbours.next.as_ref()
+					}
+				})
+				.and_then(|path| {
+					find_entries(slice::from_ref(path), context).pop()
+				})
+				.map_or_else(
+					|| Value::Dictionary {
+						map: HashMap::new(),
+					},
+					|entry| make_entry_value(&entry, context),
+				),
+			"toc" => make_toc_value(context.headings),
+			"related" => Value::List {
+				values: find_current_input(context)
+					.map_or_else(Vec::new, |(_, output_file)| {
+						find_entries(&output_file.neighbours.related, context)
+					})
+					.iter()
+					.map(|entry| make_entry_value(entry, context))
+					.collect(),
+			},
+			"backlinks" => {
+				context
+					.site_info
+					.links
+					.mark_showing(context.output_file_path);
+				Value::List {
+					values: find_current_input(context)
+						.map_or_else(Vec::new, |(_, output_file)| {
+							find_entries(&output_file.backlinks, context)
+						})
+						.iter()
+						.map(|entry| make_entry_value(entry, context))
+						.collect(),
+				}
+			}
 			_ => {
 				if let Some(value) =
 					context.front_matter.custom_attributes.get(field)
@@ -1203,37 +1376,12 @@ fn fetch_value(
 	context: &Context,
 ) -> Value {
 	if let Some(entries) = context.groups.get(name) {
-		let mut result = Vec::new();
-		for entry in entries {
-			let mut map = HashMap::new();
-			map.insert(
-				"title",
-				Value::String(entry.front_matter.title.clone()),
-			);
-			map.insert(
-				"date",
-				Value::String(
-					entry
-						.front_matter
-						.date
-						.as_ref()
-						.map_or_else(String::new, String::clone),
-				),
-			);
-			let mut link = String::from("/");
-			link.push_str(
-				&strip_prefix(&entry.path, context.root_input_dir)
-					.to_string_lossy(),
-			);
-			map.insert("link", Value::String(link));
-			map.insert(
-				"published",
-				Value::Boolean(entry.front_matter.published),
-			);
-
-			result.push(Value::Dictionary { map })
-		}
-		return Value::List { values: result };
+		return Value::List {
+			values: entries
+				.iter()
+				.map(|entry| make_entry_value(entry, context))
+				.collect(),
+		};
 	}
 
 	for cf in cf_stack.iter().rev() {
@@ -1259,6 +1407,111 @@ fn fetch_value(
 	panic!("Failed finding value for \"{}\"", name);
 }
 
+fn make_toc_value(headings: &[Heading]) -> Value {
+	let mut map = HashMap::new();
+	map.insert("html", Value::String(headings::make_toc_html(headings)));
+	map.insert(
+		"entries",
+		Value::List {
+			values: headings
+				.iter()
+				.map(|heading| {
+					let mut entry = HashMap::new();
+					entry.insert(
+						"level",
+						Value::Integer(
+							heading.level.try_into().unwrap_or_else(|e| {
+								panic!("Unexpected heading level: {}", e)
+							}),
+						),
+					);
+					entry.insert("title", Value::String(heading.title.clone()));
+					entry.insert("id", Value::String(heading.id.clone()));
+					Value::Dictionary { map: entry }
+				})
+				.collect(),
+		},
+	);
+	Value::Dictionary { map }
+}
+
+pub fn make_entry_value(entry: &InputFile, context: &Context) -> Value {
+	let mut map = HashMap::new();
+	map.insert("title", Value::String(entry.front_matter.title.clone()));
+	map.insert(
+		"date",
+		Value::String(
+			entry
+				.front_matter
+				.date
+				.as_ref()
+				.map_or_else(String::new, String::clone),
+		),
+	);
+	let mut link = String::from("/");
+	link.push_str(
+		&strip_prefix(&entry.path, context.root_input_dir).to_string_lossy(),
+	);
+	map.insert("link", Value::String(link));
+	map.insert("published", Value::Boolean(entry.front_matter.published));
+	map.insert(
+		"excerpt",
+		Value::String(markdown::render_excerpt(
+			&entry.path,
+			&entry.front_matter,
+			context,
+		)),
+	);
+
+	Value::Dictionary { map }
+}
+
+// The input path of markdown files is not part of the context when processing
+// their templates, so we go by the output path instead, which mirrors the
+// input path.
+fn find_current_input<'a>(
+	context: &Context<'a>,
+) -> Option<(&'a PathBuf, &'a GroupedOptionOutputFile)> {
+	let relative = context
+		.output_file_path
+		.strip_prefix(context.root_output_dir)
+		.ok()?;
+	[MARKDOWN_EXTENSION, HTML_EXTENSION]
+		.iter()
+		.find_map(|extension| {
+			context
+				.input_output_map
+				.get_key_value(
+					&context
+						.root_input_dir
+						.join(relative)
+						.with_extension(extension),
+				)
+				.filter(|(_, output_file)| {
+					&output_file.file.path == context.output_file_path
+				})
+		})
+}
+
+// Entries of the pages with the given input paths.
+fn find_entries(paths: &[PathBuf], context: &Context) -> Vec<InputFile> {
+	paths
+		.iter()
+		.filter_map(|path| {
+			let front_matter = context
+				.input_output_map
+				.get(path)?
+				.file
+				.front_matter
+				.clone()?;
+			Some(InputFile {
+				front_matter,
+				path: path.clone(),
+			})
+		})
+		.collect()
+}
+
 fn run_function<T: Read + Seek>(
 	input_file: &mut BufReader<T>,
 	output_buf: &mut BufWriter<Vec<u8>>,
@@ -1276,6 +1529,14 @@ fn run_function<T: Read + Seek>(
 	}
 
 	match function {
+		"asset_url" => emit_asset_url(
+			output_buf,
+			parameters,
+			outer_variables,
+			cf_stack,
+			skipping,
+			context,
+		),
 		"assign" => {
 			assign(parameters, outer_variables, cf_stack, skipping, context)
 		}
@@ -1283,6 +1544,18 @@ fn run_function<T: Read + Seek>(
 		"endcapture" => {
 			end_capture(parameters, outer_variables, cf_stack, skipping)
 		}
+		"feed_meta" => emit_feed_meta(
+			output_buf,
+			parameters,
+			outer_variables,
+			cf_stack,
+			skipping,
+			context,
+		),
+		"highlight" => start_highlight(parameters, cf_stack, skipping),
+		"endhighlight" => {
+			end_highlight(output_buf, parameters, cf_stack, skipping, context)
+		}
 		"if" => {
 			start_if(parameters, outer_variables, cf_stack, skipping, context)
 		}
@@ -1314,10 +1587,217 @@ fn run_function<T: Read + Seek>(
 			skipping,
 			context,
 		),
-		_ => panic!("Unsupported function: {}", function),
+		"seo" => emit_seo(output_buf, parameters, skipping, context),
+		_ => run_shortcode(
+			output_buf,
+			function,
+			parameters,
+			outer_variables,
+			cf_stack,
+			skipping,
+			context,
+		),
+	}
+}
+
+// Built-in tags taking a body up until their end tag.
+const BLOCK_TAGS: [&str; 4] = ["capture", "for", "highlight", "if"];
+
+// Nesting depth of blocks after the line, given the depth before it.
+pub fn block_depth(
+	line: &str,
+	mut depth: usize,
+	root_input_dir: &Path,
+) -> usize {
+	let mut rest = line;
+	while let Some(start) = rest.find("{%") {
+		rest = rest[start + 2..].trim_start_matches(['-', ' ', '\t']);
+		let name_end = rest
+			.find(|c: char| !c.is_alphanumeric() && c != '_')
+			.unwrap_or(rest.len());
+		let name = &rest[..name_end];
+		if name.starts_with("end") {
+			depth = depth.saturating_sub(1)
+		} else if BLOCK_TAGS.contains(&name)
+			|| shortcode::takes_body(root_input_dir, name)
+		{
+			depth += 1
+		}
+		rest = &rest[name_end..];
+	}
+	depth
+}
+
+// Allowing more arguments to mirror run_function.
+fn run_shortcode(
+	output_buf: &mut BufWriter<Vec<u8>>,
+	function: &str,
+	parameters: &[String],
+	outer_variables: &HashMap<String, Value>,
+	cf_stack: &mut Vec<ControlFlow>,
+	skipping: bool,
+	context: &Context,
+) {
+	if let Some(name) = function.strip_prefix("end") {
+		if shortcode::find(context.root_input_dir, name).is_some() {
+			end_shortcode(
+				output_buf, name, parameters, cf_stack, skipping, context,
+			);
+			return;
+		}
+	}
+
+	let path = shortcode::find(context.root_input_dir, function)
+		.unwrap_or_else(|| {
+			panic!(
+				"Unsupported function: {} (and no {}/{}.html shortcode exists), file: {}",
+				function,
+				shortcode::DIRECTORY,
+				function,
+				context.input_file_path.display()
+			)
+		});
+	if skipping {
+		return;
+	}
+
+	let template = shortcode::open(&path);
+	let mut variables = HashMap::new();
+	let mut positional = Vec::new();
+	for parameter in parameters {
+		if let Some((key, value)) = shortcode::split_keyword(parameter) {
+			if !template.parameters.is_empty()
+				&& !template.parameters.iter().any(|(name, _)| name == key)
+			{
+				panic!(
+					"Unknown argument {} to shortcode {}, declared parameters: {:?}, file: {}",
+					key,
+					function,
+					template
+						.parameters
+						.iter()
+						.map(|(name, _)| name)
+						.collect::<Vec<_>>(),
+					context.input_file_path.display()
+				)
+			}
+			variables.insert(
+				key.to_string(),
+				fetch_template_value(value, outer_variables, cf_stack, context),
+			);
+		} else {
+			positional.push(fetch_template_value(
+				parameter,
+				outer_variables,
+				cf_stack,
+				context,
+			));
+		}
+	}
+	if !template.parameters.is_empty()
+		&& positional.len() > template.parameters.len()
+	{
+		panic!(
+			"Shortcode {} takes at most {} arguments but got {}, file: {}",
+			function,
+			template.parameters.len(),
+			positional.len(),
+			context.input_file_path.display()
+		)
+	}
+	for (index, (name, default)) in template.parameters.iter().enumerate() {
+		if let Some(value) = positional.get(index) {
+			variables.insert(name.clone(), value.clone());
+		} else {
+			variables
+				.entry(name.clone())
+				.or_insert_with(|| default.clone());
+		}
+	}
+	variables.insert("args".to_string(), Value::List { values: positional });
+
+	if template.takes_body {
+		cf_stack.push(ControlFlow::Capture {
+			target: CaptureTarget::Shortcode {
+				name: function.to_string(),
+				path,
+				variables,
+			},
+			content: Vec::new(),
+		})
+	} else {
+		render_shortcode(output_buf, &path, template, variables, context)
+	}
+}
+
+fn end_shortcode(
+	output_buf: &mut BufWriter<Vec<u8>>,
+	name: &str,
+	parameters: &[String],
+	cf_stack: &mut Vec<ControlFlow>,
+	skipping: bool,
+	context: &Context,
+) {
+	if skipping {
+		return;
+	}
+	if !parameters.is_empty() {
+		panic!(
+			"Expecting no parameters to end{}. Encountered: {:?}",
+			name, parameters
+		)
+	}
+
+	let cf = cf_stack.pop().unwrap_or_else(|| {
+		panic!("Encountered end{} when control flow stack was empty.", name)
+	});
+	match cf {
+		ControlFlow::Capture {
+			target:
+				CaptureTarget::Shortcode {
+					name: started_name,
+					path,
+					mut variables,
+				},
+			content,
+		} if started_name == name => {
+			// The body is markdown, converted before being handed to the
+			// template.
+			variables.insert(
+				shortcode::BODY.to_string(),
+				Value::String(markdown::to_html(
+					&String::from_utf8_lossy(&content),
+					context,
+				)),
+			);
+			let template = shortcode::open(&path);
+			render_shortcode(output_buf, &path, template, variables, context)
+		}
+		_ => panic!(
+			"Expected {} shortcode as front-most control flow element but got: {:?}",
+			name, cf
+		),
 	}
 }
 
+fn render_shortcode(
+	output_buf: &mut BufWriter<Vec<u8>>,
+	path: &PathBuf,
+	mut template: shortcode::Template,
+	variables: HashMap<String, Value>,
+	context: &Context,
+) {
+	process(
+		&mut template.reader,
+		output_buf,
+		variables,
+		&Context {
+			input_file_path: path,
+			..*context
+		},
+	)
+}
+
 fn assign(
 	parameters: &[String],
 	outer_variables: &mut HashMap<String, Value>,
@@ -1413,7 +1893,7 @@ fn start_capture(
 	}
 
 	cf_stack.push(ControlFlow::Capture {
-		variable: parameters[0].clone(),
+		target: CaptureTarget::Variable(parameters[0].clone()),
 		content: Vec::new(),
 	})
 }
@@ -1438,7 +1918,10 @@ fn end_capture(
 		.pop()
 		.expect("Encountered endcapture when control flow stack was empty.");
 	match cf {
-		ControlFlow::Capture { variable, content } => assign_inner(
+		ControlFlow::Capture {
+			target: CaptureTarget::Variable(variable),
+			content,
+		} => assign_inner(
 			&variable,
 			Value::String(String::from_utf8_lossy(&content).to_string()),
 			outer_variables,
@@ -1451,6 +1934,79 @@ fn end_capture(
 	}
 }
 
+// Highlighting captures the content of the block, which is written out once
+// the block ends.
+fn start_highlight(
+	parameters: &[String],
+	cf_stack: &mut Vec<ControlFlow>,
+	skipping: bool,
+) {
+	if skipping {
+		return;
+	}
+	let line_numbers = match parameters {
+		[_] => false,
+		[_, option] if option == "linenos" => true,
+		_ => panic!("highlight-statement doesn't have the correct parameters, expecting \"highlight <language> [linenos]\", got: {:?}", parameters),
+	};
+
+	cf_stack.push(ControlFlow::Capture {
+		target: CaptureTarget::Highlight {
+			language: parameters[0].clone(),
+			line_numbers,
+		},
+		content: Vec::new(),
+	})
+}
+
+fn end_highlight(
+	output_buf: &mut BufWriter<Vec<u8>>,
+	parameters: &[String],
+	cf_stack: &mut Vec<ControlFlow>,
+	skipping: bool,
+	context: &Context,
+) {
+	if skipping {
+		return;
+	}
+	if !parameters.is_empty() {
+		panic!(
+			"Expecting no parameters to endhighlight. Encountered: {:?}",
+			parameters
+		)
+	}
+
+	let cf = cf_stack
+		.pop()
+		.expect("Encountered endhighlight when control flow stack was empty.");
+	match cf {
+		ControlFlow::Capture {
+			target:
+				CaptureTarget::Highlight {
+					language,
+					line_numbers,
+				},
+			content,
+		} => {
+			let code = String::from_utf8_lossy(&content);
+			context.site_info.highlighted.store(true, Ordering::Relaxed);
+			write_to_stream(
+				highlight::highlight(
+					code.trim_start_matches('\n').trim_end(),
+					&language,
+					line_numbers,
+				)
+				.as_bytes(),
+				output_buf,
+			)
+		}
+		_ => panic!(
+			"Expected highlight as front-most control flow element but got: {:?}",
+			cf
+		),
+	}
+}
+
 fn start_if(
 	parameters: &[String],
 	outer_variables: &mut HashMap<String, Value>,
@@ -1828,12 +2384,30 @@ fn check_and_emit_link(
 	}
 
 	let linked_output_path = &match context.input_output_map.get(&path) {
-		Some(lo) => lo,
-		_ => panic!(
-			"Failed finding {} among: {:#?}",
-			path.display(),
-			context.input_output_map.keys()
-		),
+		Some(lo) => {
+			context.site_info.links.record(&path, context);
+			lo
+		}
+		_ => {
+			let suggestion = suggest_closest(
+				&parameter,
+				context.input_output_map.keys().map(|key| {
+					format!(
+						"/{}",
+						strip_prefix(key, context.root_input_dir)
+							.to_string_lossy()
+					)
+				}),
+			)
+			.map(|s| format!(" Did you mean \"{}\"?", s))
+			.unwrap_or_default();
+			panic!(
+				"Failed finding link target {} from {}.{}",
+				path.display(),
+				context.input_file_path.display(),
+				suggestion
+			)
+		}
 	}
 	.file
 	.path;
@@ -1843,16 +2417,345 @@ fn check_and_emit_link(
 			output_file_path,
 			linked_output_path,
 			context.root_output_dir,
+			context.site_info.base_url,
 		)
 		.as_bytes(),
 		output_buf,
 	);
 }
 
-fn make_relative_link(
+fn emit_asset_url(
+	output_buf: &mut BufWriter<Vec<u8>>,
+	parameters: &[String],
+	outer_variables: &HashMap<String, Value>,
+	cf_stack: &[ControlFlow],
+	skipping: bool,
+	context: &Context,
+) {
+	if skipping {
+		return;
+	}
+
+	if parameters.len() != 1 {
+		panic!(
+			"Expecting 1 parameter in asset_url operation. Encountered: {:?}",
+			parameters
+		)
+	}
+
+	let name = fetch_template_value(
+		&parameters[0],
+		outer_variables,
+		cf_stack,
+		context,
+	)
+	.string_content();
+	write_to_stream(assets::make_url(&name, context).as_bytes(), output_buf);
+}
+
+// Emits <link rel="alternate"> tags for the combined feed and the feeds of all
+// groups, or for the group given as parameter, where "all" means the combined
+// feed.
+fn emit_feed_meta(
+	output_buf: &mut BufWriter<Vec<u8>>,
+	parameters: &[String],
+	outer_variables: &HashMap<String, Value>,
+	cf_stack: &[ControlFlow],
+	skipping: bool,
+	context: &Context,
+) {
+	if skipping {
+		return;
+	}
+
+	let mut groups = context.groups.keys().cloned().collect::<Vec<_>>();
+	groups.sort();
+	if !groups.is_empty() {
+		groups.insert(0, feed::ALL_NAME.to_string());
+	}
+	match parameters {
+		[] => {}
+		[group] => {
+			let group =
+				fetch_template_value(group, outer_variables, cf_stack, context)
+					.string_content();
+			if !groups.contains(&group) {
+				let suggestion = suggest_closest(&group, groups.into_iter())
+					.map(|s| format!(" Did you mean \"{}\"?", s))
+					.unwrap_or_default();
+				panic!(
+					"Unknown group {} in feed_meta, file: {}.{}",
+					group,
+					context.input_file_path.display(),
+					suggestion
+				)
+			}
+			groups = vec![group];
+		}
+		_ => panic!(
+			"Expecting 0 or 1 parameters in feed_meta operation. Encountered: {:?}",
+			parameters
+		),
+	}
+
+	for group in groups {
+		let title = if group == feed::ALL_NAME {
+			context.site_info.title.to_string()
+		} else {
+			format!("{} - {}", context.site_info.title, capitalize(&group))
+		};
+		for format in feed::formats_for(context.site_info.feeds, &group) {
+			let href = make_relative_link(
+				context.output_file_path,
+				&context.root_output_dir.join(format.file_path(&group)),
+				context.root_output_dir,
+				context.site_info.base_url,
+			);
+			write_to_stream(
+				format!(
+					"<link rel=\"alternate\" type=\"{}\" title=\"{}\" href=\"{}\">\n",
+					format.mime_type(),
+					escape_html(&title),
+					escape_html(&href)
+				)
+				.as_bytes(),
+				output_buf,
+			);
+		}
+	}
+}
+
+// Emits the title, description, canonical URL, Open Graph and Twitter card
+// tags and JSON-LD of the page. Group entries are described as blog posts,
+// other pages as web pages. The description, image, canonical_url and author
+// front matter keys override the excerpt, no image, the URL of the page and
+// the configured author.
+fn emit_seo(
+	output_buf: &mut BufWriter<Vec<u8>>,
+	parameters: &[String],
+	skipping: bool,
+	context: &Context,
+) {
+	if skipping {
+		return;
+	}
+	if !parameters.is_empty() {
+		panic!(
+			"Expecting no parameters in seo operation. Encountered: {:?}",
+			parameters
+		)
+	}
+
+	let site_info = context.site_info;
+	let front_matter = context.front_matter;
+	let attribute = |key: &str| {
+		front_matter
+			.custom_attributes
+			.get(key)
+			.filter(|value| !value.is_empty())
+	};
+	let absolute_url = |url: &str| {
+		if url.contains("://") {
+			url.to_string()
+		} else {
+			let path = url.strip_prefix('/').map_or_else(
+				|| {
+					let dir = strip_prefix(
+						context.output_file_path.parent().unwrap_or_else(
+							|| {
+								panic!(
+									"Failed getting parent of {}",
+									context.output_file_path.display()
+								)
+							},
+						),
+						context.root_output_dir,
+					);
+					let mut path = dir
+						.components()
+						.map(|c| c.as_os_str().to_string_lossy())
+						.collect::<Vec<_>>()
+						.join("/");
+					if !path.is_empty() {
+						path.push('/');
+					}
+					path.push_str(url);
+					path
+				},
+				str::to_string,
+			);
+			format!("{}{}", site_info.base_url, path)
+		}
+	};
+
+	let is_post = find_current_input(context)
+		.is_some_and(|(_, output_file)| output_file.group.is_some());
+	let title = if front_matter.title.is_empty()
+		|| front_matter.title == site_info.title
+	{
+		site_info.title.to_string()
+	} else {
+		format!("{} | {}", front_matter.title, site_info.title)
+	};
+	let description = attribute("description").cloned().unwrap_or_else(|| {
+		summarize(&search::html_to_text(&markdown::render_excerpt(
+			context.input_file_path,
+			front_matter,
+			context,
+		)))
+	});
+	let url = if let Some(canonical_url) = attribute("canonical_url") {
+		absolute_url(canonical_url)
+	} else {
+		let path =
+			strip_prefix(context.output_file_path, context.root_output_dir)
+				.components()
+				.map(|c| c.as_os_str().to_string_lossy())
+				.collect::<Vec<_>>()
+				.join("/");
+		absolute_url(&format!(
+			"/{}",
+			path.strip_suffix("index.html").unwrap_or(&path)
+		))
+	};
+	let image = attribute("image").map(|image| absolute_url(image));
+	let author = attribute("author").map_or(site_info.author, String::as_str);
+
+	let mut tags = format!(
+		"<title>{}</title>\n\
+		<link rel=\"canonical\" href=\"{}\">\n",
+		escape_html(&title),
+		escape_html(&url)
+	);
+	let mut meta = |attribute: &str, name: &str, content: &str| {
+		tags.push_str(&format!(
+			"<meta {}=\"{}\" content=\"{}\">\n",
+			attribute,
+			name,
+			escape_html(content)
+		))
+	};
+	if !description.is_empty() {
+		meta("name", "description", &description);
+	}
+	if !author.is_empty() {
+		meta("name", "author", author);
+	}
+	meta("property", "og:title", &front_matter.title);
+	meta("property", "og:site_name", site_info.title);
+	meta("property", "og:url", &url);
+	meta(
+		"property",
+		"og:type",
+		if is_post { "article" } else { "website" },
+	);
+	if !description.is_empty() {
+		meta("property", "og:description", &description);
+	}
+	if let Some(image) = &image {
+		meta("property", "og:image", image);
+	}
+	if is_post {
+		if let Some(date) = &front_matter.date {
+			meta("property", "article:published_time", date);
+		}
+		if let Some(edited) = &front_matter.edited {
+			meta("property", "article:modified_time", edited);
+		}
+	}
+	meta(
+		"name",
+		"twitter:card",
+		if image.is_some() {
+			"summary_large_image"
+		} else {
+			"summary"
+		},
+	);
+	meta("name", "twitter:title", &front_matter.title);
+	if !description.is_empty() {
+		meta("name", "twitter:description", &description);
+	}
+	if let Some(image) = &image {
+		meta("name", "twitter:image", image);
+	}
+
+	let mut json_ld = vec![
+		("@context", json_string("https://schema.org")),
+		(
+			"@type",
+			json_string(if is_post { "BlogPosting" } else { "WebPage" }),
+		),
+		(
+			if is_post { "headline" } else { "name" },
+			json_string(&front_matter.title),
+		),
+		("url", json_string(&url)),
+	];
+	if !description.is_empty() {
+		json_ld.push(("description", json_string(&description)));
+	}
+	if let Some(image) = &image {
+		json_ld.push(("image", json_string(image)));
+	}
+	if let Some(date) = &front_matter.date {
+		json_ld.push(("datePublished", json_string(date)));
+	}
+	if let Some(edited) = &front_matter.edited {
+		json_ld.push(("dateModified", json_string(edited)));
+	}
+	if !author.is_empty() {
+		let mut person =
+			format!("{{\"@type\":\"Person\",\"name\":{}", json_string(author));
+		if let Some(email) = site_info
+			.email
+			.filter(|email| !email.is_empty() && attribute("author").is_none())
+		{
+			person.push_str(&format!(",\"email\":{}", json_string(email)));
+		}
+		person.push('}');
+		json_ld.push(("author", person));
+	}
+	tags.push_str(&format!(
+		"<script type=\"application/ld+json\">{{{}}}</script>\n",
+		json_ld
+			.iter()
+			.map(|(key, value)| format!("\"{}\":{}", key, value))
+			.collect::<Vec<_>>()
+			.join(",")
+	));
+
+	write_to_stream(tags.as_bytes(), output_buf);
+}
+
+// Escaped for JSON inside a script element, which must not contain </.
+fn json_string(value: &str) -> String {
+	format!("\"{}\"", escape_json(value).replace("</", "<\\/"))
+}
+
+// Collapses whitespace and cuts the text at a word boundary to fit in the
+// snippets shown by search engines and link previews.
+fn summarize(text: &str) -> String {
+	const MAX_LENGTH: usize = 160;
+	let mut summary = String::new();
+	for word in text.split_whitespace() {
+		if summary.len() + word.len() + 1 > MAX_LENGTH {
+			summary.push('…');
+			break;
+		}
+		if !summary.is_empty() {
+			summary.push(' ');
+		}
+		summary.push_str(word);
+	}
+	summary
+}
+
+pub fn make_relative_link(
 	output_file_path: &PathBuf,
 	linked_output_path: &PathBuf,
 	root_output_dir: &PathBuf,
+	base_url: &str,
 ) -> std::string::String {
 	let mut equal_prefix = PathBuf::new();
 	let mut equal_component_count = 0;
@@ -1877,6 +2780,23 @@ fn make_relative_link(
 		root_output_dir.display()
 	);
 
+	// The 404 page is served in place of missing files at any depth, so its
+	// links start from the path of the base URL instead.
+	if strip_prefix(output_file_path, root_output_dir).as_os_str()
+		== NOT_FOUND_PAGE
+	{
+		let mut link = strip_prefix(linked_output_path, root_output_dir);
+		if link.file_name() == Some(OsStr::new("index.html")) {
+			link = link.with_file_name("")
+		}
+		let (_, base_path) = check::split_base_url(base_url);
+		return format!(
+			"{}/{}",
+			base_path.trim_end_matches('/'),
+			link.to_string_lossy()
+		);
+	}
+
 	// Do not strip own file name from link if path is the same.
 	if output_file_path == linked_output_path {
 		equal_prefix.pop();
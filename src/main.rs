@@ -2,18 +2,40 @@ use std::collections::{hash_map::Entry, BTreeMap, HashMap};
 use std::ffi::OsStr;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::atomic::Ordering;
 use std::sync::{Arc, Condvar, Mutex, RwLock};
 use std::thread;
 use std::time::{Duration, Instant};
 use std::{env, fs};
 
+mod admonitions;
+mod archive;
+mod assets;
 mod atom;
+mod backlinks;
+mod check;
 mod config;
+mod css;
+mod feed;
+mod footnotes;
 mod front_matter;
+mod headings;
+mod highlight;
 mod http;
+mod image;
+mod json_feed;
+mod links;
 mod liquid;
 mod markdown;
+mod math;
+mod minify;
+mod neighbours;
+mod precompress;
 mod robots;
+mod rss;
+mod search;
+mod shortcode;
+mod smart_punctuation;
 mod util;
 mod watch_fs;
 mod websocket;
@@ -22,10 +44,12 @@ mod websocket;
 mod tests;
 
 use config::{make_site_info, Config};
-use markdown::{GroupedOptionOutputFile, InputFile, OptionOutputFile};
+use feed::FeedFormat;
+use markdown::{GroupedOptionOutputFile, Indexes, InputFile, OptionOutputFile};
+use neighbours::Neighbours;
 use util::{
 	find_newest_file, get_front_matter_and_output_path, strip_prefix,
-	translate_input_to_output, Refresh,
+	BuildState, Refresh, CSS_EXTENSION,
 };
 
 fn main() {
@@ -50,10 +74,11 @@ Arguments:"
 }
 
 fn inner_main(config: &Config) {
+	let state = BuildState::default();
 	let mut input_files = markdown::get_files(&config.input_dir);
 	let mut input_output_map;
-	let mut groups;
-	let mut tags;
+	let mut indexes;
+	let mut bundles = css::Bundles::new();
 
 	if input_files.is_empty() {
 		println!(
@@ -61,8 +86,7 @@ fn inner_main(config: &Config) {
 			config.input_dir.display()
 		);
 		input_output_map = HashMap::new();
-		groups = HashMap::new();
-		tags = HashMap::new();
+		indexes = Indexes::default();
 	} else {
 		fs::create_dir(&config.output_dir).unwrap_or_else(|e| {
 			panic!(
@@ -72,23 +96,75 @@ fn inner_main(config: &Config) {
 			)
 		});
 
+		if config.bundle_css {
+			input_files.raw.retain(|path| {
+				let partial = path.extension()
+					== Some(OsStr::new(CSS_EXTENSION))
+					&& css::is_partial(path, &config.input_dir);
+				if partial {
+					println!("Skipping CSS partial: {}", path.display());
+				}
+				!partial
+			});
+			bundles = css::bundle_all(
+				&input_files.raw,
+				&config.input_dir,
+				&config.output_dir,
+			);
+		}
+
 		let fs = build_initial_fileset(
 			&mut input_files,
 			&config.input_dir,
 			&config.output_dir,
 			config.deploy,
+			&config.excerpt_separator,
+			&config.feeds,
+			config.fingerprint_assets.then_some(&bundles),
 		);
 		input_output_map = fs.input_output_map;
-		groups = fs.groups;
-		tags = fs.tags;
+		indexes = fs.indexes;
+		neighbours::update(
+			&mut input_output_map,
+			&indexes.groups,
+			config.related_limit,
+		);
 
 		process_initial_files(
 			&input_files,
 			config,
-			&input_output_map,
-			&groups,
-			&tags,
-		)
+			&mut input_output_map,
+			&indexes,
+			&bundles,
+			&state,
+		);
+		let orphans = state
+			.links
+			.find_orphans(&input_output_map, &config.input_dir);
+		if !orphans.is_empty() {
+			println!("Found {} page(s) without inbound links:", orphans.len());
+			for orphan in orphans {
+				println!(
+					"  {}",
+					strip_prefix(&orphan, &config.input_dir).display()
+				);
+			}
+		}
+
+		if config.check {
+			let report = check::run(
+				&config.output_dir,
+				&config.base_url,
+				&input_output_map,
+			);
+			report.print();
+			if !report.problems.is_empty() {
+				panic!(
+					"Check found {} broken link(s), anchor(s) or asset(s).",
+					report.problems.len()
+				)
+			}
+		}
 	}
 
 	if !config.watch && !config.deploy {
@@ -143,17 +219,17 @@ fn inner_main(config: &Config) {
 		watch_fs::run(
 			&fs_cond,
 			&mut input_output_map,
-			&mut groups,
-			&mut tags,
+			&mut indexes,
+			&mut bundles,
 			config,
+			&state,
 		);
 	}
 }
 
 struct InitialFileSet {
 	input_output_map: HashMap<PathBuf, GroupedOptionOutputFile>,
-	groups: HashMap<String, Vec<InputFile>>,
-	tags: HashMap<String, Vec<InputFile>>,
+	indexes: Indexes,
 }
 
 fn build_initial_fileset(
@@ -161,11 +237,13 @@ fn build_initial_fileset(
 	input_dir: &PathBuf,
 	output_dir: &PathBuf,
 	deploying: bool,
+	excerpt_separator: &str,
+	feeds: &BTreeMap<String, Vec<FeedFormat>>,
+	fingerprinting: Option<&css::Bundles>,
 ) -> InitialFileSet {
 	let mut result = InitialFileSet {
 		input_output_map: HashMap::new(),
-		groups: HashMap::new(),
-		tags: HashMap::new(),
+		indexes: Indexes::default(),
 	};
 
 	// First, build up the input -> output map so that later when we do
@@ -174,7 +252,10 @@ fn build_initial_fileset(
 	let mut unpublished = Vec::new();
 	for file_name in &input_files.html {
 		let output_file = markdown::parse_fm_and_compute_output_path(
-			file_name, input_dir, output_dir,
+			file_name,
+			input_dir,
+			output_dir,
+			excerpt_separator,
 		);
 		if deploying && !output_file.file.front_matter.published {
 			unpublished.push(file_name.clone());
@@ -185,10 +266,12 @@ fn build_initial_fileset(
 			GroupedOptionOutputFile {
 				file: output_file.file.convert_to_option(),
 				group: output_file.group,
+				backlinks: Vec::new(),
+				neighbours: Neighbours::default(),
 			},
 			&mut result.input_output_map,
-			Some(&mut result.groups),
-			Some(&mut result.tags),
+			Some(&mut result.indexes.groups),
+			Some(&mut result.indexes.tags),
 		)
 	}
 	input_files.html.retain(|f| !unpublished.contains(f));
@@ -196,7 +279,10 @@ fn build_initial_fileset(
 	let mut unpublished = Vec::new();
 	for file_name in &input_files.markdown {
 		let output_file = markdown::parse_fm_and_compute_output_path(
-			file_name, input_dir, output_dir,
+			file_name,
+			input_dir,
+			output_dir,
+			excerpt_separator,
 		);
 		if deploying && !output_file.file.front_matter.published {
 			unpublished.push(file_name.clone());
@@ -207,10 +293,12 @@ fn build_initial_fileset(
 			GroupedOptionOutputFile {
 				file: output_file.file.convert_to_option(),
 				group: output_file.group,
+				backlinks: Vec::new(),
+				neighbours: Neighbours::default(),
 			},
 			&mut result.input_output_map,
-			Some(&mut result.groups),
-			Some(&mut result.tags),
+			Some(&mut result.indexes.groups),
+			Some(&mut result.indexes.tags),
 		)
 	}
 	input_files.markdown.retain(|f| !unpublished.contains(f));
@@ -220,53 +308,128 @@ fn build_initial_fileset(
 			file_name,
 			GroupedOptionOutputFile {
 				file: OptionOutputFile {
-					path: translate_input_to_output(
-						file_name, input_dir, output_dir,
+					path: assets::make_output_path(
+						file_name,
+						input_dir,
+						output_dir,
+						fingerprinting,
 					),
 					front_matter: None,
 				},
 				group: None,
+				backlinks: Vec::new(),
+				neighbours: Neighbours::default(),
 			},
 			&mut result.input_output_map,
-			Some(&mut result.groups),
-			Some(&mut result.tags),
+			Some(&mut result.indexes.groups),
+			Some(&mut result.indexes.tags),
 		)
 	}
 
 	// Use stable sort in attempt to stay relatively deterministic, even
 	// though we are still relying on the file system to give us files with
 	// exactly equal front matter dates in the same order.
-	for entries in result.groups.values_mut() {
+	for entries in result.indexes.groups.values_mut() {
 		entries.sort_by(|lhs, rhs| {
 			rhs.front_matter.date.cmp(&lhs.front_matter.date)
 		})
 	}
-	for entries in result.tags.values_mut() {
+	for entries in result.indexes.tags.values_mut() {
 		entries.sort_by(|lhs, rhs| {
 			rhs.front_matter.date.cmp(&lhs.front_matter.date)
 		})
 	}
 
-	for group in result.groups.keys() {
-		let xml_file = PathBuf::from("feeds")
-			.join(group)
-			.with_extension(util::XML_EXTENSION);
-		checked_insert(
-			&input_dir.join(&xml_file), // virtual input
-			GroupedOptionOutputFile {
-				file: OptionOutputFile {
-					path: output_dir.join(xml_file),
-					front_matter: None,
+	if archive::enabled(input_dir) {
+		// Entries get pushed in the already sorted order of their groups.
+		for entries in result.indexes.groups.values() {
+			for entry in entries {
+				for period in archive::get_periods(
+					&entry.path,
+					input_dir,
+					&entry.front_matter,
+				) {
+					match result.indexes.archives.entry(period) {
+						Entry::Vacant(ve) => {
+							ve.insert(vec![entry.clone()]);
+						}
+						Entry::Occupied(oe) => {
+							oe.into_mut().push(entry.clone())
+						}
+					}
+				}
+			}
+		}
+
+		let mut colliding = Vec::new();
+		for period in result.indexes.archives.keys() {
+			if archive::collides(
+				period,
+				input_dir,
+				output_dir,
+				&result.input_output_map,
+			) {
+				colliding.push(period.clone());
+				continue;
+			}
+
+			checked_insert(
+				&archive::make_input_path(input_dir, period), // virtual input
+				GroupedOptionOutputFile {
+					file: OptionOutputFile {
+						path: archive::make_output_path(output_dir, period),
+						front_matter: Some(Arc::new(
+							archive::make_front_matter(period),
+						)),
+					},
+					group: None,
+					backlinks: Vec::new(),
+					neighbours: Neighbours::default(),
 				},
-				group: None,
-			},
-			&mut result.input_output_map,
-			None,
-			Some(&mut result.tags),
-		)
+				&mut result.input_output_map,
+				None,
+				None,
+			)
+		}
+		for period in colliding {
+			result.indexes.archives.remove(&period);
+		}
 	}
 
-	for tag in result.tags.keys() {
+	let mut feed_names = result
+		.indexes
+		.groups
+		.keys()
+		.map(|group| (group.clone(), group.as_str()))
+		.chain(result.indexes.tags.keys().map(|tag| {
+			(format!("{}/{}", feed::TAGS_NAME, tag), feed::TAGS_NAME)
+		}))
+		.collect::<Vec<_>>();
+	if !result.indexes.groups.is_empty() {
+		feed_names.push((feed::ALL_NAME.to_string(), feed::ALL_NAME));
+	}
+	for (name, formats_key) in &feed_names {
+		for format in feed::formats_for(feeds, formats_key) {
+			let feed_file = format.file_path(name);
+			checked_insert(
+				&input_dir.join(&feed_file), // virtual input
+				GroupedOptionOutputFile {
+					file: OptionOutputFile {
+						path: output_dir.join(feed_file),
+						front_matter: None,
+					},
+					group: None,
+					backlinks: Vec::new(),
+					neighbours: Neighbours::default(),
+				},
+				&mut result.input_output_map,
+				None,
+				Some(&mut result.indexes.tags),
+			)
+		}
+	}
+
+	for tag in result.indexes.tags.keys() {
 		let tags_file = PathBuf::from("tags")
 			.join(tag)
 			.with_extension(util::HTML_EXTENSION);
@@ -275,23 +438,19 @@ fn build_initial_fileset(
 			GroupedOptionOutputFile {
 				file: OptionOutputFile {
 					path: output_dir.join(tags_file),
-					front_matter: Some(Arc::new(front_matter::FrontMatter {
-						title: format!("Tag: {}", tag),
-						date: None,
-						published: true,
-						edited: None,
-						categories: Vec::new(),
-						tags: Vec::new(),
-						layout: None,
-						custom_attributes: BTreeMap::new(),
-						end_position: 0,
-						subsequent_line: 1,
-					})),
+					front_matter: Some(Arc::new(
+						front_matter::FrontMatter::with_title(format!(
+							"Tag: {}",
+							tag
+						)),
+					)),
 				},
 				group: None,
+				backlinks: Vec::new(),
+				neighbours: Neighbours::default(),
 			},
 			&mut result.input_output_map,
-			Some(&mut result.groups),
+			Some(&mut result.indexes.groups),
 			None,
 		)
 	}
@@ -302,14 +461,49 @@ fn build_initial_fileset(
 fn process_initial_files(
 	input_files: &markdown::InputFileCollection,
 	config: &Config,
-	input_output_map: &HashMap<PathBuf, GroupedOptionOutputFile>,
-	groups: &HashMap<String, Vec<InputFile>>,
-	tags: &HashMap<String, Vec<InputFile>>,
+	input_output_map: &mut HashMap<PathBuf, GroupedOptionOutputFile>,
+	indexes: &Indexes,
+	bundles: &css::Bundles,
+	state: &BuildState,
 ) {
 	let timer = Instant::now();
+	let Indexes {
+		groups,
+		tags,
+		archives,
+	} = indexes;
+
+	// Pages only offer the variants which were written, so they come first.
+	if !config.image_widths.is_empty() {
+		crossbeam_utils::thread::scope(|s| {
+			for file_name in &input_files.raw {
+				if config.single_file.is_some()
+					&& config.single_file.as_deref() != Some(file_name)
+				{
+					continue;
+				}
+
+				let handle = s.spawn(move |_| {
+					image::write_variants(
+						file_name,
+						&config.input_dir,
+						&config.output_dir,
+						&config.image_widths,
+					);
+				});
+				if config.serial {
+					handle.join().unwrap_or_else(|e| {
+						panic!("Failed joining on thread: {:?}", e)
+					});
+				}
+			}
+		})
+		.unwrap_or_else(|e| panic!("Crossbeam scope failed: {:?}", e));
+	}
 
 	let mut file_count = 0;
 	crossbeam_utils::thread::scope(|s| {
+		let input_output_map = &*input_output_map;
 		let feed_map = Arc::new(RwLock::new(HashMap::new()));
 		let mut feed_map_writers = Vec::new();
 		let mut processed_single = false;
@@ -339,13 +533,26 @@ fn process_initial_files(
 						&config.output_dir,
 						input_output_map,
 						groups,
-						&make_site_info(config),
+						&make_site_info(config, state),
 					);
 					if let Some(group) = generated.group {
-						let entry = atom::FeedEntry {
+						let enclosure = feed::make_enclosure(
+							file_name,
+							&generated.file.front_matter,
+							&config.input_dir,
+							&config.output_dir,
+							input_output_map,
+						);
+						let entry = feed::FeedEntry {
 							front_matter: generated.file.front_matter,
 							html_content: generated.html_content,
+							summary: if config.feed_summary {
+								Some(generated.excerpt)
+							} else {
+								None
+							},
 							permalink: generated.file.path,
+							enclosure,
 						};
 						let mut locked_feed_map =
 							feed_map_c.write().unwrap_or_else(|e| {
@@ -401,7 +608,7 @@ fn process_initial_files(
 						&config.output_dir,
 						input_output_map,
 						groups,
-						&make_site_info(config),
+						&make_site_info(config, state),
 					)
 				}
 			});
@@ -429,7 +636,22 @@ fn process_initial_files(
 				&raw,
 				&config.input_dir,
 				&config.output_dir,
+				bundles,
 			);
+			if config.fingerprint_assets {
+				assets::write_fingerprinted(
+					&raw,
+					&config.input_dir,
+					&config.output_dir,
+					input_output_map,
+					bundles,
+				);
+				assets::write_manifest(
+					&config.input_dir,
+					&config.output_dir,
+					input_output_map,
+				);
+			}
 		});
 		if config.serial {
 			handle.join().unwrap_or_else(|e| {
@@ -437,6 +659,9 @@ fn process_initial_files(
 			});
 		}
 		file_count += input_files.raw.len();
+		if config.fingerprint_assets {
+			file_count += 1;
+		}
 
 		for (tag, entries) in tags {
 			let tags_file = PathBuf::from("tags")
@@ -457,7 +682,7 @@ fn process_initial_files(
 					&config.output_dir,
 					input_output_map,
 					groups,
-					&make_site_info(config),
+					&make_site_info(config, state),
 				);
 			});
 			if config.serial {
@@ -468,54 +693,117 @@ fn process_initial_files(
 		}
 		file_count += tags.len();
 
+		for period in archives.keys() {
+			if config.single_file.is_some()
+				&& config.single_file.as_deref()
+					!= Some(&period.join("index.html"))
+			{
+				continue;
+			}
+
+			processed_single = true;
+			let handle = s.spawn(move |_| {
+				markdown::generate_archive_file(
+					period,
+					archives,
+					&config.input_dir,
+					&config.output_dir,
+					input_output_map,
+					groups,
+					&make_site_info(config, state),
+				);
+			});
+			if config.serial {
+				handle.join().unwrap_or_else(|e| {
+					panic!("Failed joining on thread: {:?}", e)
+				});
+			}
+		}
+		file_count += archives.len();
+
 		if let Some(single_file) = &config.single_file {
 			if !processed_single {
 				panic!("Failed finding single file: {}", single_file.display());
 			}
 		}
 
-		let handle = s.spawn(|_| {
-			let sitemap_url = robots::write_sitemap_xml(
-				&config.output_dir,
-				&config.base_url,
-				input_output_map,
-			);
-			robots::write_robots_txt(&config.output_dir, &sitemap_url);
-		});
-		if config.serial {
-			handle.join().unwrap_or_else(|e| {
-				panic!("Failed joining on thread: {:?}", e)
-			});
-		}
-		file_count += 2;
-
 		for handle in feed_map_writers {
 			handle.join().unwrap_or_else(|e| {
 				panic!("Failed joining on thread: {:?}", e)
 			});
 		}
-		atom::generate(
+		feed::generate(
 			Arc::try_unwrap(feed_map)
 				.unwrap_or_else(|_arc: Arc<_>| panic!("Failed unwrapping Arc"))
 				.into_inner()
 				.unwrap_or_else(|e| {
 					panic!("Failed acquiring feed map read-lock: {}", e)
 				}),
-			&config.output_dir,
-			&config.base_url,
-			&config.author,
-			&config.email,
-			&config.title,
+			tags,
+			config,
 		);
 		file_count += 1;
 	})
 	.unwrap_or_else(|e| panic!("Crossbeam scope failed: {:?}", e));
 
+	// Once all pages are rendered, as any of them may have highlighted code.
+	if state.highlighted.load(Ordering::Relaxed) {
+		highlight::write_stylesheet(&config.input_dir, &config.output_dir);
+		file_count += 1;
+	}
+
+	// Pages showing their backlinks are only up to date once all links are
+	// known, so they are rendered again before anything reads the output.
+	let stale = state.links.apply(input_output_map);
+	backlinks::rerender(&stale, input_output_map, groups, config, state);
+
+	// Only complete when processing all files.
+	if let (Some(search_index), None) =
+		(&config.search_index, &config.single_file)
+	{
+		search::write_index(
+			&state.search_entries,
+			&config.output_dir.join(search_index),
+		);
+		file_count += 1;
+	}
+
+	// After all pages are generated, to find the images on them.
+	let sitemap_url = robots::write_sitemap_xml(
+		&config.output_dir,
+		&config.base_url,
+		input_output_map,
+		tags,
+		archives,
+	);
+	robots::write_robots_txt(
+		&config.output_dir,
+		&sitemap_url,
+		&config.robots,
+		config.staging,
+	);
+	file_count += 2;
+
 	println!(
 		"Processed {} files in {} ms.",
 		file_count,
 		timer.elapsed().as_millis()
-	)
+	);
+	if config.minify {
+		println!(
+			"Minifying HTML saved {} bytes.",
+			minify::total_savings(&state.minified_bytes)
+		)
+	}
+	if config.precompress {
+		let timer = Instant::now();
+		let count = precompress::run(&config.output_dir, &state.precompressed);
+		println!(
+			"Precompressed {} files in {} ms.",
+			count,
+			timer.elapsed().as_millis()
+		)
+	}
 }
 
 fn checked_insert(
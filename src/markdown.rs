@@ -1,7 +1,7 @@
-use std::collections::{hash_map::Entry, BTreeMap, HashMap};
+use std::collections::{hash_map::Entry, HashMap};
 use std::ffi::OsStr;
 use std::fs;
-use std::io::{BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::io::{BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -9,8 +9,21 @@ use std::time::Instant;
 
 use pulldown_cmark::{html, Options, Parser};
 
+use crate::admonitions;
+use crate::archive;
+use crate::footnotes;
 use crate::front_matter::FrontMatter;
+use crate::headings;
+use crate::headings::Heading;
+use crate::highlight;
+use crate::image;
+use crate::links;
 use crate::liquid;
+use crate::math;
+use crate::minify;
+use crate::neighbours;
+use crate::neighbours::Neighbours;
+use crate::smart_punctuation;
 use crate::util;
 use crate::util::{strip_prefix, SiteInfo};
 
@@ -18,6 +31,18 @@ use crate::util::{strip_prefix, SiteInfo};
 pub struct GroupedOptionOutputFile {
 	pub file: OptionOutputFile,
 	pub group: Option<String>,
+	// Input paths of the pages linking to this one.
+	pub backlinks: Vec<PathBuf>,
+	pub neighbours: Neighbours,
+}
+
+// Entries of each group, tag and archive period, listed on the pages generated
+// for them.
+#[derive(Default)]
+pub struct Indexes {
+	pub groups: HashMap<String, Vec<InputFile>>,
+	pub tags: HashMap<String, Vec<InputFile>>,
+	pub archives: HashMap<PathBuf, Vec<InputFile>>,
 }
 
 pub struct ComputedTemplatePath {
@@ -34,6 +59,46 @@ pub struct GeneratedFile {
 	pub file: OutputFile,
 	pub group: Option<String>,
 	pub html_content: String,
+	pub excerpt: String,
+	pub headings: Vec<Heading>,
+}
+
+// CommonMark extensions, configured site-wide and optionally overridden per
+// page.
+#[derive(Clone, Copy)]
+pub struct Extensions {
+	pub footnotes: bool,
+	// Site-wide only, adds a link to itself in each heading.
+	pub heading_anchors: bool,
+	pub math: bool,
+	pub smart_punctuation: bool,
+	pub strikethrough: bool,
+	pub tasklists: bool,
+}
+
+impl Extensions {
+	pub fn for_page(&self, front_matter: &FrontMatter) -> Self {
+		Self {
+			footnotes: front_matter.footnotes.unwrap_or(self.footnotes),
+			heading_anchors: self.heading_anchors,
+			math: front_matter.math.unwrap_or(self.math),
+			smart_punctuation: front_matter
+				.smart_punctuation
+				.unwrap_or(self.smart_punctuation),
+			strikethrough: front_matter
+				.strikethrough
+				.unwrap_or(self.strikethrough),
+			tasklists: front_matter.tasklists.unwrap_or(self.tasklists),
+		}
+	}
+
+	fn options(&self) -> Options {
+		let mut options = Options::ENABLE_TABLES;
+		options.set(Options::ENABLE_FOOTNOTES, self.footnotes);
+		options.set(Options::ENABLE_STRIKETHROUGH, self.strikethrough);
+		options.set(Options::ENABLE_TASKLISTS, self.tasklists);
+		options
+	}
 }
 
 #[derive(Clone)]
@@ -71,6 +136,8 @@ impl GroupedOutputFile {
 				path: self.file.path.clone(),
 			},
 			group: self.group.clone(),
+			backlinks: Vec::new(),
+			neighbours: Neighbours::default(),
 		}
 	}
 }
@@ -257,6 +324,7 @@ pub fn process_file(
 	);
 
 	let timer = Instant::now();
+	site_info.links.clear_from(output_file_path);
 
 	let mut input_file =
 		BufReader::new(fs::File::open(input_file_path).unwrap_or_else(|e| {
@@ -271,22 +339,25 @@ pub fn process_file(
 
 	let mut processed_markdown_content = BufWriter::new(Vec::new());
 
+	let context = liquid::Context {
+		input_file_path,
+		output_file_path,
+		front_matter,
+		html_content: None,
+		headings: &[],
+		root_input_dir,
+		root_output_dir,
+		input_output_map,
+		groups,
+		site_info,
+	};
 	liquid::process(
 		&mut input_file,
 		&mut processed_markdown_content,
 		HashMap::new(),
-		&liquid::Context {
-			input_file_path,
-			output_file_path,
-			front_matter,
-			html_content: None,
-			root_input_dir,
-			root_output_dir,
-			input_output_map,
-			groups,
-			site_info,
-		},
+		&context,
 	);
+	let excerpt = render_excerpt(input_file_path, front_matter, &context);
 
 	let markdown_content = String::from_utf8_lossy(
 		&processed_markdown_content
@@ -299,11 +370,7 @@ pub fn process_file(
 	let template_path_result =
 		compute_template_path(input_file_path, root_input_dir);
 
-	let mut html_content = String::with_capacity(markdown_content.len());
-	html::push_html(
-		&mut html_content,
-		Parser::new_ext(&markdown_content, Options::ENABLE_TABLES),
-	);
+	let (html_content, headings) = render(&markdown_content, &context);
 
 	let mut template_file = BufReader::new(
 		fs::File::open(&template_path_result.path).unwrap_or_else(|e| {
@@ -324,6 +391,7 @@ pub fn process_file(
 			output_file_path,
 			front_matter,
 			html_content: Some(&html_content),
+			headings: &headings,
 			root_input_dir,
 			root_output_dir,
 			input_output_map,
@@ -337,6 +405,7 @@ pub fn process_file(
 			.into_inner()
 			.unwrap_or_else(|e| panic!("Failed unwrapping BufWriter: {}", e)),
 		output_file_path,
+		site_info.minify,
 	);
 
 	println!(
@@ -347,14 +416,20 @@ pub fn process_file(
 		timer.elapsed().as_millis()
 	);
 
-	GeneratedFile {
+	let generated = GeneratedFile {
 		file: OutputFile {
 			front_matter: front_matter.clone(),
 			path: strip_prefix(output_file_path, root_output_dir),
 		},
 		group: template_path_result.group,
 		html_content,
+		excerpt,
+		headings,
+	};
+	if let Some(search) = &site_info.search {
+		search.add(&generated);
 	}
+	generated
 }
 
 pub fn reindex(
@@ -363,12 +438,21 @@ pub fn reindex(
 	input_dir: &PathBuf,
 	output_dir: &PathBuf,
 	input_output_map: &mut HashMap<PathBuf, GroupedOptionOutputFile>,
-	groups: &mut HashMap<String, Vec<InputFile>>,
-	tags: &mut HashMap<String, Vec<InputFile>>,
+	indexes: &mut Indexes,
 	site_info: &SiteInfo,
 ) {
+	let Indexes {
+		groups,
+		tags,
+		archives,
+	} = indexes;
 	let previous = input_output_map
 		.insert(input_file_path.clone(), grouped_file.clone_to_option());
+	if let Some(previous) = &previous {
+		if let Some(entry) = input_output_map.get_mut(input_file_path) {
+			entry.backlinks = previous.backlinks.clone();
+		}
+	}
 
 	if let Some(group) = &grouped_file.group {
 		if match &previous {
@@ -404,6 +488,7 @@ pub fn reindex(
 			entries.retain(|f| &f.path != input_file_path)
 		}
 	}
+	neighbours::update(input_output_map, groups, site_info.related_limit);
 
 	for tag in &grouped_file.file.front_matter.tags {
 		if let Some(previous) = &previous {
@@ -428,20 +513,16 @@ pub fn reindex(
 					GroupedOptionOutputFile {
 						file: OptionOutputFile {
 							path: output_dir.join(&tags_file),
-							front_matter: Some(Arc::new(FrontMatter {
-								title: format!("Tag: {}", tag),
-								date: None,
-								published: true,
-								edited: None,
-								categories: Vec::new(),
-								tags: Vec::new(),
-								layout: None,
-								custom_attributes: BTreeMap::new(),
-								end_position: 0,
-								subsequent_line: 1,
-							})),
+							front_matter: Some(Arc::new(
+								FrontMatter::with_title(format!(
+									"Tag: {}",
+									tag
+								)),
+							)),
 						},
 						group: None,
+						backlinks: Vec::new(),
+						neighbours: Neighbours::default(),
 					},
 				);
 				assert!(prev.is_none());
@@ -469,6 +550,110 @@ pub fn reindex(
 		);
 	}
 
+	if archive::enabled(input_dir) {
+		let periods = if grouped_file.group.is_some() {
+			archive::get_periods(
+				input_file_path,
+				input_dir,
+				&grouped_file.file.front_matter,
+			)
+		} else {
+			Vec::new()
+		};
+		// Added or removed periods change the previous and next links of the
+		// others.
+		let mut reshaped = false;
+
+		// The date or group of the file may have changed, so it is removed
+		// from all periods before being listed again.
+		let mut changed = Vec::new();
+		for (period, entries) in archives.iter_mut() {
+			let count = entries.len();
+			entries.retain(|f| &f.path != input_file_path);
+			if entries.len() != count || periods.contains(period) {
+				changed.push(period.clone());
+			}
+		}
+		for period in &periods {
+			let file = InputFile {
+				front_matter: grouped_file.file.front_matter.clone(),
+				path: input_file_path.clone(),
+			};
+			match archives.entry(period.clone()) {
+				Entry::Vacant(_)
+					if archive::collides(
+						period,
+						input_dir,
+						output_dir,
+						input_output_map,
+					) => {}
+				Entry::Vacant(ve) => {
+					let prev = input_output_map.insert(
+						archive::make_input_path(input_dir, period),
+						GroupedOptionOutputFile {
+							file: OptionOutputFile {
+								path: archive::make_output_path(
+									output_dir, period,
+								),
+								front_matter: Some(Arc::new(
+									archive::make_front_matter(period),
+								)),
+							},
+							group: None,
+							backlinks: Vec::new(),
+							neighbours: Neighbours::default(),
+						},
+					);
+					assert!(prev.is_none());
+					ve.insert(vec![file]);
+					changed.push(period.clone());
+					reshaped = true;
+				}
+				Entry::Occupied(oe) => {
+					let entries = oe.into_mut();
+					entries.push(file);
+					entries.sort_by(|lhs, rhs| {
+						rhs.front_matter.date.cmp(&lhs.front_matter.date)
+					});
+				}
+			}
+		}
+
+		changed.retain(|period| {
+			if !archives[period].is_empty() {
+				return true;
+			}
+			archives.remove(period);
+			reshaped = true;
+			input_output_map
+				.remove(&archive::make_input_path(input_dir, period));
+			let output_file_path =
+				archive::make_output_path(output_dir, period);
+			fs::remove_file(&output_file_path).unwrap_or_else(|e| {
+				panic!(
+					"Failed removing emptied archive page {}: {}",
+					output_file_path.display(),
+					e
+				)
+			});
+			false
+		});
+		if reshaped {
+			changed = archives.keys().cloned().collect();
+		}
+		for period in &changed {
+			generate_archive_file(
+				period,
+				archives,
+				input_dir,
+				output_dir,
+				input_output_map,
+				groups,
+				site_info,
+			);
+		}
+	}
+
 	// TODO: Remove when tags are removed?
 }
 
@@ -488,6 +673,7 @@ pub fn process_template_file(
 	);
 
 	let timer = Instant::now();
+	site_info.links.clear_from(output_file_path);
 
 	let mut input_file =
 		BufReader::new(fs::File::open(input_file_path).unwrap_or_else(|e| {
@@ -511,6 +697,7 @@ pub fn process_template_file(
 			output_file_path,
 			front_matter,
 			html_content: None,
+			headings: &[],
 			root_input_dir,
 			root_output_dir,
 			input_output_map,
@@ -519,12 +706,24 @@ pub fn process_template_file(
 		},
 	);
 
-	write_buffer_to_file(
-		&output_buf
-			.into_inner()
-			.unwrap_or_else(|e| panic!("Failed unwrapping BufWriter: {}", e)),
-		output_file_path,
-	);
+	let output = output_buf
+		.into_inner()
+		.unwrap_or_else(|e| panic!("Failed unwrapping BufWriter: {}", e));
+	if let Some(search) = &site_info.search {
+		search.add(&GeneratedFile {
+			file: OutputFile {
+				front_matter: front_matter.clone(),
+				path: strip_prefix(output_file_path, root_output_dir),
+			},
+			group: input_output_map
+				.get(input_file_path)
+				.and_then(|output_file| output_file.group.clone()),
+			html_content: String::from_utf8_lossy(&output).to_string(),
+			excerpt: String::new(),
+			headings: Vec::new(),
+		});
+	}
+	write_buffer_to_file(&output, output_file_path, site_info.minify);
 
 	println!(
 		"Processed markdown-less {} to {} in {} ms.",
@@ -543,13 +742,99 @@ pub fn generate_tag_file(
 	groups: &HashMap<String, Vec<InputFile>>,
 	site_info: &SiteInfo,
 ) {
+	let timer = Instant::now();
+
+	let output_file_path = generate_virtual_file(
+		input_file_path,
+		Listing {
+			template_file: root_input_dir.join("_layouts/tag.html"),
+			entries,
+			variables: HashMap::new(),
+		},
+		root_input_dir,
+		root_output_dir,
+		input_output_map,
+		groups,
+		site_info,
+	);
+
+	println!(
+		"Generated tags file {} in {} ms.",
+		output_file_path.display(),
+		timer.elapsed().as_millis(),
+	);
+}
+
+pub fn generate_archive_file(
+	period: &Path,
+	archives: &HashMap<PathBuf, Vec<InputFile>>,
+	root_input_dir: &PathBuf,
+	root_output_dir: &PathBuf,
+	input_output_map: &HashMap<PathBuf, GroupedOptionOutputFile>,
+	groups: &HashMap<String, Vec<InputFile>>,
+	site_info: &SiteInfo,
+) {
+	let timer = Instant::now();
+
+	let entries = archives.get(period).unwrap_or_else(|| {
+		panic!("Failed finding archive period {}.", period.display())
+	});
+	let (previous, next) = archive::get_neighbours(period, archives);
+
+	let (_, year, month) = archive::split_period(period);
+
+	let mut variables = HashMap::new();
+	for (name, neighbour) in &[("previous", previous), ("next", next)] {
+		variables.insert(name.to_string(), make_period_value(*neighbour));
+	}
+	variables.insert("year".to_string(), liquid::Value::String(year));
+	variables.insert("month".to_string(), liquid::Value::String(month));
+
+	let output_file_path = generate_virtual_file(
+		&archive::make_input_path(root_input_dir, period),
+		Listing {
+			template_file: root_input_dir.join(archive::TEMPLATE_PATH),
+			entries,
+			variables,
+		},
+		root_input_dir,
+		root_output_dir,
+		input_output_map,
+		groups,
+		site_info,
+	);
+
+	println!(
+		"Generated archive file {} in {} ms.",
+		output_file_path.display(),
+		timer.elapsed().as_millis(),
+	);
+}
+
+// Entries listed on a virtual page through a template, along with any other
+// variables of the template.
+struct Listing<'a> {
+	template_file: PathBuf,
+	entries: &'a [InputFile],
+	variables: HashMap<String, liquid::Value>,
+}
+
+// Renders a page without a backing input file (such as tag and archive pages)
+// from the listing, returning the output path.
+fn generate_virtual_file<'a>(
+	input_file_path: &PathBuf,
+	listing: Listing,
+	root_input_dir: &PathBuf,
+	root_output_dir: &PathBuf,
+	input_output_map: &'a HashMap<PathBuf, GroupedOptionOutputFile>,
+	groups: &HashMap<String, Vec<InputFile>>,
+	site_info: &SiteInfo,
+) -> &'a PathBuf {
 	assert_eq!(
 		input_file_path.extension(),
 		Some(OsStr::new(util::HTML_EXTENSION))
 	);
 
-	let timer = Instant::now();
-
 	let grouped_file =
 		input_output_map.get(input_file_path).unwrap_or_else(|| {
 			panic!(
@@ -559,7 +844,11 @@ pub fn generate_tag_file(
 			)
 		});
 
-	let template_file = root_input_dir.join("_layouts/tag.html");
+	let Listing {
+		template_file,
+		entries,
+		variables: mut outer_variables,
+	} = listing;
 	let mut input_file =
 		BufReader::new(fs::File::open(&template_file).unwrap_or_else(|e| {
 			panic!("Failed opening \"{}\": {}.", &template_file.display(), e)
@@ -576,35 +865,25 @@ pub fn generate_tag_file(
 
 	let mut output_buf = BufWriter::new(Vec::new());
 
-	let mut entries_as_values = Vec::new();
-	for entry in entries {
-		let mut map = HashMap::new();
-		let mut link = String::from("/");
-		link.push_str(
-			&strip_prefix(&entry.path, root_input_dir).to_string_lossy(),
-		);
-		map.insert("link", liquid::Value::String(link));
-		map.insert(
-			"title",
-			liquid::Value::String(entry.front_matter.title.clone()),
-		);
-		map.insert(
-			"date",
-			liquid::Value::String(
-				entry
-					.front_matter
-					.date
-					.as_ref()
-					.map_or_else(String::new, String::clone),
-			),
-		);
-		entries_as_values.push(liquid::Value::Dictionary { map });
-	}
-	let mut outer_variables = HashMap::new();
+	let context = liquid::Context {
+		input_file_path: &template_file,
+		output_file_path,
+		front_matter,
+		html_content: None,
+		headings: &[],
+		root_input_dir,
+		root_output_dir,
+		input_output_map,
+		groups,
+		site_info,
+	};
 	outer_variables.insert(
 		"entries".to_string(),
 		liquid::Value::List {
-			values: entries_as_values,
+			values: entries
+				.iter()
+				.map(|entry| liquid::make_entry_value(entry, &context))
+				.collect(),
 		},
 	);
 
@@ -612,17 +891,7 @@ pub fn generate_tag_file(
 		&mut input_file,
 		&mut output_buf,
 		outer_variables,
-		&liquid::Context {
-			input_file_path: &template_file,
-			output_file_path,
-			front_matter,
-			html_content: None,
-			root_input_dir,
-			root_output_dir,
-			input_output_map,
-			groups,
-			site_info,
-		},
+		&context,
 	);
 
 	write_buffer_to_file(
@@ -630,16 +899,50 @@ pub fn generate_tag_file(
 			.into_inner()
 			.unwrap_or_else(|e| panic!("Failed unwrapping BufWriter: {}", e)),
 		output_file_path,
+		site_info.minify,
 	);
 
-	println!(
-		"Generated tags file {} in {} ms.",
-		output_file_path.display(),
-		timer.elapsed().as_millis(),
-	);
+	output_file_path
 }
 
-fn write_buffer_to_file(buffer: &[u8], path: &PathBuf) {
+// Missing periods become empty dictionaries so that templates can test for
+// them through "count". Like the links of entries, the link is the path of the
+// virtual input, to pass through {% link %}.
+fn make_period_value(period: Option<&PathBuf>) -> liquid::Value {
+	let mut map = HashMap::new();
+	if let Some(period) = period {
+		let mut link = String::from("/");
+		link.push_str(&period.join("index.html").to_string_lossy());
+		map.insert("link", liquid::Value::String(link));
+		map.insert(
+			"title",
+			liquid::Value::String(archive::make_front_matter(period).title),
+		);
+	}
+	liquid::Value::Dictionary { map }
+}
+
+fn write_buffer_to_file(
+	buffer: &[u8],
+	path: &PathBuf,
+	minify: Option<&minify::Savings>,
+) {
+	let minified;
+	let buffer = match minify {
+		Some(savings)
+			if path.extension() == Some(OsStr::new(util::HTML_EXTENSION)) =>
+		{
+			minified = minify::minify_html(&String::from_utf8_lossy(buffer));
+			minify::record_saving(
+				savings,
+				path,
+				buffer.len().saturating_sub(minified.len()),
+			);
+			minified.as_bytes()
+		}
+		_ => buffer,
+	};
+
 	let closest_output_dir = path.parent().unwrap_or_else(|| {
 		panic!(
 			"Output file path without a parent directory?: {}",
@@ -671,6 +974,7 @@ pub fn parse_fm_and_compute_output_path(
 	input_file_path: &PathBuf,
 	root_input_dir: &PathBuf,
 	root_output_dir: &PathBuf,
+	excerpt_separator: &str,
 ) -> GroupedOutputFile {
 	let mut path = root_output_dir.clone();
 	if input_file_path.starts_with(root_input_dir) {
@@ -705,8 +1009,25 @@ pub fn parse_fm_and_compute_output_path(
 			panic!("Failed opening \"{}\": {}.", &input_file_path.display(), e)
 		}));
 
-	let front_matter =
-		Arc::new(crate::front_matter::parse(input_file_path, &mut input_file));
+	let mut front_matter =
+		crate::front_matter::parse(input_file_path, &mut input_file);
+	if front_matter.excerpt.is_none()
+		&& input_file_path.extension()
+			== Some(OsStr::new(util::MARKDOWN_EXTENSION))
+	{
+		input_file
+			.seek(SeekFrom::Start(front_matter.end_position))
+			.unwrap_or_else(|e| {
+				panic!("Failed seeking in {}: {}", input_file_path.display(), e)
+			});
+		let mut content = String::new();
+		input_file.read_to_string(&mut content).unwrap_or_else(|e| {
+			panic!("Failed reading {}: {}", input_file_path.display(), e)
+		});
+		front_matter.excerpt =
+			Some(extract_excerpt(&content, excerpt_separator, root_input_dir));
+	}
+	let front_matter = Arc::new(front_matter);
 
 	let mut group = None;
 	let input_file_parent = input_file_path
@@ -732,6 +1053,187 @@ pub fn parse_fm_and_compute_output_path(
 	}
 }
 
+pub fn to_html(markdown_content: &str, context: &liquid::Context) -> String {
+	render(markdown_content, context).0
+}
+
+// Returns the HTML along with the headings found in it.
+pub fn render(
+	markdown_content: &str,
+	context: &liquid::Context,
+) -> (String, Vec<Heading>) {
+	let extensions = context
+		.site_info
+		.markdown_extensions
+		.for_page(context.front_matter);
+	let (markdown_content, inserted_lines) = admonitions::expand_containers(
+		markdown_content,
+		context.site_info.admonitions,
+	);
+	let parser = Parser::new_ext(&markdown_content, extensions.options());
+	let events: Vec<_> = if extensions.math {
+		math::convert(
+			parser.into_offset_iter().collect(),
+			&markdown_content,
+			|offset, tex| {
+				let line = markdown_content[..offset].matches('\n').count();
+				let inserted =
+					inserted_lines.iter().filter(|&&l| l < line).count();
+				format!(
+					"{}:{}",
+					context.input_file_path.display(),
+					math::find_source_line(
+						context.input_file_path,
+						tex,
+						context.front_matter.subsequent_line + line - inserted
+					)
+				)
+			},
+		)
+	} else {
+		parser.collect()
+	};
+	let events = links::resolve(events, context);
+	let events = image::convert_markdown_images(events, context);
+	let events: Vec<_> = if extensions.smart_punctuation {
+		smart_punctuation::apply(events.into_iter()).collect()
+	} else {
+		events
+	};
+	let events =
+		admonitions::convert_blockquotes(events, context.site_info.admonitions);
+	let (events, headings) =
+		headings::assign_ids(events, extensions.heading_anchors);
+	let events =
+		highlight::highlight_code_blocks(events, context.site_info.highlighted);
+
+	if extensions.footnotes {
+		return (footnotes::render(events.into_iter()), headings);
+	}
+
+	let mut html_content = String::with_capacity(markdown_content.len());
+	html::push_html(&mut html_content, events.into_iter());
+	(html_content, headings)
+}
+
+// Takes everything up until the separator, or the first paragraph if there is
+// no separator, skipping any leading headings. Blank lines inside fenced code
+// and Liquid blocks don't end the paragraph.
+pub fn extract_excerpt(
+	content: &str,
+	separator: &str,
+	root_input_dir: &Path,
+) -> String {
+	if !separator.is_empty() {
+		if let Some(end) = content.find(separator) {
+			return content[..end].trim().to_string();
+		}
+	}
+
+	let mut paragraph = String::new();
+	let mut fence = None;
+	let mut depth = 0;
+	for line in content.lines() {
+		let nested = fence.is_some() || depth > 0;
+		if line.trim().is_empty() && !nested {
+			if paragraph.is_empty() {
+				continue;
+			}
+			break;
+		}
+		if paragraph.is_empty() && !nested && line.starts_with('#') {
+			continue;
+		}
+		paragraph.push_str(line);
+		paragraph.push('\n');
+
+		let marker = fence_marker(line);
+		fence = match (fence, marker) {
+			(None, Some(marker)) => Some(marker),
+			(Some(open), Some(marker))
+				if marker.starts_with(open) && line.trim() == marker =>
+			{
+				None
+			}
+			(fence, _) => fence,
+		};
+		depth = liquid::block_depth(line, depth, root_input_dir);
+	}
+	paragraph
+}
+
+// The run of backticks or tildes opening or closing fenced code.
+fn fence_marker(line: &str) -> Option<&str> {
+	let trimmed = line.trim_start();
+	let c = trimmed.chars().next().filter(|&c| c == '`' || c == '~')?;
+	let length = trimmed.len() - trimmed.trim_start_matches(c).len();
+	if length >= 3 {
+		Some(&trimmed[..length])
+	} else {
+		None
+	}
+}
+
+// Excerpts are processed in the context of the page displaying them so that
+// relative links stay correct, once per directory.
+pub fn render_excerpt(
+	input_file_path: &PathBuf,
+	front_matter: &FrontMatter,
+	context: &liquid::Context,
+) -> String {
+	let excerpt = if let Some(excerpt) = &front_matter.excerpt {
+		excerpt
+	} else {
+		return String::new();
+	};
+	let dir = context
+		.output_file_path
+		.parent()
+		.unwrap_or_else(|| {
+			panic!(
+				"Failed getting parent of {}",
+				context.output_file_path.display()
+			)
+		})
+		.to_path_buf();
+	let lock_rendered = || {
+		front_matter
+			.excerpt_html
+			.lock()
+			.unwrap_or_else(|e| panic!("Failed acquiring excerpt lock: {}", e))
+	};
+	if let Some(html) = lock_rendered().get(&dir) {
+		return html.clone();
+	}
+
+	let mut processed_excerpt = BufWriter::new(Vec::new());
+	let excerpt_context = liquid::Context {
+		input_file_path,
+		front_matter,
+		html_content: None,
+		headings: &[],
+		..*context
+	};
+	liquid::process(
+		&mut BufReader::new(Cursor::new(excerpt.as_bytes())),
+		&mut processed_excerpt,
+		HashMap::new(),
+		&excerpt_context,
+	);
+
+	// Not holding the lock while rendering, as excerpts may list others.
+	let html = to_html(
+		&String::from_utf8_lossy(
+			&processed_excerpt
+				.into_inner()
+				.unwrap_or_else(|e| panic!("into_inner() failed: {}", e)),
+		),
+		&excerpt_context,
+	);
+	lock_rendered().insert(dir, html.clone());
+	html
+}
+
 fn compute_template_path(
 	input_file_path: &Path,
 	root_input_dir: &PathBuf,
@@ -0,0 +1,858 @@
+// Converts $inline$ and $$display$$ TeX math in markdown text to MathML at
+// generation time, so pages don't need a client-side math library. Only a
+// commonly used subset of TeX is understood, anything else is reported as an
+// error rather than rendered wrongly.
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+use pulldown_cmark::{CowStr, Event, Tag};
+
+use crate::util::escape_html;
+
+// Pieces of text as the markdown parser split them up. Backslash escaped
+// characters start a new piece, with the backslash left out of its source.
+// Entities come as separate pieces whose source differs from the text.
+struct Piece<'a> {
+	text: CowStr<'a>,
+	source: &'a str,
+	offset: usize,
+	escaped: bool,
+	soft_break: bool,
+}
+
+impl<'a> Piece<'a> {
+	fn is_entity(&self) -> bool {
+		!self.soft_break && *self.text != *self.source
+	}
+}
+
+struct Unit {
+	c: char,
+	piece: usize,
+	// Escaped or part of an entity, so not a math delimiter.
+	literal: bool,
+	escaped: bool,
+	offset: usize,
+}
+
+// Replaces math in runs of text events with inline HTML. Offsets of the events
+// must refer to markdown_content, location turns them along with the offending
+// TeX into a description for error messages.
+pub fn convert<'a, L: Fn(usize, &str) -> String>(
+	events: Vec<(Event<'a>, Range<usize>)>,
+	markdown_content: &'a str,
+	location: L,
+) -> Vec<Event<'a>> {
+	let mut result = Vec::with_capacity(events.len());
+	let mut run = Vec::new();
+	let mut in_code_block = false;
+	for (event, range) in events {
+		match event {
+			Event::Text(text) if !in_code_block => run.push(Piece {
+				text,
+				source: &markdown_content[range.clone()],
+				offset: range.start,
+				escaped: markdown_content[..range.start].ends_with('\\'),
+				soft_break: false,
+			}),
+			Event::SoftBreak if !in_code_block => run.push(Piece {
+				text: CowStr::from("\n"),
+				source: "\n",
+				offset: range.start,
+				escaped: false,
+				soft_break: true,
+			}),
+			_ => {
+				convert_run(&mut run, &mut result, &location);
+				match event {
+					Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+					Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+					_ => {}
+				}
+				result.push(event);
+			}
+		}
+	}
+	convert_run(&mut run, &mut result, &location);
+	result
+}
+
+fn convert_run<'a, L: Fn(usize, &str) -> String>(
+	run: &mut Vec<Piece<'a>>,
+	result: &mut Vec<Event<'a>>,
+	location: &L,
+) {
+	if !run
+		.iter()
+		.any(|piece| !piece.is_entity() && piece.text.contains('$'))
+	{
+		result.extend(run.drain(..).map(|piece| {
+			if piece.soft_break {
+				Event::SoftBreak
+			} else {
+				Event::Text(piece.text)
+			}
+		}));
+		return;
+	}
+
+	let mut units = Vec::new();
+	for (index, piece) in run.iter().enumerate() {
+		for (byte_index, c) in piece.text.char_indices() {
+			let escaped = piece.escaped && byte_index == 0;
+			units.push(Unit {
+				c,
+				piece: index,
+				literal: escaped || piece.is_entity(),
+				escaped,
+				offset: if piece.is_entity() {
+					piece.offset
+				} else {
+					piece.offset + byte_index
+				},
+			});
+		}
+	}
+
+	let is_dollar = |i: usize| {
+		units
+			.get(i)
+			.is_some_and(|unit: &Unit| unit.c == '$' && !unit.literal)
+	};
+
+	let mut text = String::new();
+	let mut i = 0;
+	while i < units.len() {
+		let unit = &units[i];
+		if is_dollar(i) {
+			let display = is_dollar(i + 1);
+			let end = if display {
+				(i + 2..units.len()).find(|&j| is_dollar(j) && is_dollar(j + 1))
+			} else {
+				find_inline_end(&units, i, &is_dollar)
+			};
+			if let Some(end) = end {
+				let start = if display { i + 2 } else { i + 1 };
+				let tex = collect_tex(&units[start..end]);
+				let mathml = to_mathml(&tex, display).unwrap_or_else(|e| {
+					panic!(
+						"Failed converting math \"{}\": {} Location: {}.",
+						tex.trim(),
+						e,
+						location(unit.offset, tex.trim())
+					)
+				});
+				flush_text(&mut text, result);
+				result.push(Event::Html(CowStr::from(mathml)));
+				i = if display { end + 2 } else { end + 1 };
+				continue;
+			}
+		}
+
+		if run[unit.piece].soft_break {
+			flush_text(&mut text, result);
+			result.push(Event::SoftBreak);
+		} else {
+			text.push(unit.c);
+		}
+		i += 1;
+	}
+	flush_text(&mut text, result);
+	run.clear();
+}
+
+// Inline math needs non-whitespace just inside both dollars, and the closing
+// one may not be followed by a digit, so that amounts such as $5 and $10 are
+// left alone.
+fn find_inline_end<D: Fn(usize) -> bool>(
+	units: &[Unit],
+	start: usize,
+	is_dollar: &D,
+) -> Option<usize> {
+	if units
+		.get(start + 1)
+		.is_none_or(|unit| unit.c.is_whitespace())
+	{
+		return None;
+	}
+	let end = (start + 2..units.len()).find(|&j| is_dollar(j))?;
+	if units[end - 1].c.is_whitespace()
+		|| units
+			.get(end + 1)
+			.is_some_and(|unit| unit.c.is_ascii_digit())
+	{
+		return None;
+	}
+	Some(end)
+}
+
+// Line numbers computed from the markdown drift when Liquid changes the number
+// of lines above the math, so the TeX is looked up in the source file instead,
+// taking the occurrence closest to the computed line.
+pub fn find_source_line(
+	input_file_path: &Path,
+	tex: &str,
+	line: usize,
+) -> usize {
+	let source = match fs::read_to_string(input_file_path) {
+		Ok(source) if !tex.is_empty() => source,
+		_ => return line,
+	};
+	source
+		.match_indices(tex)
+		.map(|(index, _)| source[..index].matches('\n').count() + 1)
+		.min_by_key(|source_line| source_line.abs_diff(line))
+		.unwrap_or(line)
+}
+
+// Puts back the backslashes of escaped characters, since \{ means something
+// else in TeX than in markdown.
+fn collect_tex(units: &[Unit]) -> String {
+	let mut tex = String::new();
+	for unit in units {
+		if unit.escaped {
+			tex.push('\\');
+		}
+		tex.push(unit.c);
+	}
+	tex
+}
+
+fn flush_text<'a>(text: &mut String, result: &mut Vec<Event<'a>>) {
+	if !text.is_empty() {
+		result.push(Event::Text(CowStr::from(std::mem::take(text))));
+	}
+}
+
+pub fn to_mathml(tex: &str, display: bool) -> Result<String, String> {
+	let mut parser = Parser {
+		chars: tex.chars().collect(),
+		position: 0,
+		display,
+	};
+	let body = parser.parse_row(false)?;
+	Ok(format!(
+		"<math xmlns=\"http://www.w3.org/1998/Math/MathML\"{}><semantics><mrow>{}</mrow><annotation encoding=\"application/x-tex\">{}</annotation></semantics></math>",
+		if display { " display=\"block\"" } else { "" },
+		body,
+		escape_html(tex.trim())
+	))
+}
+
+struct Parser {
+	chars: Vec<char>,
+	position: usize,
+	display: bool,
+}
+
+// A parsed element, large operators take their limits above and below in
+// display mode.
+struct Element {
+	mathml: String,
+	large_operator: bool,
+}
+
+impl Element {
+	fn new(mathml: String) -> Self {
+		Self {
+			mathml,
+			large_operator: false,
+		}
+	}
+}
+
+impl Parser {
+	fn peek(&self) -> Option<char> {
+		self.chars.get(self.position).copied()
+	}
+
+	fn skip_whitespace(&mut self) {
+		while self.peek().is_some_and(char::is_whitespace) {
+			self.position += 1;
+		}
+	}
+
+	fn parse_row(&mut self, in_group: bool) -> Result<String, String> {
+		let mut elements: Vec<Element> = Vec::new();
+		loop {
+			self.skip_whitespace();
+			let c = match self.peek() {
+				Some(c) => c,
+				None if in_group => {
+					return Err("Missing closing }.".to_string())
+				}
+				None => break,
+			};
+			match c {
+				'}' if in_group => {
+					self.position += 1;
+					break;
+				}
+				'}' => return Err("Unexpected }.".to_string()),
+				'^' | '_' => {
+					let base = elements.pop().unwrap_or_else(|| {
+						Element::new("<mrow></mrow>".to_string())
+					});
+					elements.push(Element::new(self.parse_scripts(base)?));
+				}
+				_ => elements.push(self.parse_element()?),
+			}
+		}
+		Ok(elements.into_iter().map(|element| element.mathml).collect())
+	}
+
+	fn parse_scripts(&mut self, base: Element) -> Result<String, String> {
+		let mut subscript = None;
+		let mut superscript = None;
+		loop {
+			self.skip_whitespace();
+			let script = match self.peek() {
+				Some('_') => &mut subscript,
+				Some('^') => &mut superscript,
+				_ => break,
+			};
+			if script.is_some() {
+				return Err("Double subscript or superscript.".to_string());
+			}
+			self.position += 1;
+			*script = Some(self.parse_argument()?);
+		}
+
+		let limits = base.large_operator && self.display;
+		Ok(match (subscript, superscript) {
+			(Some(sub), Some(sup)) => format!(
+				"<{0}>{1}{2}{3}</{0}>",
+				if limits { "munderover" } else { "msubsup" },
+				base.mathml,
+				sub,
+				sup
+			),
+			(Some(sub), None) => format!(
+				"<{0}>{1}{2}</{0}>",
+				if limits { "munder" } else { "msub" },
+				base.mathml,
+				sub
+			),
+			(None, Some(sup)) => format!(
+				"<{0}>{1}{2}</{0}>",
+				if limits { "mover" } else { "msup" },
+				base.mathml,
+				sup
+			),
+			(None, None) => base.mathml,
+		})
+	}
+
+	// The argument of a command or script: a group or a single token.
+	fn parse_argument(&mut self) -> Result<String, String> {
+		self.skip_whitespace();
+		match self.peek() {
+			Some('{') => {
+				self.position += 1;
+				Ok(format!("<mrow>{}</mrow>", self.parse_row(true)?))
+			}
+			Some('}') | Some('^') | Some('_') | None => {
+				Err("Missing argument.".to_string())
+			}
+			Some(c) if c.is_ascii_digit() => {
+				self.position += 1;
+				Ok(format!("<mn>{}</mn>", c))
+			}
+			Some(_) => Ok(self.parse_element()?.mathml),
+		}
+	}
+
+	// The raw text of a {...} argument.
+	fn parse_text_argument(&mut self) -> Result<String, String> {
+		self.skip_whitespace();
+		if self.peek() != Some('{') {
+			return Err("Expected { after text command.".to_string());
+		}
+		self.position += 1;
+		let mut depth = 0;
+		let mut text = String::new();
+		loop {
+			let c = self
+				.peek()
+				.ok_or_else(|| "Missing closing }.".to_string())?;
+			self.position += 1;
+			match c {
+				'{' => depth += 1,
+				'}' if depth == 0 => return Ok(text),
+				'}' => depth -= 1,
+				_ => {}
+			}
+			text.push(c);
+		}
+	}
+
+	fn parse_element(&mut self) -> Result<Element, String> {
+		let c = self.peek().ok_or_else(|| "Unexpected end.".to_string())?;
+		self.position += 1;
+		if c == '\\' {
+			return self.parse_command();
+		}
+		if c == '{' {
+			return Ok(Element::new(format!(
+				"<mrow>{}</mrow>",
+				self.parse_row(true)?
+			)));
+		}
+		if c.is_ascii_digit() {
+			let mut number = c.to_string();
+			while let Some(next) = self.peek() {
+				let decimal_point = next == '.'
+					&& self
+						.chars
+						.get(self.position + 1)
+						.is_some_and(char::is_ascii_digit);
+				if !next.is_ascii_digit() && !decimal_point {
+					break;
+				}
+				number.push(next);
+				self.position += 1;
+			}
+			return Ok(Element::new(format!("<mn>{}</mn>", number)));
+		}
+		if c.is_alphabetic() {
+			return Ok(Element::new(format!("<mi>{}</mi>", c)));
+		}
+		let operator = match c {
+			'-' => '\u{2212}',
+			'*' => '\u{2217}',
+			'\'' => '\u{2032}',
+			'+' | '=' | '<' | '>' | '(' | ')' | '[' | ']' | '|' | ',' | ';'
+			| ':' | '!' | '/' | '.' | '?' => c,
+			_ => return Err(format!("Unsupported character '{}'.", c)),
+		};
+		Ok(Element::new(make_operator(operator)))
+	}
+
+	fn parse_command(&mut self) -> Result<Element, String> {
+		let mut name = String::new();
+		while let Some(c) = self.peek() {
+			if !c.is_ascii_alphabetic() {
+				break;
+			}
+			name.push(c);
+			self.position += 1;
+		}
+		if name.is_empty() {
+			let c = self
+				.peek()
+				.ok_or_else(|| "Unexpected end after \\.".to_string())?;
+			self.position += 1;
+			return match c {
+				'{' | '}' | '|' | '%' | '$' | '#' | '&' | '_' => {
+					let c = if c == '|' { '\u{2016}' } else { c };
+					Ok(Element::new(make_operator(c)))
+				}
+				',' => Ok(Element::new(make_space("0.1667em"))),
+				':' | '>' => Ok(Element::new(make_space("0.2222em"))),
+				';' => Ok(Element::new(make_space("0.2778em"))),
+				'!' => Ok(Element::new(make_space("-0.1667em"))),
+				' ' => Ok(Element::new(make_space("0.3333em"))),
+				'\\' => {
+					Err("Line breaks (\\\\) are not supported.".to_string())
+				}
+				_ => Err(format!("Unsupported command \\{}.", c)),
+			};
+		}
+
+		if let Some(c) = lookup(GREEK_LETTERS, &name) {
+			let mathml = if c.is_uppercase() {
+				format!("<mi mathvariant=\"normal\">{}</mi>", c)
+			} else {
+				format!("<mi>{}</mi>", c)
+			};
+			return Ok(Element::new(mathml));
+		}
+		if let Some(c) = lookup(SYMBOLS, &name) {
+			return Ok(Element::new(format!("<mi>{}</mi>", c)));
+		}
+		if let Some(c) = lookup(OPERATORS, &name) {
+			return Ok(Element::new(make_operator(c)));
+		}
+		if let Some(c) = lookup(LARGE_OPERATORS, &name) {
+			return Ok(Element {
+				mathml: format!("<mo largeop=\"true\">{}</mo>", c),
+				large_operator: true,
+			});
+		}
+		if FUNCTIONS.contains(&name.as_str()) {
+			return Ok(Element::new(format!("<mi>{}</mi>", name)));
+		}
+		if LIMIT_FUNCTIONS.contains(&name.as_str()) {
+			return Ok(Element {
+				mathml: format!("<mi>{}</mi>", name),
+				large_operator: true,
+			});
+		}
+		if let Some(width) = lookup(SPACES, &name) {
+			return Ok(Element::new(make_space(width)));
+		}
+		if let Some(accent) = lookup(ACCENTS, &name) {
+			return Ok(Element::new(format!(
+				"<mover accent=\"true\">{}<mo>{}</mo></mover>",
+				self.parse_argument()?,
+				accent
+			)));
+		}
+		if let Some((_, upper, lower, digits, exceptions)) = ALPHABETS
+			.iter()
+			.find(|alphabet| alphabet.0 == name.as_str())
+		{
+			let text = self.parse_text_argument()?;
+			let mut mathml = String::new();
+			for c in text.chars().filter(|c| !c.is_whitespace()) {
+				let styled = exceptions
+					.iter()
+					.find(|exception| exception.0 == c)
+					.map(|exception| exception.1)
+					.or_else(|| {
+						let (base, first) = match c {
+							'A'..='Z' => (*upper, 'A'),
+							'a'..='z' => (*lower, 'a'),
+							'0'..='9' => ((*digits)?, '0'),
+							_ => return None,
+						};
+						std::char::from_u32(base + (c as u32 - first as u32))
+					})
+					.ok_or_else(|| {
+						format!("Unsupported character '{}' in \\{}.", c, name)
+					})?;
+				mathml.push_str(&format!("<mi>{}</mi>", styled));
+			}
+			return Ok(Element::new(format!("<mrow>{}</mrow>", mathml)));
+		}
+
+		match name.as_str() {
+			"frac" | "dfrac" | "tfrac" => {
+				let numerator = self.parse_argument()?;
+				let denominator = self.parse_argument()?;
+				Ok(Element::new(format!(
+					"<mfrac>{}{}</mfrac>",
+					numerator, denominator
+				)))
+			}
+			"binom" => {
+				let top = self.parse_argument()?;
+				let bottom = self.parse_argument()?;
+				Ok(Element::new(format!(
+					"<mrow><mo>(</mo><mfrac linethickness=\"0\">{}{}</mfrac><mo>)</mo></mrow>",
+					top, bottom
+				)))
+			}
+			"sqrt" => {
+				self.skip_whitespace();
+				if self.peek() == Some('[') {
+					self.position += 1;
+					let start = self.position;
+					while self.peek().is_some_and(|c| c != ']') {
+						self.position += 1;
+					}
+					if self.peek().is_none() {
+						return Err("Missing closing ].".to_string());
+					}
+					let index: String =
+						self.chars[start..self.position].iter().collect();
+					self.position += 1;
+					let index = Parser {
+						chars: index.chars().collect(),
+						position: 0,
+						display: false,
+					}
+					.parse_row(false)?;
+					Ok(Element::new(format!(
+						"<mroot>{}<mrow>{}</mrow></mroot>",
+						self.parse_argument()?,
+						index
+					)))
+				} else {
+					Ok(Element::new(format!(
+						"<msqrt>{}</msqrt>",
+						self.parse_argument()?
+					)))
+				}
+			}
+			"overline" => Ok(Element::new(format!(
+				"<mover accent=\"true\">{}<mo>\u{203e}</mo></mover>",
+				self.parse_argument()?
+			))),
+			"underline" => Ok(Element::new(format!(
+				"<munder accentunder=\"true\">{}<mo>_</mo></munder>",
+				self.parse_argument()?
+			))),
+			"text" | "textrm" | "mbox" => Ok(Element::new(format!(
+				"<mtext>{}</mtext>",
+				escape_html(&self.parse_text_argument()?)
+			))),
+			"operatorname" | "mathrm" => {
+				let text = self.parse_text_argument()?;
+				Ok(Element::new(format!(
+					"<mi mathvariant=\"normal\">{}</mi>",
+					escape_html(text.trim())
+				)))
+			}
+			"left" | "right" => {
+				self.skip_whitespace();
+				let delimiter = match self.peek() {
+					Some('.') => {
+						self.position += 1;
+						return Ok(Element::new(String::new()));
+					}
+					Some(_) => self.parse_element()?.mathml,
+					None => {
+						return Err(format!(
+							"Missing delimiter after \\{}.",
+							name
+						))
+					}
+				};
+				Ok(Element::new(delimiter.replacen(
+					"<mo>",
+					"<mo fence=\"true\" stretchy=\"true\">",
+					1,
+				)))
+			}
+			"begin" => Err(format!(
+				"Environments such as \\begin{{{}}} are not supported.",
+				self.parse_text_argument().unwrap_or_default()
+			)),
+			_ => Err(format!("Unsupported command \\{}.", name)),
+		}
+	}
+}
+
+fn make_operator(c: char) -> String {
+	format!("<mo>{}</mo>", escape_html(&c.to_string()))
+}
+
+fn make_space(width: &str) -> String {
+	format!("<mspace width=\"{}\"/>", width)
+}
+
+fn lookup<T: Copy>(table: &[(&str, T)], name: &str) -> Option<T> {
+	table
+		.iter()
+		.find(|(entry, _)| *entry == name)
+		.map(|(_, value)| *value)
+}
+
+const GREEK_LETTERS: &[(&str, char)] = &[
+	("alpha", 'α'),
+	("beta", 'β'),
+	("gamma", 'γ'),
+	("delta", 'δ'),
+	("epsilon", 'ϵ'),
+	("varepsilon", 'ε'),
+	("zeta", 'ζ'),
+	("eta", 'η'),
+	("theta", 'θ'),
+	("vartheta", 'ϑ'),
+	("iota", 'ι'),
+	("kappa", 'κ'),
+	("lambda", 'λ'),
+	("mu", 'μ'),
+	("nu", 'ν'),
+	("xi", 'ξ'),
+	("pi", 'π'),
+	("varpi", 'ϖ'),
+	("rho", 'ρ'),
+	("varrho", 'ϱ'),
+	("sigma", 'σ'),
+	("varsigma", 'ς'),
+	("tau", 'τ'),
+	("upsilon", 'υ'),
+	("phi", 'ϕ'),
+	("varphi", 'φ'),
+	("chi", 'χ'),
+	("psi", 'ψ'),
+	("omega", 'ω'),
+	("Gamma", 'Γ'),
+	("Delta", 'Δ'),
+	("Theta", 'Θ'),
+	("Lambda", 'Λ'),
+	("Xi", 'Ξ'),
+	("Pi", 'Π'),
+	("Sigma", 'Σ'),
+	("Upsilon", 'Υ'),
+	("Phi", 'Φ'),
+	("Psi", 'Ψ'),
+	("Omega", 'Ω'),
+];
+
+const SYMBOLS: &[(&str, char)] = &[
+	("infty", '∞'),
+	("partial", '∂'),
+	("nabla", '∇'),
+	("emptyset", '∅'),
+	("varnothing", '∅'),
+	("hbar", 'ℏ'),
+	("ell", 'ℓ'),
+	("Re", 'ℜ'),
+	("Im", 'ℑ'),
+	("aleph", 'ℵ'),
+];
+
+const OPERATORS: &[(&str, char)] = &[
+	("times", '×'),
+	("cdot", '⋅'),
+	("div", '÷'),
+	("pm", '±'),
+	("mp", '∓'),
+	("leq", '≤'),
+	("le", '≤'),
+	("geq", '≥'),
+	("ge", '≥'),
+	("neq", '≠'),
+	("ne", '≠'),
+	("ll", '≪'),
+	("gg", '≫'),
+	("approx", '≈'),
+	("equiv", '≡'),
+	("sim", '∼'),
+	("simeq", '≃'),
+	("cong", '≅'),
+	("propto", '∝'),
+	("to", '→'),
+	("rightarrow", '→'),
+	("leftarrow", '←'),
+	("gets", '←'),
+	("leftrightarrow", '↔'),
+	("Rightarrow", '⇒'),
+	("Leftarrow", '⇐'),
+	("Leftrightarrow", '⇔'),
+	("implies", '⟹'),
+	("iff", '⟺'),
+	("mapsto", '↦'),
+	("in", '∈'),
+	("notin", '∉'),
+	("ni", '∋'),
+	("subset", '⊂'),
+	("subseteq", '⊆'),
+	("supset", '⊃'),
+	("supseteq", '⊇'),
+	("cup", '∪'),
+	("cap", '∩'),
+	("setminus", '∖'),
+	("forall", '∀'),
+	("exists", '∃'),
+	("neg", '¬'),
+	("lnot", '¬'),
+	("land", '∧'),
+	("wedge", '∧'),
+	("lor", '∨'),
+	("vee", '∨'),
+	("oplus", '⊕'),
+	("otimes", '⊗'),
+	("circ", '∘'),
+	("ast", '∗'),
+	("star", '⋆'),
+	("perp", '⊥'),
+	("parallel", '∥'),
+	("mid", '∣'),
+	("ldots", '…'),
+	("dots", '…'),
+	("cdots", '⋯'),
+	("vdots", '⋮'),
+	("ddots", '⋱'),
+	("langle", '⟨'),
+	("rangle", '⟩'),
+	("lfloor", '⌊'),
+	("rfloor", '⌋'),
+	("lceil", '⌈'),
+	("rceil", '⌉'),
+	("lbrace", '{'),
+	("rbrace", '}'),
+	("vert", '|'),
+	("Vert", '‖'),
+];
+
+const LARGE_OPERATORS: &[(&str, char)] = &[
+	("sum", '∑'),
+	("prod", '∏'),
+	("coprod", '∐'),
+	("int", '∫'),
+	("iint", '∬'),
+	("iiint", '∭'),
+	("oint", '∮'),
+	("bigcup", '⋃'),
+	("bigcap", '⋂'),
+	("bigoplus", '⨁'),
+	("bigotimes", '⨂'),
+];
+
+const FUNCTIONS: &[&str] = &[
+	"sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan",
+	"sinh", "cosh", "tanh", "log", "ln", "lg", "exp", "det", "dim", "ker",
+	"deg", "gcd", "arg", "Pr",
+];
+
+const LIMIT_FUNCTIONS: &[&str] = &["lim", "max", "min", "sup", "inf"];
+
+const SPACES: &[(&str, &str)] = &[("quad", "1em"), ("qquad", "2em")];
+
+const ACCENTS: &[(&str, char)] = &[
+	("hat", '^'),
+	("widehat", '^'),
+	("bar", '¯'),
+	("vec", '→'),
+	("dot", '˙'),
+	("ddot", '¨'),
+	("tilde", '~'),
+	("widetilde", '~'),
+];
+
+// Mathematical alphanumeric symbols: command, start of upper case letters,
+// lower case letters and digits, along with letters predating the block.
+type Alphabet = (&'static str, u32, u32, Option<u32>, &'static [(char, char)]);
+
+const ALPHABETS: &[Alphabet] = &[
+	("mathbf", 0x1d400, 0x1d41a, Some(0x1d7ce), &[]),
+	("mathit", 0x1d434, 0x1d44e, None, &[('h', 'ℎ')]),
+	(
+		"mathbb",
+		0x1d538,
+		0x1d552,
+		Some(0x1d7d8),
+		&[
+			('C', 'ℂ'),
+			('H', 'ℍ'),
+			('N', 'ℕ'),
+			('P', 'ℙ'),
+			('Q', 'ℚ'),
+			('R', 'ℝ'),
+			('Z', 'ℤ'),
+		],
+	),
+	(
+		"mathcal",
+		0x1d49c,
+		0x1d4b6,
+		None,
+		&[
+			('B', 'ℬ'),
+			('E', 'ℰ'),
+			('F', 'ℱ'),
+			('H', 'ℋ'),
+			('I', 'ℐ'),
+			('L', 'ℒ'),
+			('M', 'ℳ'),
+			('R', 'ℛ'),
+			('e', 'ℯ'),
+			('g', 'ℊ'),
+			('o', 'ℴ'),
+		],
+	),
+	(
+		"mathfrak",
+		0x1d504,
+		0x1d51e,
+		None,
+		&[('C', 'ℭ'), ('H', 'ℌ'), ('I', 'ℑ'), ('R', 'ℜ'), ('Z', 'ℨ')],
+	),
+	("mathsf", 0x1d5a0, 0x1d5ba, Some(0x1d7e2), &[]),
+	("mathtt", 0x1d670, 0x1d68a, Some(0x1d7f6), &[]),
+];
@@ -0,0 +1,298 @@
+// Minifies generated HTML: whitespace is collapsed, and dropped next to tags
+// of elements which aren't rendered inline, comments are removed and
+// attribute values are unquoted where HTML allows it. The contents of <pre>,
+// <textarea> and <script> are left alone, and <style> contents are minified as
+// CSS.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::css;
+
+// Bytes saved by minifying each HTML output, so that pages generated again
+// don't count twice.
+pub type Savings = Mutex<HashMap<PathBuf, usize>>;
+
+// Elements whose tags make surrounding whitespace insignificant.
+pub const BLOCK_ELEMENTS: &[&str] = &[
+	"!doctype",
+	"address",
+	"article",
+	"aside",
+	"base",
+	"blockquote",
+	"body",
+	"br",
+	"caption",
+	"col",
+	"colgroup",
+	"dd",
+	"details",
+	"div",
+	"dl",
+	"dt",
+	"fieldset",
+	"figcaption",
+	"figure",
+	"footer",
+	"form",
+	"h1",
+	"h2",
+	"h3",
+	"h4",
+	"h5",
+	"h6",
+	"head",
+	"header",
+	"hr",
+	"html",
+	"li",
+	"link",
+	"main",
+	"meta",
+	"nav",
+	"ol",
+	"p",
+	"pre",
+	"script",
+	"section",
+	"style",
+	"summary",
+	"table",
+	"tbody",
+	"td",
+	"tfoot",
+	"th",
+	"thead",
+	"title",
+	"tr",
+	"ul",
+];
+
+// Elements whose contents are copied as they are.
+const RAW_ELEMENTS: &[&str] = &["pre", "script", "style", "textarea"];
+
+pub fn minify_html(html: &str) -> String {
+	let mut result = String::with_capacity(html.len());
+	// Whitespace seen since the last output, only written once we know that
+	// it isn't next to a block tag.
+	let mut pending_space = false;
+	let mut after_block_tag = true;
+	let mut rest = html;
+	while let Some(c) = rest.chars().next() {
+		if c.is_whitespace() {
+			pending_space = true;
+			rest = &rest[c.len_utf8()..];
+			continue;
+		}
+
+		if rest.starts_with("<!--") {
+			let end = rest.find("-->").map_or(rest.len(), |end| end + 3);
+			// Conditional comments mean something to old browsers.
+			if rest.starts_with("<!--[if") {
+				flush_space(&mut result, &mut pending_space, after_block_tag);
+				result.push_str(&rest[..end]);
+				after_block_tag = false;
+			}
+			rest = &rest[end..];
+			continue;
+		}
+
+		if let Some(tag) = parse_tag(rest) {
+			let is_block = BLOCK_ELEMENTS.contains(&tag.name.as_str());
+			flush_space(
+				&mut result,
+				&mut pending_space,
+				after_block_tag || is_block,
+			);
+			result.push_str(&tag.minified);
+			rest = &rest[tag.length..];
+			after_block_tag = is_block;
+
+			if !tag.closing && RAW_ELEMENTS.contains(&tag.name.as_str()) {
+				let end = find_closing_tag(rest, &tag.name);
+				if tag.name == "style" {
+					result.push_str(&css::minify(&rest[..end]));
+				} else {
+					result.push_str(&rest[..end]);
+				}
+				rest = &rest[end..];
+			}
+			continue;
+		}
+
+		flush_space(&mut result, &mut pending_space, after_block_tag);
+		result.push(c);
+		rest = &rest[c.len_utf8()..];
+		after_block_tag = false;
+	}
+	result
+}
+
+fn flush_space(result: &mut String, pending_space: &mut bool, drop: bool) {
+	if *pending_space && !drop {
+		result.push(' ');
+	}
+	*pending_space = false;
+}
+
+struct Tag {
+	// Lowercase, with a leading ! for declarations such as !doctype.
+	name: String,
+	closing: bool,
+	minified: String,
+	// Bytes of the original tag.
+	length: usize,
+}
+
+// Parses the tag at the start of the text, if it is one.
+fn parse_tag(text: &str) -> Option<Tag> {
+	let bytes = text.as_bytes();
+	if bytes.first() != Some(&b'<') {
+		return None;
+	}
+	let mut i = 1;
+	let closing = bytes.get(i) == Some(&b'/');
+	if closing {
+		i += 1;
+	}
+	let name_start = i;
+	if bytes.get(i) == Some(&b'!') && !closing {
+		i += 1;
+	}
+	if !bytes.get(i).is_some_and(u8::is_ascii_alphabetic) {
+		return None;
+	}
+	while i < bytes.len() && is_name_byte(bytes[i]) {
+		i += 1;
+	}
+	let name = &text[name_start..i];
+
+	let mut minified = String::from(&text[..i]);
+	// Whether the last thing written is an unquoted attribute value, which a
+	// following / would become part of.
+	let mut after_unquoted = false;
+	loop {
+		while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+			i += 1;
+		}
+		match bytes.get(i) {
+			None => return None,
+			Some(b'>') => {
+				minified.push('>');
+				i += 1;
+				break;
+			}
+			Some(b'/') if bytes.get(i + 1) == Some(&b'>') => {
+				if after_unquoted {
+					minified.push(' ');
+				}
+				minified.push_str("/>");
+				i += 2;
+				break;
+			}
+			_ => {}
+		}
+
+		let attribute_start = i;
+		while i < bytes.len()
+			&& !bytes[i].is_ascii_whitespace()
+			&& !matches!(bytes[i], b'=' | b'>')
+			&& (bytes[i] != b'/' || bytes.get(i + 1) != Some(&b'>'))
+		{
+			i += 1;
+		}
+		minified.push(' ');
+		minified.push_str(&text[attribute_start..i]);
+		after_unquoted = false;
+
+		let mut j = i;
+		while j < bytes.len() && bytes[j].is_ascii_whitespace() {
+			j += 1;
+		}
+		if bytes.get(j) != Some(&b'=') {
+			continue;
+		}
+		i = j + 1;
+		while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+			i += 1;
+		}
+		minified.push('=');
+		match bytes.get(i) {
+			Some(&quote) if quote == b'"' || quote == b'\'' => {
+				let end = text[i + 1..].find(quote as char)? + i + 1;
+				let value = &text[i + 1..end];
+				if can_unquote(value) {
+					minified.push_str(value);
+					after_unquoted = true;
+				} else {
+					minified.push_str(&text[i..=end]);
+				}
+				i = end + 1;
+			}
+			_ => {
+				let value_start = i;
+				while i < bytes.len()
+					&& !bytes[i].is_ascii_whitespace()
+					&& bytes[i] != b'>'
+				{
+					i += 1;
+				}
+				minified.push_str(&text[value_start..i]);
+				after_unquoted = true;
+			}
+		}
+	}
+
+	Some(Tag {
+		name: name.to_ascii_lowercase(),
+		closing,
+		minified,
+		length: i,
+	})
+}
+
+fn is_name_byte(byte: u8) -> bool {
+	byte.is_ascii_alphanumeric() || byte == b'-' || byte == b':'
+}
+
+fn can_unquote(value: &str) -> bool {
+	!value.is_empty()
+		&& !value.ends_with('/')
+		&& !value.chars().any(|c| {
+			c.is_ascii_whitespace()
+				|| matches!(c, '"' | '\'' | '=' | '<' | '>' | '`')
+		})
+}
+
+// Index of the closing tag of the named raw element, or the end of the text.
+fn find_closing_tag(text: &str, name: &str) -> usize {
+	let lowercase = text.to_ascii_lowercase();
+	let closing = format!("</{}", name);
+	let mut offset = 0;
+	while let Some(start) = lowercase[offset..].find(&closing) {
+		let end = offset + start + closing.len();
+		match lowercase.as_bytes().get(end) {
+			Some(b'>') | Some(b'/') => return offset + start,
+			Some(byte) if byte.is_ascii_whitespace() => return offset + start,
+			None => return offset + start,
+			_ => offset = end,
+		}
+	}
+	text.len()
+}
+
+pub fn record_saving(savings: &Savings, path: &Path, saved: usize) {
+	savings
+		.lock()
+		.unwrap_or_else(|e| panic!("Failed locking minify savings: {}", e))
+		.insert(path.to_path_buf(), saved);
+}
+
+pub fn total_savings(savings: &Savings) -> usize {
+	savings
+		.lock()
+		.unwrap_or_else(|e| panic!("Failed locking minify savings: {}", e))
+		.values()
+		.sum()
+}
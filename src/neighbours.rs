@@ -0,0 +1,92 @@
+// Previous, next and related entries of each page behind page.previous,
+// page.next and page.related. They are computed once whenever the groups
+// change, rather than by scanning all groups on every access from templates.
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::front_matter::FrontMatter;
+use crate::markdown::{GroupedOptionOutputFile, InputFile};
+
+#[derive(Clone, Default)]
+pub struct Neighbours {
+	// Input paths of the older and newer entries within the same group.
+	pub previous: Option<PathBuf>,
+	pub next: Option<PathBuf>,
+	// Input paths of entries of any group sharing tags with the page.
+	pub related: Vec<PathBuf>,
+}
+
+// Expects the entries of each group to be sorted newest first. A related_limit
+// of 0 means no limit.
+pub fn update(
+	input_output_map: &mut HashMap<PathBuf, GroupedOptionOutputFile>,
+	groups: &HashMap<String, Vec<InputFile>>,
+	related_limit: usize,
+) {
+	let mut tagged = HashMap::<&str, Vec<&InputFile>>::new();
+	let mut adjacent = HashMap::<&PathBuf, Neighbours>::new();
+	for entries in groups.values() {
+		for (index, entry) in entries.iter().enumerate() {
+			for tag in &entry.front_matter.tags {
+				tagged.entry(tag).or_default().push(entry);
+			}
+			adjacent.insert(
+				&entry.path,
+				Neighbours {
+					previous: entries.get(index + 1).map(|e| e.path.clone()),
+					next: index.checked_sub(1).map(|i| entries[i].path.clone()),
+					related: Vec::new(),
+				},
+			);
+		}
+	}
+
+	for (input_file_path, output_file) in input_output_map.iter_mut() {
+		let mut neighbours =
+			adjacent.remove(input_file_path).unwrap_or_default();
+		if let Some(front_matter) = &output_file.file.front_matter {
+			neighbours.related = find_related(
+				input_file_path,
+				front_matter,
+				&tagged,
+				related_limit,
+			);
+		}
+		output_file.neighbours = neighbours;
+	}
+}
+
+// Ranks entries by the number of tags they share with the page, newest first
+// among equals.
+fn find_related(
+	input_file_path: &PathBuf,
+	front_matter: &FrontMatter,
+	tagged: &HashMap<&str, Vec<&InputFile>>,
+	limit: usize,
+) -> Vec<PathBuf> {
+	let mut shared = HashMap::<&PathBuf, (usize, &InputFile)>::new();
+	for tag in &front_matter.tags {
+		for entry in tagged.get(tag.as_str()).into_iter().flatten() {
+			if &entry.path != input_file_path {
+				shared.entry(&entry.path).or_insert((0, entry)).0 += 1;
+			}
+		}
+	}
+
+	let mut related = shared.into_values().collect::<Vec<_>>();
+	related.sort_by(|(lhs_count, lhs), (rhs_count, rhs)| {
+		rhs_count.cmp(lhs_count).then_with(|| {
+			rhs.front_matter
+				.date
+				.cmp(&lhs.front_matter.date)
+				.then_with(|| lhs.path.cmp(&rhs.path))
+		})
+	});
+	if limit > 0 {
+		related.truncate(limit);
+	}
+	related
+		.into_iter()
+		.map(|(_, entry)| entry.path.clone())
+		.collect()
+}
@@ -0,0 +1,139 @@
+// Writes gzip and brotli compressed siblings of text outputs, such as
+// index.html.gz and index.html.br, for hosts which serve them to clients
+// accepting those encodings. The built-in HTTP server does the same.
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::util;
+
+pub const EXTENSIONS: [&str; 5] = [
+	util::CSS_EXTENSION,
+	util::HTML_EXTENSION,
+	util::JS_EXTENSION,
+	util::TXT_EXTENSION,
+	util::XML_EXTENSION,
+];
+
+// Compressed variants by Content-Encoding, in order of preference.
+pub const ENCODINGS: [(&str, &str); 2] = [("br", "br"), ("gzip", "gz")];
+
+pub fn is_compressible(path: &Path) -> bool {
+	path.extension()
+		.and_then(|e| e.to_str())
+		.is_some_and(|extension| EXTENSIONS.contains(&extension))
+}
+
+// Path of the variant with the given extension, such as style.css.br.
+pub fn sibling_path(path: &Path, extension: &str) -> PathBuf {
+	let mut file_name = path.as_os_str().to_os_string();
+	file_name.push(".");
+	file_name.push(extension);
+	PathBuf::from(file_name)
+}
+
+// Modification times of outputs as of when their variants were last written
+// or found not to be worth writing, so that unchanged outputs aren't
+// compressed again.
+pub type Checked = Mutex<HashMap<PathBuf, SystemTime>>;
+
+// Compresses the text outputs under the directory which changed since they
+// were last checked and whose variants are missing or older than them,
+// returning the number of files written. Variants which wouldn't be smaller
+// than the original are left out, removing stale ones.
+pub fn run(output_dir: &Path, checked: &Checked) -> usize {
+	let mut checked = checked
+		.lock()
+		.unwrap_or_else(|e| panic!("Failed locking checked outputs: {}", e));
+	let mut files = Vec::new();
+	find_compressible_files(output_dir, &mut files);
+	let mut count = 0;
+	for file_name in &files {
+		let modified = fs::metadata(file_name)
+			.and_then(|m| m.modified())
+			.unwrap_or_else(|e| {
+				panic!("Failed reading mtime of {}: {}", file_name.display(), e)
+			});
+		if checked.get(file_name) == Some(&modified) {
+			continue;
+		}
+
+		let mut data = None;
+		for (extension, compress) in
+			[("gz", gzip as fn(&[u8]) -> Vec<u8>), ("br", brotli)]
+		{
+			let target = sibling_path(file_name, extension);
+			if fs::metadata(&target)
+				.and_then(|m| m.modified())
+				.is_ok_and(|target_modified| target_modified >= modified)
+			{
+				continue;
+			}
+
+			let data: &Vec<u8> = data.get_or_insert_with(|| {
+				fs::read(file_name).unwrap_or_else(|e| {
+					panic!("Failed reading {}: {}", file_name.display(), e)
+				})
+			});
+			let compressed = compress(data);
+			if compressed.len() < data.len() {
+				fs::write(&target, compressed).unwrap_or_else(|e| {
+					panic!("Failed writing {}: {}", target.display(), e)
+				});
+				count += 1;
+			} else if target.exists() {
+				fs::remove_file(&target).unwrap_or_else(|e| {
+					panic!("Failed removing {}: {}", target.display(), e)
+				});
+			}
+		}
+		checked.insert(file_name.clone(), modified);
+	}
+	count
+}
+
+pub fn gzip(data: &[u8]) -> Vec<u8> {
+	let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+	encoder
+		.write_all(data)
+		.and_then(|_| encoder.finish())
+		.unwrap_or_else(|e| panic!("Failed gzip compressing: {}", e))
+}
+
+pub fn brotli(data: &[u8]) -> Vec<u8> {
+	let mut result = Vec::new();
+	{
+		// Highest quality and a 4 MiB window, as pages are compressed once
+		// and served many times.
+		let mut writer =
+			::brotli::CompressorWriter::new(&mut result, 4096, 11, 22);
+		writer
+			.write_all(data)
+			.unwrap_or_else(|e| panic!("Failed brotli compressing: {}", e));
+	}
+	result
+}
+
+fn find_compressible_files(dir: &Path, result: &mut Vec<PathBuf>) {
+	let entries = fs::read_dir(dir).unwrap_or_else(|e| {
+		panic!("Failed reading paths from \"{}\": {}.", dir.display(), e)
+	});
+	for entry in entries {
+		let path = entry
+			.unwrap_or_else(|e| {
+				panic!("Invalid entry in \"{}\": {}", dir.display(), e)
+			})
+			.path();
+		if path.is_dir() {
+			find_compressible_files(&path, result)
+		} else if is_compressible(&path) {
+			result.push(path)
+		}
+	}
+}
@@ -1,31 +1,89 @@
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::ffi::OsStr;
 use std::fs;
 use std::io::Write;
-use std::path::Path;
 use std::path::PathBuf;
+use std::path::{Component, Path};
 
-use crate::markdown::GroupedOptionOutputFile;
+use crate::archive;
+use crate::check;
+use crate::markdown::{GroupedOptionOutputFile, InputFile};
 use crate::util;
-use crate::util::{strip_prefix, write_to_stream};
+use crate::util::{escape_html, strip_prefix, write_to_stream};
 
-pub fn write_robots_txt(output_dir: &Path, sitemap_url: &str) {
+#[derive(Clone, Debug, PartialEq)]
+pub enum Rule {
+	Allow(String),
+	Disallow(String),
+}
+
+// Parses comma-separated rules such as "allow /, disallow /drafts/".
+pub fn parse_rules(agent: &str, rules: &str) -> Vec<Rule> {
+	rules
+		.split(',')
+		.map(str::trim)
+		.filter(|rule| !rule.is_empty())
+		.map(|rule| {
+			let (kind, path) =
+				rule.split_once(char::is_whitespace).unwrap_or_else(|| {
+					panic!(
+						"Expected \"allow <path>\" or \"disallow <path>\" in robots rules of {} but got \"{}\".",
+						agent, rule
+					)
+				});
+			let path = path.trim().to_string();
+			match kind {
+				"allow" => Rule::Allow(path),
+				"disallow" => Rule::Disallow(path),
+				_ => panic!(
+					"Unknown robots rule \"{}\" for {}, expected allow or disallow.",
+					kind, agent
+				),
+			}
+		})
+		.collect()
+}
+
+// Agents without rules are allowed everywhere. Staging builds disallow all
+// agents from everything, regardless of the rules.
+pub fn write_robots_txt(
+	output_dir: &Path,
+	sitemap_url: &str,
+	rules: &BTreeMap<String, Vec<Rule>>,
+	staging: bool,
+) {
 	let file_name = output_dir.join(PathBuf::from("robots.txt"));
 	let mut file = fs::File::create(&file_name).unwrap_or_else(|e| {
 		panic!("Failed creating {}: {}", file_name.display(), e)
 	});
-	file.write_all(
-		format!(
-			"User-agent: *
-Allow: /
-Sitemap: {}
-",
-			sitemap_url
-		)
-		.as_bytes(),
-	)
-	.unwrap_or_else(|e| {
+	let mut content = String::new();
+	if staging {
+		content.push_str("User-agent: *\nDisallow: /\n");
+	} else if rules.is_empty() {
+		content.push_str("User-agent: *\nAllow: /\n");
+	} else {
+		for (agent, agent_rules) in rules {
+			if !content.is_empty() {
+				content.push('\n');
+			}
+			content.push_str(&format!("User-agent: {}\n", agent));
+			for rule in agent_rules {
+				match rule {
+					Rule::Allow(path) => {
+						content.push_str(&format!("Allow: {}\n", path))
+					}
+					Rule::Disallow(path) => {
+						content.push_str(&format!("Disallow: {}\n", path))
+					}
+				}
+			}
+		}
+	}
+	if !staging {
+		content.push_str(&format!("Sitemap: {}\n", sitemap_url));
+	}
+	file.write_all(content.as_bytes()).unwrap_or_else(|e| {
 		panic!("Failed writing to {}: {}", file_name.display(), e)
 	});
 	// Avoiding sync_all() for now to be friendlier to disks.
@@ -35,32 +93,70 @@ Sitemap: {}
 	println!("Wrote {}.", file_name.display());
 }
 
+// Limits of a single sitemap file from the protocol, past which the entries
+// are split into child sitemaps listed by a sitemap index.
+pub const MAX_SITEMAP_URLS: usize = 50_000;
+pub const MAX_SITEMAP_BYTES: usize = 50 * 1024 * 1024;
+
+const URLSET_START: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\" xmlns:image=\"http://www.google.com/schemas/sitemap-image/1.1\">\n";
+const URLSET_END: &str = "</urlset>\n";
+
+// Lists the HTML pages, leaving out the 404 page, tag and archive pages,
+// unpublished pages and those opting out through sitemap: false or
+// noindex: true. Returns the URL of the sitemap, or of the sitemap index if
+// split.
 pub fn write_sitemap_xml(
 	output_dir: &Path,
 	base_url: &str,
 	input_output_map: &HashMap<PathBuf, GroupedOptionOutputFile>,
+	tags: &HashMap<String, Vec<InputFile>>,
+	archives: &HashMap<PathBuf, Vec<InputFile>>,
 ) -> String {
 	struct Entry<'a> {
 		path: String,
 		date: Option<&'a str>,
+		priority: Option<f32>,
+		changefreq: Option<&'a str>,
+		images: Vec<String>,
 	}
 
-	let official_file_name = PathBuf::from("sitemap.xml");
-	let file_name = output_dir.join(&official_file_name);
-	let mut file = fs::File::create(&file_name).unwrap_or_else(|e| {
-		panic!("Failed creating {}: {}", file_name.display(), e)
-	});
-	write_to_stream(
-		b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>
-<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
-		&mut file,
-	);
+	// Listings of other pages.
+	let listing_pages = tags
+		.keys()
+		.map(|tag| {
+			output_dir
+				.join("tags")
+				.join(tag)
+				.with_extension(util::HTML_EXTENSION)
+		})
+		.chain(
+			archives
+				.keys()
+				.map(|period| archive::make_output_path(output_dir, period)),
+		)
+		.collect::<HashSet<_>>();
 
+	let not_found_page = output_dir.join(util::NOT_FOUND_PAGE);
 	let html_extension = OsStr::new(util::HTML_EXTENSION);
 
 	let mut entries = Vec::new();
 	for output_file in input_output_map.values() {
-		if output_file.file.path.extension() != Some(html_extension) {
+		if output_file.file.path.extension() != Some(html_extension)
+			|| listing_pages.contains(&output_file.file.path)
+			|| output_file.file.path == not_found_page
+		{
+			continue;
+		}
+		if output_file
+			.file
+			.front_matter
+			.as_ref()
+			.is_some_and(|front_matter| {
+				front_matter.noindex
+					|| !front_matter.sitemap
+					|| !front_matter.published
+			}) {
 			continue;
 		}
 
@@ -72,17 +168,22 @@ pub fn write_sitemap_xml(
 			output_url.push_str(&path.to_string_lossy())
 		}
 
-		let date_entry =
-			if let Some(front_matter) = &output_file.file.front_matter {
-				if let Some(date) = &front_matter.edited {
-					Some(date.as_str())
-				} else { front_matter.date.as_deref() }
+		let front_matter = output_file.file.front_matter.as_deref();
+		let date_entry = if let Some(front_matter) = front_matter {
+			if let Some(date) = &front_matter.edited {
+				Some(date.as_str())
 			} else {
-				None
-			};
+				front_matter.date.as_deref()
+			}
+		} else {
+			None
+		};
 		entries.push(Entry {
 			path: output_url,
 			date: date_entry,
+			priority: front_matter.and_then(|f| f.priority),
+			changefreq: front_matter.and_then(|f| f.changefreq.as_deref()),
+			images: find_images(&output_file.file.path, &path, base_url),
 		});
 	}
 
@@ -94,35 +195,198 @@ pub fn write_sitemap_xml(
 		}
 	});
 
-	for entry in &entries {
-		write_to_stream(
-			format!(
-				"	<url>
-		<loc>{}</loc>\n",
-				entry.path
-			)
-			.as_bytes(),
-			&mut file,
+	let urls = entries
+		.iter()
+		.map(|entry| {
+			let mut url = format!(
+				"\t<url>\n\t\t<loc>{}</loc>\n",
+				escape_url(&entry.path)
+			);
+			if let Some(date) = entry.date {
+				url.push_str(&format!("\t\t<lastmod>{}</lastmod>\n", date));
+			}
+			if let Some(changefreq) = entry.changefreq {
+				url.push_str(&format!(
+					"\t\t<changefreq>{}</changefreq>\n",
+					changefreq
+				));
+			}
+			if let Some(priority) = entry.priority {
+				url.push_str(&format!(
+					"\t\t<priority>{}</priority>\n",
+					priority
+				));
+			}
+			for image in &entry.images {
+				url.push_str(&format!(
+					"\t\t<image:image>\n\
+					\t\t\t<image:loc>{}</image:loc>\n\
+					\t\t</image:image>\n",
+					escape_url(image)
+				));
+			}
+			url.push_str("\t</url>\n");
+			url
+		})
+		.collect::<Vec<_>>();
+
+	let official_file_name = PathBuf::from("sitemap.xml");
+	let chunks = chunk_urls(&urls, MAX_SITEMAP_URLS, MAX_SITEMAP_BYTES);
+	if chunks.len() <= 1 {
+		write_urlset(
+			&output_dir.join(&official_file_name),
+			chunks.first().copied().unwrap_or(&[]),
+		);
+	} else {
+		let mut index = String::from(
+			"<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<sitemapindex xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
 		);
+		for (i, chunk) in chunks.iter().enumerate() {
+			let child_name = format!("sitemap-{}.xml", i + 1);
+			write_urlset(&output_dir.join(&child_name), chunk);
+			index.push_str(&format!(
+				"\t<sitemap>\n\t\t<loc>{}{}</loc>\n\t</sitemap>\n",
+				escape_url(base_url),
+				child_name
+			));
+		}
+		index.push_str("</sitemapindex>\n");
+		write_file(&output_dir.join(&official_file_name), &index);
+	}
 
-		if let Some(date) = entry.date {
-			write_to_stream(
-				format!("		<lastmod>{}</lastmod>\n", date).as_bytes(),
-				&mut file,
-			);
+	let mut result = base_url.to_string();
+	result.push_str(&official_file_name.to_string_lossy());
+	result
+}
+
+// Splits the <url> elements into groups fitting within the given number of
+// URLs and bytes per sitemap file, including its start and end.
+pub fn chunk_urls(
+	urls: &[String],
+	max_urls: usize,
+	max_bytes: usize,
+) -> Vec<&[String]> {
+	let overhead = URLSET_START.len() + URLSET_END.len();
+	let mut chunks = Vec::new();
+	let mut start = 0;
+	let mut bytes = overhead;
+	for (i, url) in urls.iter().enumerate() {
+		if i > start && (i - start == max_urls || bytes + url.len() > max_bytes)
+		{
+			chunks.push(&urls[start..i]);
+			start = i;
+			bytes = overhead;
 		}
+		bytes += url.len();
+	}
+	if start < urls.len() {
+		chunks.push(&urls[start..]);
+	}
+	chunks
+}
 
-		write_to_stream(b"	</url>\n", &mut file);
+fn write_urlset(file_name: &Path, urls: &[String]) {
+	let mut content = String::from(URLSET_START);
+	for url in urls {
+		content.push_str(url);
 	}
+	content.push_str(URLSET_END);
+	write_file(file_name, &content);
+}
 
-	write_to_stream(b"</urlset>\n", &mut file);
+fn write_file(file_name: &Path, content: &str) {
+	let mut file = fs::File::create(file_name).unwrap_or_else(|e| {
+		panic!("Failed creating {}: {}", file_name.display(), e)
+	});
+	write_to_stream(content.as_bytes(), &mut file);
 	// Avoiding sync_all() for now to be friendlier to disks.
 	file.sync_data().unwrap_or_else(|e| {
 		panic!("Failed sync_data() for \"{}\": {}.", file_name.display(), e)
 	});
 	println!("Wrote {}.", file_name.display());
+}
 
-	let mut result = base_url.to_string();
-	result.push_str(&official_file_name.to_string_lossy());
-	result
+// Absolute URLs of the images on a generated page, skipping inline data. Pages
+// which were not generated, such as when processing a single file, have none.
+fn find_images(
+	output_file_path: &Path,
+	relative_path: &Path,
+	base_url: &str,
+) -> Vec<String> {
+	let content = match fs::read_to_string(output_file_path) {
+		Ok(content) => content,
+		Err(_) => return Vec::new(),
+	};
+	let origin = match base_url.find("://") {
+		Some(scheme_end) => match base_url[scheme_end + 3..].find('/') {
+			Some(host_end) => &base_url[..scheme_end + 3 + host_end],
+			None => base_url,
+		},
+		None => "",
+	};
+
+	let mut images = Vec::new();
+	for tag in check::scan_tags(&content) {
+		if tag.name != "img" {
+			continue;
+		}
+		let src = match tag.attributes.iter().find(|(name, _)| name == "src") {
+			Some((_, src)) if !src.is_empty() && !src.starts_with("data:") => {
+				src
+			}
+			_ => continue,
+		};
+		let url = if src.contains("://") {
+			src.clone()
+		} else if let Some(host_relative) = src.strip_prefix("//") {
+			let scheme = base_url.split("://").next().unwrap_or("https");
+			format!("{}://{}", scheme, host_relative)
+		} else if src.starts_with('/') {
+			format!("{}{}", origin, src)
+		} else {
+			let mut normalized = Vec::new();
+			let joined = relative_path
+				.parent()
+				.map_or_else(PathBuf::new, Path::to_path_buf)
+				.join(src);
+			let mut outside = false;
+			for component in joined.components() {
+				match component {
+					Component::ParentDir => {
+						outside |= normalized.pop().is_none();
+					}
+					Component::Normal(name) => {
+						normalized.push(name.to_string_lossy())
+					}
+					_ => {}
+				}
+			}
+			if outside {
+				continue;
+			}
+			format!("{}{}", base_url, normalized.join("/"))
+		};
+		if !images.contains(&url) {
+			images.push(url);
+		}
+	}
+	images
+}
+
+// Percent-encodes characters which may not appear in URLs, such as spaces and
+// non-ASCII text, then entity-escapes the result for XML, as required by the
+// sitemap protocol.
+pub fn escape_url(url: &str) -> String {
+	let mut encoded = String::with_capacity(url.len());
+	for byte in url.bytes() {
+		if byte.is_ascii_alphanumeric()
+			|| b"-._~:/?#[]@!$&'()*+,;=%".contains(&byte)
+		{
+			encoded.push(byte as char);
+		} else {
+			encoded.push_str(&format!("%{:02X}", byte));
+		}
+	}
+	escape_html(&encoded)
 }
@@ -0,0 +1,321 @@
+// RSS 2.0 feeds, for readers which don't support Atom, and the variant with
+// iTunes tags (https://help.apple.com/itc/podcasts_connect/#/itcb54353390)
+// expected by podcast directories.
+use std::fs;
+use std::io::BufWriter;
+use std::time::UNIX_EPOCH;
+
+use crate::feed::{complete_url, FeedEntry, FeedHeader};
+use crate::util::{escape_html, write_to_stream};
+
+pub fn write_feed(
+	header: &FeedHeader,
+	entries: &[&FeedEntry],
+	output: &mut BufWriter<fs::File>,
+) {
+	write_channel(header, entries, false, output);
+}
+
+// Only includes entries with an enclosure, as episodes.
+pub fn write_podcast_feed(
+	header: &FeedHeader,
+	entries: &[&FeedEntry],
+	output: &mut BufWriter<fs::File>,
+) {
+	let episodes = entries
+		.iter()
+		.copied()
+		.filter(|entry| entry.enclosure.is_some())
+		.collect::<Vec<_>>();
+	write_channel(header, &episodes, true, output);
+}
+
+fn write_channel(
+	header: &FeedHeader,
+	entries: &[&FeedEntry],
+	podcast: bool,
+	mut output: &mut BufWriter<fs::File>,
+) {
+	let namespaces = if podcast {
+		" xmlns:itunes=\"http://www.itunes.com/dtds/podcast-1.0.dtd\""
+	} else {
+		""
+	};
+	let description = header
+		.podcast
+		.get("description")
+		.filter(|_| podcast)
+		.unwrap_or(&header.title);
+	write_to_stream(
+		format!(
+			"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+			<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\"{}>\n\
+			<channel>\n\
+			\t<title>{}</title>\n\
+			\t<link>{}</link>\n\
+			\t<description>{}</description>\n\
+			\t<atom:link href=\"{}\" rel=\"self\" type=\"application/rss+xml\"/>\n",
+			namespaces,
+			escape_html(&header.title),
+			escape_html(&header.base_url),
+			escape_html(description),
+			escape_html(&header.feed_url),
+		)
+		.as_bytes(),
+		&mut output,
+	);
+
+	if let Some(latest_update) = &header.latest_update {
+		write_to_stream(
+			format!(
+				"\t<lastBuildDate>{}</lastBuildDate>\n",
+				format_date(latest_update)
+			)
+			.as_bytes(),
+			&mut output,
+		);
+	}
+
+	let author = format_author(header);
+	if let Some(author) = &author {
+		write_to_stream(
+			format!("\t<managingEditor>{}</managingEditor>\n", author)
+				.as_bytes(),
+			&mut output,
+		);
+	}
+
+	if podcast {
+		generate_podcast_channel(header, output);
+	}
+
+	for entry in entries {
+		generate_item(entry, header, &author, podcast, output);
+	}
+
+	write_to_stream(b"</channel>\n</rss>\n", &mut output);
+}
+
+fn generate_item(
+	entry: &FeedEntry,
+	header: &FeedHeader,
+	author: &Option<String>,
+	podcast: bool,
+	mut output: &mut BufWriter<fs::File>,
+) {
+	let entry_url = escape_html(&complete_url(
+		&header.base_url,
+		&entry.permalink.to_string_lossy(),
+	));
+
+	write_to_stream(
+		format!(
+			"\n\
+			\t<item>\n\
+			\t\t<title>{}</title>\n\
+			\t\t<link>{}</link>\n\
+			\t\t<guid isPermaLink=\"true\">{}</guid>\n",
+			escape_html(&entry.front_matter.title),
+			entry_url,
+			entry_url
+		)
+		.as_bytes(),
+		&mut output,
+	);
+
+	if let Some(published_date) = &entry.front_matter.date {
+		write_to_stream(
+			format!("\t\t<pubDate>{}</pubDate>\n", format_date(published_date))
+				.as_bytes(),
+			&mut output,
+		);
+	}
+	if let Some(author) = author {
+		write_to_stream(
+			format!("\t\t<author>{}</author>\n", author).as_bytes(),
+			&mut output,
+		);
+	}
+
+	write_to_stream(
+		format!(
+			"\t\t<description><![CDATA[{}]]></description>\n",
+			escape_cdata(entry.summary.as_ref().unwrap_or(&entry.html_content))
+		)
+		.as_bytes(),
+		&mut output,
+	);
+
+	if let Some(enclosure) = &entry.enclosure {
+		write_to_stream(
+			format!(
+				"\t\t<enclosure url=\"{}\" length=\"{}\" type=\"{}\"/>\n",
+				escape_html(&complete_url(
+					&header.base_url,
+					&enclosure.path.to_string_lossy()
+				)),
+				enclosure.length,
+				enclosure.mime_type
+			)
+			.as_bytes(),
+			&mut output,
+		);
+		if podcast {
+			if let Some(duration) = enclosure.duration_seconds {
+				write_to_stream(
+					format!(
+						"\t\t<itunes:duration>{}</itunes:duration>\n",
+						duration
+					)
+					.as_bytes(),
+					&mut output,
+				);
+			}
+		}
+	}
+	if let (true, Some(episode)) = (podcast, entry.front_matter.episode) {
+		write_to_stream(
+			format!("\t\t<itunes:episode>{}</itunes:episode>\n", episode)
+				.as_bytes(),
+			&mut output,
+		);
+	}
+
+	write_to_stream(b"\t</item>\n", &mut output);
+}
+
+fn generate_podcast_channel(
+	header: &FeedHeader,
+	mut output: &mut BufWriter<fs::File>,
+) {
+	write_to_stream(
+		format!(
+			"\t<itunes:author>{}</itunes:author>\n\
+			\t<itunes:owner>\n\
+			\t\t<itunes:name>{}</itunes:name>\n\
+			\t\t<itunes:email>{}</itunes:email>\n\
+			\t</itunes:owner>\n\
+			\t<itunes:explicit>{}</itunes:explicit>\n",
+			escape_html(&header.author_name),
+			escape_html(&header.author_name),
+			escape_html(&header.author_email),
+			header
+				.podcast
+				.get("explicit")
+				.map_or("false", String::as_str)
+		)
+		.as_bytes(),
+		&mut output,
+	);
+
+	if let Some(language) = header.podcast.get("language") {
+		write_to_stream(
+			format!("\t<language>{}</language>\n", escape_html(language))
+				.as_bytes(),
+			&mut output,
+		);
+	}
+	if let Some(category) = header.podcast.get("category") {
+		write_to_stream(
+			format!(
+				"\t<itunes:category text=\"{}\"/>\n",
+				escape_html(category)
+			)
+			.as_bytes(),
+			&mut output,
+		);
+	}
+	if let Some(image) = header.podcast.get("image") {
+		// Directories require absolute URLs.
+		let url = match image.strip_prefix('/') {
+			Some(path) => complete_url(&header.base_url, path),
+			None => image.clone(),
+		};
+		write_to_stream(
+			format!("\t<itunes:image href=\"{}\"/>\n", escape_html(&url))
+				.as_bytes(),
+			&mut output,
+		);
+	}
+}
+
+// RSS wants an email address, optionally followed by the name.
+fn format_author(header: &FeedHeader) -> Option<String> {
+	if header.author_email.is_empty() {
+		None
+	} else if header.author_name.is_empty() {
+		Some(escape_html(&header.author_email))
+	} else {
+		Some(escape_html(&format!(
+			"{} ({})",
+			header.author_email, header.author_name
+		)))
+	}
+}
+
+// Splits the CDATA section around any ]]> in the text.
+fn escape_cdata(text: &str) -> String {
+	text.replace("]]>", "]]]]><![CDATA[>")
+}
+
+// Converts an RFC 3339 date such as 2001-01-19T20:10:01Z into the RFC 822
+// format of RSS: Fri, 19 Jan 2001 20:10:01 +0000. A UTC offset such as +02:00
+// is kept as-is, the date and time staying those local to it.
+pub fn format_date(date: &str) -> String {
+	const WEEKDAYS: [&str; 7] =
+		["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+	const MONTHS: [&str; 12] = [
+		"Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct",
+		"Nov", "Dec",
+	];
+
+	let (local, offset) = split_offset(date);
+	let time = humantime::parse_rfc3339_weak(local).unwrap_or_else(|e| {
+		panic!("Failed parsing date \"{}\" for RSS feed: {}", date, e)
+	});
+	let seconds = time
+		.duration_since(UNIX_EPOCH)
+		.unwrap_or_else(|e| {
+			panic!("Date \"{}\" is before 1970 in RSS feed: {}", date, e)
+		})
+		.as_secs();
+	// Normalized to the form 2001-01-19T20:10:01Z.
+	let local = humantime::format_rfc3339_seconds(time).to_string();
+	let month = local[5..7].parse::<usize>().unwrap_or_else(|e| {
+		panic!("Failed parsing month of \"{}\": {}", local, e)
+	});
+	format!(
+		"{}, {} {} {} {} {}",
+		// 1970-01-01 was a Thursday.
+		WEEKDAYS[(seconds / 86400 % 7) as usize],
+		&local[8..10],
+		MONTHS[month - 1],
+		&local[0..4],
+		&local[11..19],
+		offset
+	)
+}
+
+// Splits a trailing UTC offset such as +02:00 off the date, returning it in
+// the RFC 822 form +0200. Dates ending in Z or without offset are in UTC.
+fn split_offset(date: &str) -> (&str, String) {
+	let bytes = date.as_bytes();
+	if bytes.len() > 6 {
+		let start = bytes.len() - 6;
+		let sign = bytes[start];
+		let rest = &date[start + 1..];
+		if (sign == b'+' || sign == b'-')
+			&& bytes[start + 3] == b':'
+			&& rest.bytes().filter(u8::is_ascii_digit).count() == 4
+		{
+			return (
+				&date[..start],
+				format!("{}{}{}", sign as char, &rest[..2], &rest[3..]),
+			);
+		}
+	}
+	(
+		date.strip_suffix('Z').unwrap_or(date),
+		String::from("+0000"),
+	)
+}
@@ -0,0 +1,216 @@
+// Index of the pages for client-side search, written as compact JSON with one
+// page per line:
+//
+// {"version":1,"pages":[
+// {"url":"posts/hello.html","title":"Hello","tags":["rust"],"headings":["Intro"],"body":"plain text of the page"}
+// ]}
+//
+// URLs are relative to base_url. The body is the plain text of the page
+// without stop words, optionally truncated to a number of words.
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::check;
+use crate::markdown::GeneratedFile;
+use crate::minify;
+use crate::util::{escape_json, write_to_stream, NOT_FOUND_PAGE};
+
+// Key of the inclusion rules applying to pages outside groups, and groups not
+// listed.
+pub const DEFAULT_KEY: &str = "*";
+
+pub struct Entry {
+	pub url: String,
+	pub title: String,
+	pub tags: Vec<String>,
+	pub headings: Vec<String>,
+	pub body: String,
+}
+
+// Entries by URL, so that regenerated pages replace their previous entry.
+pub type Entries = Mutex<BTreeMap<String, Entry>>;
+
+// Adds pages to the index as they are generated.
+pub struct Indexer<'a> {
+	pub rules: &'a BTreeMap<String, bool>,
+	pub stop_words: &'a HashSet<String>,
+	pub body_words: usize,
+	pub entries: &'a Entries,
+}
+
+impl Indexer<'_> {
+	pub fn add(&self, generated: &GeneratedFile) {
+		let mut entries = lock(self.entries);
+		match make_entry(
+			generated,
+			self.rules,
+			self.stop_words,
+			self.body_words,
+		) {
+			Some(entry) => {
+				entries.insert(entry.url.clone(), entry);
+			}
+			None => {
+				entries.remove(&make_url(&generated.file.path));
+			}
+		}
+	}
+}
+
+pub fn is_included(
+	group: Option<&str>,
+	rules: &BTreeMap<String, bool>,
+) -> bool {
+	group
+		.and_then(|group| rules.get(group))
+		.or_else(|| rules.get(DEFAULT_KEY))
+		.copied()
+		.unwrap_or(true)
+}
+
+// Returns None for pages left out by the inclusion rules and the 404 page.
+pub fn make_entry(
+	generated: &GeneratedFile,
+	rules: &BTreeMap<String, bool>,
+	stop_words: &HashSet<String>,
+	body_words: usize,
+) -> Option<Entry> {
+	// The 404 page isn't content anyone searches for.
+	if !is_included(generated.group.as_deref(), rules)
+		|| generated.file.path.as_os_str() == NOT_FOUND_PAGE
+	{
+		return None;
+	}
+
+	let url = make_url(&generated.file.path);
+	let words = html_to_text(&generated.html_content);
+	let words = words.split_whitespace().filter(|word| {
+		let word = word
+			.trim_matches(|c: char| !c.is_alphanumeric())
+			.to_lowercase();
+		!word.is_empty() && !stop_words.contains(&word)
+	});
+	let body = if body_words > 0 {
+		words.take(body_words).collect::<Vec<_>>()
+	} else {
+		words.collect()
+	};
+
+	Some(Entry {
+		url,
+		title: generated.file.front_matter.title.clone(),
+		tags: generated.file.front_matter.tags.clone(),
+		headings: generated
+			.headings
+			.iter()
+			.map(|heading| heading.title.clone())
+			.collect(),
+		body: body.join(" "),
+	})
+}
+
+fn make_url(path: &Path) -> String {
+	let mut url = path
+		.components()
+		.map(|c| c.as_os_str().to_string_lossy())
+		.collect::<Vec<_>>()
+		.join("/");
+	if path.file_name() == Some(OsStr::new("index.html")) {
+		url.truncate(url.len() - "index.html".len());
+	}
+	url
+}
+
+// Text content of the HTML, without tags, comments, scripts, styles and the TeX
+// annotations of math.
+pub fn html_to_text(html: &str) -> String {
+	let mut text = String::with_capacity(html.len());
+	let mut rest = html;
+	while let Some(start) = rest.find('<') {
+		text.push_str(&rest[..start]);
+		rest = &rest[start..];
+		let name = rest[1..]
+			.trim_start_matches('/')
+			.split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+			.next()
+			.unwrap_or("")
+			.to_ascii_lowercase();
+		let end_marker = if rest.starts_with("<!--") {
+			"-->"
+		} else if rest.starts_with("</") {
+			">"
+		} else {
+			match name.as_str() {
+				"annotation" => "</annotation>",
+				"script" => "</script>",
+				"style" => "</style>",
+				_ => ">",
+			}
+		};
+		let end = if end_marker == ">" {
+			rest.find('>')
+		} else {
+			rest.to_ascii_lowercase().find(end_marker)
+		}
+		.map_or(rest.len(), |end| end + end_marker.len());
+		rest = &rest[end..];
+		// Block tags separate words, such as those of adjacent cells, while
+		// inline ones may be in the middle of a word.
+		if minify::BLOCK_ELEMENTS.contains(&name.as_str()) {
+			text.push(' ');
+		}
+	}
+	text.push_str(rest);
+	check::decode_entities(&text)
+}
+
+pub fn write_index(entries: &Entries, file_name: &Path) {
+	let entries = lock(entries);
+	if let Some(parent) = file_name.parent() {
+		fs::create_dir_all(parent).unwrap_or_else(|e| {
+			panic!("Failed creating {}: {}", parent.display(), e)
+		});
+	}
+	let mut file = fs::File::create(file_name).unwrap_or_else(|e| {
+		panic!("Failed creating {}: {}", file_name.display(), e)
+	});
+	write_to_stream(b"{\"version\":1,\"pages\":[", &mut file);
+	for (i, entry) in entries.values().enumerate() {
+		write_to_stream(
+			format!(
+				"{}\n{{\"url\":\"{}\",\"title\":\"{}\",\"tags\":{},\"headings\":{},\"body\":\"{}\"}}",
+				if i > 0 { "," } else { "" },
+				escape_json(&entry.url),
+				escape_json(&entry.title),
+				json_list(&entry.tags),
+				json_list(&entry.headings),
+				escape_json(&entry.body)
+			)
+			.as_bytes(),
+			&mut file,
+		);
+	}
+	write_to_stream(b"\n]}\n", &mut file);
+	// Avoiding sync_all() for now to be friendlier to disks.
+	file.sync_data().unwrap_or_else(|e| {
+		panic!("Failed sync_data() for \"{}\": {}.", file_name.display(), e)
+	});
+	println!("Wrote {}.", file_name.display());
+}
+
+fn lock(entries: &Entries) -> MutexGuard<'_, BTreeMap<String, Entry>> {
+	entries.lock().unwrap_or_else(|e| {
+		panic!("Failed acquiring search entries lock: {}", e)
+	})
+}
+
+fn json_list(values: &[String]) -> String {
+	let quoted = values
+		.iter()
+		.map(|value| format!("\"{}\"", escape_json(value)))
+		.collect::<Vec<_>>();
+	format!("[{}]", quoted.join(","))
+}
@@ -0,0 +1,152 @@
+// Shortcodes are Liquid templates under _shortcodes/, invoked by file name as
+// if they were built-in tags, such as {% figure src="a.png" %} for
+// _shortcodes/figure.html. Front matter in the template declares the
+// parameters along with their defaults, which also gives the order in which
+// positional arguments are assigned. Declaring body makes the shortcode take
+// one, ending at the matching end tag.
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use yaml_rust::{Yaml, YamlLoader};
+
+use crate::liquid::Value;
+
+pub const DIRECTORY: &str = "_shortcodes";
+
+// Parameter making the shortcode take a body, passed as a variable of the same
+// name rather than as an argument.
+pub const BODY: &str = "body";
+
+pub struct Template {
+	pub reader: BufReader<fs::File>,
+	pub parameters: Vec<(String, Value)>,
+	pub takes_body: bool,
+}
+
+pub fn find(root_input_dir: &Path, name: &str) -> Option<PathBuf> {
+	if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_')
+	{
+		return None;
+	}
+
+	let path = root_input_dir
+		.join(DIRECTORY)
+		.join(name)
+		.with_extension(crate::util::HTML_EXTENSION);
+	if path.exists() {
+		Some(path)
+	} else {
+		None
+	}
+}
+
+pub fn open(path: &Path) -> Template {
+	let mut reader = BufReader::new(fs::File::open(path).unwrap_or_else(|e| {
+		panic!("Failed opening \"{}\": {}.", path.display(), e)
+	}));
+
+	let mut line = String::new();
+	reader.read_line(&mut line).unwrap_or_else(|e| {
+		panic!("Failed reading first line of {}: {}", path.display(), e)
+	});
+	if line != "---\n" {
+		reader.seek(SeekFrom::Start(0)).unwrap_or_else(|e| {
+			panic!("Failed seeking in {}: {}", path.display(), e)
+		});
+		return Template {
+			reader,
+			parameters: Vec::new(),
+			takes_body: false,
+		};
+	}
+
+	let mut front_matter = String::new();
+	loop {
+		line.clear();
+		let size = reader.read_line(&mut line).unwrap_or_else(|e| {
+			panic!("Failed reading {}: {}", path.display(), e)
+		});
+		if size == 0 {
+			panic!("Missing end of front matter in {}.", path.display())
+		}
+		if line == "---\n" {
+			break;
+		}
+		front_matter.push_str(&line);
+	}
+
+	let yaml = YamlLoader::load_from_str(&front_matter).unwrap_or_else(|e| {
+		panic!(
+			"Failed loading YAML front matter from {}: {}",
+			path.display(),
+			e
+		)
+	});
+	let mut parameters = Vec::new();
+	let mut takes_body = false;
+	if let Some(Yaml::Hash(hash)) = yaml.first() {
+		for (key, value) in hash {
+			let key = if let Yaml::String(key) = key {
+				key.clone()
+			} else {
+				panic!(
+					"Expected string keys in front matter of {} but got {:?}.",
+					path.display(),
+					key
+				)
+			};
+			if key == BODY {
+				takes_body = true;
+				continue;
+			}
+			let value = match value {
+				Yaml::String(value) => Value::String(value.clone()),
+				Yaml::Boolean(value) => Value::Boolean(*value),
+				Yaml::Integer(value) => {
+					Value::Integer(i32::try_from(*value).unwrap_or_else(|e| {
+						panic!(
+							"Default of {} in {} out of range: {}",
+							key,
+							path.display(),
+							e
+						)
+					}))
+				}
+				_ => panic!(
+					"Default of {} in {} has unexpected type {:?}",
+					key,
+					path.display(),
+					value
+				),
+			};
+			parameters.push((key, value));
+		}
+	}
+
+	Template {
+		reader,
+		parameters,
+		takes_body,
+	}
+}
+
+// Whether the tag is a shortcode taking a body.
+pub fn takes_body(root_input_dir: &Path, name: &str) -> bool {
+	find(root_input_dir, name).is_some_and(|path| open(&path).takes_body)
+}
+
+// Splits key="value" or key=variable parameters.
+pub fn split_keyword(parameter: &str) -> Option<(&str, &str)> {
+	let equals = parameter.find('=')?;
+	let (key, value) = (&parameter[..equals], &parameter[equals + 1..]);
+	if key.is_empty()
+		|| key.starts_with('"')
+		|| value.is_empty()
+		|| !key.chars().all(|c| c.is_alphanumeric() || c == '_')
+	{
+		return None;
+	}
+	Some((key, value))
+}
@@ -0,0 +1,84 @@
+// Replaces straight quotes, dashes and ellipses in text with their
+// typographic counterparts, leaving code untouched.
+use pulldown_cmark::{CowStr, Event, Tag};
+
+pub fn apply<'a, I: Iterator<Item = Event<'a>>>(
+	events: I,
+) -> impl Iterator<Item = Event<'a>> {
+	let mut in_code_block = false;
+	let mut previous: Option<char> = None;
+	events.map(move |event| match event {
+		Event::Start(Tag::CodeBlock(_)) => {
+			in_code_block = true;
+			event
+		}
+		Event::End(Tag::CodeBlock(_)) => {
+			in_code_block = false;
+			event
+		}
+		Event::Start(Tag::Paragraph)
+		| Event::Start(Tag::Heading(_))
+		| Event::Start(Tag::Item)
+		| Event::Start(Tag::TableCell) => {
+			previous = None;
+			event
+		}
+		Event::SoftBreak | Event::HardBreak => {
+			previous = Some(' ');
+			event
+		}
+		Event::Code(_) => {
+			previous = Some('`');
+			event
+		}
+		Event::Text(text) if !in_code_block => {
+			Event::Text(CowStr::from(smarten(&text, &mut previous)))
+		}
+		_ => event,
+	})
+}
+
+pub fn smarten(text: &str, previous: &mut Option<char>) -> String {
+	let chars = text.chars().collect::<Vec<_>>();
+	let mut result = String::with_capacity(text.len());
+	let mut i = 0;
+	while i < chars.len() {
+		let c = chars[i];
+		let opening = previous.is_none_or(|p| {
+			p.is_whitespace() || "([{\u{2013}\u{2014}".contains(p)
+		});
+		let replacement = match c {
+			'"' => {
+				if opening {
+					'\u{201c}'
+				} else {
+					'\u{201d}'
+				}
+			}
+			'\'' => {
+				if opening {
+					'\u{2018}'
+				} else {
+					'\u{2019}'
+				}
+			}
+			'.' if chars[i..].starts_with(&['.', '.', '.']) => {
+				i += 2;
+				'\u{2026}'
+			}
+			'-' if chars[i..].starts_with(&['-', '-', '-']) => {
+				i += 2;
+				'\u{2014}'
+			}
+			'-' if chars[i..].starts_with(&['-', '-']) => {
+				i += 1;
+				'\u{2013}'
+			}
+			_ => c,
+		};
+		result.push(replacement);
+		*previous = Some(replacement);
+		i += 1;
+	}
+	result
+}
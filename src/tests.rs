@@ -1,26 +1,136 @@
 use std::collections::{BTreeMap, HashMap};
-use std::io::{BufReader, BufWriter, Cursor};
-use std::path::PathBuf;
+use std::io::{BufReader, BufWriter, Cursor, Read};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
+use crate::archive;
+use crate::assets;
+use crate::backlinks;
+use crate::check;
+use crate::config;
+use crate::css;
+use crate::feed;
+use crate::feed::FeedFormat;
 use crate::front_matter::FrontMatter;
+use crate::headings::Heading;
+use crate::http;
+use crate::image;
 use crate::liquid;
-use crate::markdown::{GroupedOptionOutputFile, InputFile, OptionOutputFile};
-use crate::util::SiteInfo;
+use crate::markdown;
+use crate::markdown::{
+	Extensions, GeneratedFile, GroupedOptionOutputFile, Indexes, InputFile,
+	OptionOutputFile, OutputFile,
+};
+use crate::minify;
+use crate::neighbours;
+use crate::neighbours::Neighbours;
+use crate::precompress;
+use crate::robots;
+use crate::robots::Rule;
+use crate::rss;
+use crate::search;
+use crate::util::{BuildState, SiteInfo};
+
+fn make_site_info() -> SiteInfo<'static> {
+	SiteInfo {
+		title: "Site",
+		base_url: "https://example.com/",
+		author: "Author",
+		email: None,
+		markdown_extensions: Extensions {
+			footnotes: true,
+			heading_anchors: false,
+			math: true,
+			smart_punctuation: true,
+			strikethrough: true,
+			tasklists: true,
+		},
+		admonitions: Box::leak(Box::new(config::default_admonitions())),
+		image_sizes: "",
+		image_widths: &[],
+		minify: None,
+		feeds: Box::leak(Box::new(BTreeMap::new())),
+		highlighted: Box::leak(Box::new(AtomicBool::new(false))),
+		links: Box::leak(Box::new(backlinks::Graph::default())),
+		related_limit: 5,
+		search: None,
+	}
+}
+
+fn render_markdown(
+	markdown_content: &str,
+	site_info: &SiteInfo,
+	front_matter: &FrontMatter,
+) -> (String, Vec<Heading>) {
+	markdown::render(
+		markdown_content,
+		&liquid::Context {
+			input_file_path: &PathBuf::from("./input/a.md"),
+			output_file_path: &PathBuf::from("./output/a.html"),
+			front_matter,
+			html_content: None,
+			headings: &[],
+			root_input_dir: &PathBuf::from("./input"),
+			root_output_dir: &PathBuf::from("./output"),
+			input_output_map: &HashMap::new(),
+			groups: &HashMap::new(),
+			site_info,
+		},
+	)
+}
+
+fn to_html(markdown_content: &str) -> String {
+	render_markdown(
+		markdown_content,
+		&make_site_info(),
+		&FrontMatter::with_title("A".to_string()),
+	)
+	.0
+}
 
 fn make_front_matter(title: &str, date: Option<&str>) -> Arc<FrontMatter> {
-	Arc::new(FrontMatter {
-		title: title.to_string(),
-		date: date.map(|s| s.to_string()),
-		published: true,
-		edited: None,
-		categories: Vec::new(),
-		tags: Vec::new(),
-		layout: None,
-		custom_attributes: BTreeMap::new(),
-		end_position: 0,
-		subsequent_line: 1,
-	})
+	let mut front_matter = FrontMatter::with_title(title.to_string());
+	front_matter.date = date.map(|s| s.to_string());
+	Arc::new(front_matter)
+}
+
+// Directory of a test under the system temp directory, removed when dropped so
+// that failing tests don't leave it behind.
+struct TempDir(PathBuf);
+
+impl TempDir {
+	fn new(name: &str) -> Self {
+		let path = std::env::temp_dir().join(format!(
+			"sitegen_test_{}_{}",
+			name,
+			std::process::id()
+		));
+		std::fs::create_dir_all(&path).unwrap();
+		Self(path)
+	}
+}
+
+impl Deref for TempDir {
+	type Target = PathBuf;
+
+	fn deref(&self) -> &PathBuf {
+		&self.0
+	}
+}
+
+impl AsRef<Path> for TempDir {
+	fn as_ref(&self) -> &Path {
+		&self.0
+	}
+}
+
+impl Drop for TempDir {
+	fn drop(&mut self) {
+		let _ = std::fs::remove_dir_all(&self.0);
+	}
 }
 
 #[test]
@@ -41,6 +151,8 @@ fn test_liquid_link() {
 				path: output_file_path.clone(),
 			},
 			group: None,
+			backlinks: Vec::new(),
+			neighbours: Neighbours::default(),
 		},
 	);
 
@@ -54,11 +166,12 @@ fn test_liquid_link() {
 			output_file_path: &output_file_path,
 			front_matter: &front_matter,
 			html_content: None,
+			headings: &[],
 			root_input_dir: &PathBuf::from("./input"),
 			root_output_dir: &PathBuf::from("./output"),
 			input_output_map: &input_output_map,
 			groups: &HashMap::new(),
-			site_info: &SiteInfo { title: "Site" },
+			site_info: &make_site_info(),
 		},
 	);
 
@@ -89,6 +202,8 @@ fn test_liquid_unfinished() {
 				path: output_file_path.clone(),
 			},
 			group: None,
+			backlinks: Vec::new(),
+			neighbours: Neighbours::default(),
 		},
 	);
 
@@ -104,11 +219,42 @@ fn test_liquid_unfinished() {
 			output_file_path: &output_file_path,
 			front_matter: &front_matter,
 			html_content: None,
+			headings: &[],
 			root_input_dir: &PathBuf::from("./input"),
 			root_output_dir: &PathBuf::from("./output"),
 			input_output_map: &input_output_map,
 			groups: &HashMap::new(),
-			site_info: &SiteInfo { title: "Site" },
+			site_info: &make_site_info(),
+		},
+	);
+}
+
+#[test]
+#[should_panic(
+	expected = "Content of ./input/virtual_test.md ended inside of unclosed block"
+)]
+fn test_liquid_unclosed() {
+	let front_matter = make_front_matter("Title", None);
+	let mut input_file = BufReader::new(Cursor::new(
+		(r#"{% if page.title == "A" %}A"#).as_bytes(),
+	));
+
+	// Expecting panic here:
+	liquid::process(
+		&mut input_file,
+		&mut BufWriter::new(Vec::new()),
+		HashMap::new(),
+		&liquid::Context {
+			input_file_path: &PathBuf::from("./input/virtual_test.md"),
+			output_file_path: &PathBuf::from("./output/virtual_test.html"),
+			front_matter: &front_matter,
+			html_content: None,
+			headings: &[],
+			root_input_dir: &PathBuf::from("./input"),
+			root_output_dir: &PathBuf::from("./output"),
+			input_output_map: &HashMap::new(),
+			groups: &HashMap::new(),
+			site_info: &make_site_info(),
 		},
 	);
 }
@@ -138,6 +284,8 @@ fn test_liquid_for() {
 				path: output_file_path_a.clone(),
 			},
 			group: None,
+			backlinks: Vec::new(),
+			neighbours: Neighbours::default(),
 		},
 	);
 	input_output_map.insert(
@@ -148,6 +296,8 @@ fn test_liquid_for() {
 				path: output_file_path_b,
 			},
 			group: None,
+			backlinks: Vec::new(),
+			neighbours: Neighbours::default(),
 		},
 	);
 
@@ -176,11 +326,12 @@ fn test_liquid_for() {
 			output_file_path: &output_file_path_a,
 			front_matter: &front_matter_a,
 			html_content: None,
+			headings: &[],
 			root_input_dir: &PathBuf::from("./input"),
 			root_output_dir: &PathBuf::from("./output"),
 			input_output_map: &input_output_map,
 			groups: &groups,
-			site_info: &SiteInfo { title: "Site" },
+			site_info: &make_site_info(),
 		},
 	);
 
@@ -211,6 +362,8 @@ fn test_liquid_date() {
 				path: output_file_path.clone(),
 			},
 			group: None,
+			backlinks: Vec::new(),
+			neighbours: Neighbours::default(),
 		},
 	);
 
@@ -224,11 +377,12 @@ fn test_liquid_date() {
 			output_file_path: &output_file_path,
 			front_matter: &front_matter,
 			html_content: None,
+			headings: &[],
 			root_input_dir: &PathBuf::from("./input"),
 			root_output_dir: &PathBuf::from("./output"),
 			input_output_map: &input_output_map,
 			groups: &HashMap::new(),
-			site_info: &SiteInfo { title: "Site" },
+			site_info: &make_site_info(),
 		},
 	);
 
@@ -258,6 +412,8 @@ fn test_liquid_upcase() {
 				path: output_file_path.clone(),
 			},
 			group: None,
+			backlinks: Vec::new(),
+			neighbours: Neighbours::default(),
 		},
 	);
 
@@ -271,11 +427,12 @@ fn test_liquid_upcase() {
 			output_file_path: &output_file_path,
 			front_matter: &front_matter,
 			html_content: None,
+			headings: &[],
 			root_input_dir: &PathBuf::from("./input"),
 			root_output_dir: &PathBuf::from("./output"),
 			input_output_map: &input_output_map,
 			groups: &HashMap::new(),
-			site_info: &SiteInfo { title: "Site" },
+			site_info: &make_site_info(),
 		},
 	);
 
@@ -305,6 +462,8 @@ fn test_liquid_upcase_downcase() {
 				path: output_file_path.clone(),
 			},
 			group: None,
+			backlinks: Vec::new(),
+			neighbours: Neighbours::default(),
 		},
 	);
 
@@ -318,11 +477,12 @@ fn test_liquid_upcase_downcase() {
 			output_file_path: &output_file_path,
 			front_matter: &front_matter,
 			html_content: None,
+			headings: &[],
 			root_input_dir: &PathBuf::from("./input"),
 			root_output_dir: &PathBuf::from("./output"),
 			input_output_map: &input_output_map,
 			groups: &HashMap::new(),
-			site_info: &SiteInfo { title: "Site" },
+			site_info: &make_site_info(),
 		},
 	);
 
@@ -355,6 +515,8 @@ These shoes are awesome!
 				path: output_file_path.clone(),
 			},
 			group: None,
+			backlinks: Vec::new(),
+			neighbours: Neighbours::default(),
 		},
 	);
 
@@ -368,11 +530,12 @@ These shoes are awesome!
 			output_file_path: &output_file_path,
 			front_matter: &front_matter,
 			html_content: None,
+			headings: &[],
 			root_input_dir: &PathBuf::from("./input"),
 			root_output_dir: &PathBuf::from("./output"),
 			input_output_map: &input_output_map,
 			groups: &HashMap::new(),
-			site_info: &SiteInfo { title: "Site" },
+			site_info: &make_site_info(),
 		},
 	);
 
@@ -403,6 +566,8 @@ fn test_liquid_assign() {
 				path: output_file_path.clone(),
 			},
 			group: None,
+			backlinks: Vec::new(),
+			neighbours: Neighbours::default(),
 		},
 	);
 
@@ -416,11 +581,12 @@ fn test_liquid_assign() {
 			output_file_path: &output_file_path,
 			front_matter: &front_matter,
 			html_content: None,
+			headings: &[],
 			root_input_dir: &PathBuf::from("./input"),
 			root_output_dir: &PathBuf::from("./output"),
 			input_output_map: &input_output_map,
 			groups: &HashMap::new(),
-			site_info: &SiteInfo { title: "Site" },
+			site_info: &make_site_info(),
 		},
 	);
 
@@ -431,3 +597,2383 @@ fn test_liquid_assign() {
 		"assign works"
 	);
 }
+
+#[test]
+fn test_archive_periods() {
+	let front_matter_a = make_front_matter("A", Some("2019-12-24T10:00:00Z"));
+	let front_matter_b = make_front_matter("B", Some("2020-05-01T00:00:00Z"));
+	let front_matter_c = make_front_matter("C", Some("2020-06-11T00:00:00Z"));
+
+	let mut archives = HashMap::new();
+	for (file_name, front_matter) in &[
+		("a.md", &front_matter_a),
+		("b.md", &front_matter_b),
+		("c.md", &front_matter_c),
+	] {
+		let path = PathBuf::from("./input/posts").join(file_name);
+		for period in
+			archive::get_periods(&path, Path::new("./input"), front_matter)
+		{
+			archives
+				.entry(period)
+				.or_insert_with(Vec::new)
+				.push(InputFile {
+					front_matter: (*front_matter).clone(),
+					path: path.clone(),
+				});
+		}
+	}
+
+	assert_eq!(
+		archive::get_periods(
+			Path::new("./input/posts/b.md"),
+			Path::new("./input"),
+			&front_matter_b
+		),
+		vec![PathBuf::from("posts/2020"), PathBuf::from("posts/2020/05")]
+	);
+	assert_eq!(archives[&PathBuf::from("posts/2020")].len(), 2);
+	assert_eq!(
+		archive::get_neighbours(&PathBuf::from("posts/2020/05"), &archives),
+		(
+			Some(&PathBuf::from("posts/2019/12")),
+			Some(&PathBuf::from("posts/2020/06"))
+		)
+	);
+	assert_eq!(
+		archive::get_neighbours(&PathBuf::from("posts/2019"), &archives),
+		(None, Some(&PathBuf::from("posts/2020")))
+	);
+	assert_eq!(
+		archive::make_front_matter(&PathBuf::from("posts/2020/05")).title,
+		"Posts: 2020-05"
+	);
+}
+
+#[test]
+fn test_archive_nested_groups() {
+	let dir = TempDir::new("archive_nested");
+	let input_dir = dir.join("input");
+	let output_dir = dir.join("output");
+	std::fs::create_dir_all(input_dir.join("_layouts")).unwrap();
+	std::fs::write(input_dir.join("_layouts/default.html"), "").unwrap();
+	std::fs::write(input_dir.join(archive::TEMPLATE_PATH), "").unwrap();
+	for (name, date) in &[
+		("blog/posts/a.md", "2020-05-01T00:00:00Z"),
+		("news/posts/b.md", "2021-06-01T00:00:00Z"),
+	] {
+		let input_file_path = input_dir.join(name);
+		std::fs::create_dir_all(input_file_path.parent().unwrap()).unwrap();
+		std::fs::write(
+			&input_file_path,
+			format!("---\ntitle: {}\ndate: {}\n---\n", name, date),
+		)
+		.unwrap();
+	}
+
+	// Both are in the posts group, but get archives of their own directory.
+	let fileset = crate::build_initial_fileset(
+		&mut markdown::get_files(&input_dir),
+		&input_dir,
+		&output_dir,
+		false,
+		"",
+		&BTreeMap::new(),
+		None,
+	);
+	assert_eq!(fileset.indexes.groups["posts"].len(), 2);
+	let mut periods =
+		fileset.indexes.archives.keys().cloned().collect::<Vec<_>>();
+	periods.sort();
+	assert_eq!(
+		periods,
+		vec![
+			PathBuf::from("blog/posts/2020"),
+			PathBuf::from("blog/posts/2020/05"),
+			PathBuf::from("news/posts/2021"),
+			PathBuf::from("news/posts/2021/06")
+		]
+	);
+	assert_eq!(
+		fileset.input_output_map
+			[&input_dir.join("blog/posts/2020/05/index.html")]
+			.file
+			.path,
+		output_dir.join("blog/posts/2020/05/index.html")
+	);
+	assert_eq!(
+		archive::get_neighbours(
+			&PathBuf::from("blog/posts/2020"),
+			&fileset.indexes.archives
+		),
+		(None, None)
+	);
+	assert_eq!(
+		archive::make_front_matter(&PathBuf::from("news/posts/2021/06")).title,
+		"Posts: 2021-06"
+	);
+}
+
+#[test]
+fn test_archive_reindex() {
+	let dir = TempDir::new("archive");
+	let input_dir = dir.join("input");
+	let output_dir = dir.join("output");
+	std::fs::create_dir_all(input_dir.join("_layouts")).unwrap();
+	std::fs::create_dir_all(input_dir.join("posts")).unwrap();
+	std::fs::write(input_dir.join("_layouts/default.html"), "").unwrap();
+	std::fs::write(
+		input_dir.join(archive::TEMPLATE_PATH),
+		"{% if previous.count > 0 %}{% link previous.link %}{% endif %}|\
+		{% if next.count > 0 %}{% link next.link %}{% endif %}|\
+		{% for e in entries %}{{ e.title }};{% endfor %}",
+	)
+	.unwrap();
+
+	let mut input_output_map = HashMap::new();
+	let mut indexes = Indexes::default();
+	let mut reindex = |name: &str, date: &str| {
+		let input_file_path = input_dir.join("posts").join(name);
+		std::fs::write(
+			&input_file_path,
+			format!("---\ntitle: {}\ndate: {}\n---\n", name, date),
+		)
+		.unwrap();
+		let grouped_file = markdown::parse_fm_and_compute_output_path(
+			&input_file_path,
+			&input_dir,
+			&output_dir,
+			"",
+		);
+		markdown::reindex(
+			&input_file_path,
+			&grouped_file,
+			&input_dir,
+			&output_dir,
+			&mut input_output_map,
+			&mut indexes,
+			&make_site_info(),
+		);
+		let mut periods = indexes.archives.keys().cloned().collect::<Vec<_>>();
+		periods.sort();
+		periods
+	};
+	let read = |period: &str| {
+		std::fs::read_to_string(output_dir.join(period).join("index.html"))
+	};
+
+	reindex("a.md", "2020-05-01T00:00:00Z");
+	reindex("b.md", "2020-06-01T00:00:00Z");
+	assert_eq!(read("posts/2020/05").unwrap(), "|../06/|a.md;");
+	assert_eq!(read("posts/2020").unwrap(), "||b.md;a.md;");
+
+	// Moving the only entry of a month drops its page.
+	assert_eq!(
+		reindex("a.md", "2019-12-01T00:00:00Z"),
+		vec![
+			PathBuf::from("posts/2019"),
+			PathBuf::from("posts/2019/12"),
+			PathBuf::from("posts/2020"),
+			PathBuf::from("posts/2020/06")
+		]
+	);
+	assert!(read("posts/2020/05").is_err());
+	assert_eq!(read("posts/2020").unwrap(), "../2019/||b.md;");
+	assert_eq!(read("posts/2020/06").unwrap(), "../../2019/12/||b.md;");
+}
+
+#[test]
+fn test_archive_collision() {
+	let dir = TempDir::new("archive_collision");
+	let input_dir = dir.join("input");
+	let output_dir = dir.join("output");
+	std::fs::create_dir_all(input_dir.join("_layouts")).unwrap();
+	std::fs::create_dir_all(input_dir.join("posts/2020/05")).unwrap();
+	std::fs::write(input_dir.join("_layouts/default.html"), "").unwrap();
+	std::fs::write(input_dir.join(archive::TEMPLATE_PATH), "").unwrap();
+	let post = |name: &str, date: &str| {
+		let input_file_path = input_dir.join("posts").join(name);
+		std::fs::write(
+			&input_file_path,
+			format!("---\ntitle: {}\ndate: {}\n---\n", name, date),
+		)
+		.unwrap();
+		input_file_path
+	};
+	post("a.md", "2020-05-01T00:00:00Z");
+	std::fs::write(
+		input_dir.join("posts/2020/index.html"),
+		"---\ntitle: Year\n---\n",
+	)
+	.unwrap();
+	std::fs::write(
+		input_dir.join("posts/2020/05/index.md"),
+		"---\ntitle: Month\n---\n",
+	)
+	.unwrap();
+
+	// Real pages are kept in place of the archive pages.
+	let mut fileset = crate::build_initial_fileset(
+		&mut markdown::get_files(&input_dir),
+		&input_dir,
+		&output_dir,
+		false,
+		"",
+		&BTreeMap::new(),
+		None,
+	);
+	assert!(fileset.indexes.archives.is_empty());
+	assert_eq!(
+		fileset.input_output_map[&input_dir.join("posts/2020/index.html")]
+			.file
+			.front_matter
+			.as_ref()
+			.unwrap()
+			.title,
+		"Year"
+	);
+
+	let input_file_path = post("b.md", "2020-05-02T00:00:00Z");
+	let grouped_file = markdown::parse_fm_and_compute_output_path(
+		&input_file_path,
+		&input_dir,
+		&output_dir,
+		"",
+	);
+	markdown::reindex(
+		&input_file_path,
+		&grouped_file,
+		&input_dir,
+		&output_dir,
+		&mut fileset.input_output_map,
+		&mut fileset.indexes,
+		&make_site_info(),
+	);
+	assert!(fileset.indexes.archives.is_empty());
+	assert!(!output_dir.join("posts/2020/index.html").exists());
+}
+
+#[test]
+fn test_liquid_page_neighbours() {
+	let input_file_paths = [
+		PathBuf::from("./input/posts/a.md"),
+		PathBuf::from("./input/posts/b.md"),
+		PathBuf::from("./input/posts/c.md"),
+	];
+	let output_file_paths = [
+		PathBuf::from("./output/posts/a.html"),
+		PathBuf::from("./output/posts/b.html"),
+		PathBuf::from("./output/posts/c.html"),
+	];
+	let mut front_matters = Vec::new();
+	for (title, date, tags) in &[
+		("A", "2020-03-01T00:00:00Z", vec!["rust", "web"]),
+		("B", "2020-02-01T00:00:00Z", vec!["rust"]),
+		("C", "2020-01-01T00:00:00Z", vec!["rust", "web"]),
+	] {
+		let mut front_matter = FrontMatter::with_title(title.to_string());
+		front_matter.date = Some(date.to_string());
+		front_matter.tags = tags.iter().map(|t| t.to_string()).collect();
+		front_matters.push(Arc::new(front_matter));
+	}
+
+	let mut input_output_map = HashMap::new();
+	let mut entries = Vec::new();
+	for i in 0..3 {
+		input_output_map.insert(
+			input_file_paths[i].clone(),
+			GroupedOptionOutputFile {
+				file: OptionOutputFile {
+					front_matter: Some(front_matters[i].clone()),
+					path: output_file_paths[i].clone(),
+				},
+				group: Some("posts".to_string()),
+				backlinks: Vec::new(),
+				neighbours: Neighbours::default(),
+			},
+		);
+		entries.push(InputFile {
+			front_matter: front_matters[i].clone(),
+			path: input_file_paths[i].clone(),
+		});
+	}
+	let mut groups = HashMap::new();
+	groups.insert("posts".to_string(), entries);
+
+	let render = |input_output_map: &HashMap<_, _>| {
+		let mut input_file = BufReader::new(Cursor::new(
+			(r#"{{ page.collection }}: {{ page.previous.title }}/{{ page.next.title }}{% for r in page.related %} {{ r.title }}{% endfor %}{% if page.next.count > 0 %}!{% endif %}"#).as_bytes(),
+		));
+
+		let mut processed_markdown_content = BufWriter::new(Vec::new());
+		liquid::process(
+			&mut input_file,
+			&mut processed_markdown_content,
+			HashMap::new(),
+			&liquid::Context {
+				input_file_path: &PathBuf::from("./input/_layouts/post.html"),
+				output_file_path: &output_file_paths[1],
+				front_matter: &front_matters[1],
+				html_content: None,
+				headings: &[],
+				root_input_dir: &PathBuf::from("./input"),
+				root_output_dir: &PathBuf::from("./output"),
+				input_output_map,
+				groups: &groups,
+				site_info: &make_site_info(),
+			},
+		);
+		String::from_utf8(processed_markdown_content.into_inner().unwrap())
+			.unwrap()
+	};
+
+	neighbours::update(&mut input_output_map, &groups, 5);
+	assert_eq!(render(&input_output_map), "posts: C/A A C!");
+
+	// Related entries are capped by related_limit.
+	neighbours::update(&mut input_output_map, &groups, 1);
+	assert_eq!(render(&input_output_map), "posts: C/A A!");
+}
+
+#[test]
+fn test_excerpt() {
+	assert_eq!(
+		markdown::extract_excerpt(
+			"# Heading\n\nFirst *paragraph*\ncontinued.\n\nSecond.\n",
+			"<!--more-->",
+			Path::new("./input")
+		),
+		"First *paragraph*\ncontinued.\n"
+	);
+	assert_eq!(
+		markdown::extract_excerpt(
+			"\nIntro.\n\nMore intro.\n<!--more-->\nRest.\n",
+			"<!--more-->",
+			Path::new("./input")
+		),
+		"Intro.\n\nMore intro."
+	);
+	// Blank lines inside blocks don't end the first paragraph.
+	assert_eq!(
+		markdown::extract_excerpt(
+			"{% highlight rust %}\nfn a() {}\n\nfn b() {}\n{% endhighlight %}\n\
+			\n\
+			```\nc\n\n```\n\
+			\n\
+			Second.\n",
+			"",
+			Path::new("./input")
+		),
+		"{% highlight rust %}\nfn a() {}\n\nfn b() {}\n{% endhighlight %}\n"
+	);
+	assert_eq!(
+		markdown::extract_excerpt(
+			"{% if page.title %}\nA\n\n{% endif %} B\n````\n```\n\n````\n\nC\n",
+			"",
+			Path::new("./input")
+		),
+		"{% if page.title %}\nA\n\n{% endif %} B\n````\n```\n\n````\n"
+	);
+
+	let mut front_matter = FrontMatter::with_title("A".to_string());
+	front_matter.excerpt = Some("Hello *{{ page.title }}*".to_string());
+	let front_matter = Arc::new(front_matter);
+
+	let mut input_file =
+		BufReader::new(Cursor::new((r#"{{ page.excerpt }}"#).as_bytes()));
+
+	let mut processed_markdown_content = BufWriter::new(Vec::new());
+	liquid::process(
+		&mut input_file,
+		&mut processed_markdown_content,
+		HashMap::new(),
+		&liquid::Context {
+			input_file_path: &PathBuf::from("./input/posts/a.md"),
+			output_file_path: &PathBuf::from("./output/posts/a.html"),
+			front_matter: &front_matter,
+			html_content: None,
+			headings: &[],
+			root_input_dir: &PathBuf::from("./input"),
+			root_output_dir: &PathBuf::from("./output"),
+			input_output_map: &HashMap::new(),
+			groups: &HashMap::new(),
+			site_info: &make_site_info(),
+		},
+	);
+
+	assert_eq!(
+		String::from_utf8_lossy(
+			&processed_markdown_content.into_inner().unwrap()
+		),
+		"<p>Hello <em>A</em></p>\n"
+	);
+	// Rendered once for the directory.
+	assert_eq!(front_matter.excerpt_html.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn test_markdown_extensions() {
+	assert_eq!(
+		to_html("\"Don't\" -- ~~really~~...\n\n- [x] `\"code\"`\n"),
+		"<p>\u{201c}Don\u{2019}t\u{201d} \u{2013} <del>really</del>\u{2026}</p>\n\
+		<ul>\n<li><input disabled=\"\" type=\"checkbox\" checked=\"\"/>\n<code>&quot;code&quot;</code></li>\n</ul>\n"
+	);
+
+	assert_eq!(
+		to_html("Text[^b] and[^a].\n\n[^a]: First.\n\n[^b]: Second.\n"),
+		"<p>Text<sup class=\"footnote-ref\"><a href=\"#fn-b\" id=\"fnref-b\" role=\"doc-noteref\">1</a></sup> and<sup class=\"footnote-ref\"><a href=\"#fn-a\" id=\"fnref-a\" role=\"doc-noteref\">2</a></sup>.</p>\n\
+		<section class=\"footnotes\" role=\"doc-endnotes\">\n<hr />\n<ol>\n\
+		<li id=\"fn-b\">\n<p>Second. <a href=\"#fnref-b\" class=\"footnote-backref\" role=\"doc-backlink\" aria-label=\"Back to reference 1\">\u{21a9}\u{fe0e}</a></p>\n</li>\n\
+		<li id=\"fn-a\">\n<p>First. <a href=\"#fnref-a\" class=\"footnote-backref\" role=\"doc-backlink\" aria-label=\"Back to reference 2\">\u{21a9}\u{fe0e}</a></p>\n</li>\n\
+		</ol>\n</section>\n"
+	);
+
+	let mut front_matter = FrontMatter::with_title("A".to_string());
+	front_matter.strikethrough = Some(false);
+	assert_eq!(
+		render_markdown("~~a~~", &make_site_info(), &front_matter).0,
+		"<p>~~a~~</p>\n"
+	);
+
+	// Existing sites keep their output unless the extensions are turned on.
+	let config = config::Args::new().values();
+	let state = BuildState::default();
+	let site_info = config::make_site_info(&config, &state);
+	let mut front_matter = FrontMatter::with_title("A".to_string());
+	assert_eq!(
+		render_markdown(
+			"\"Don't\" -- ~~a~~ $x$\n\n- [ ] b\n",
+			&site_info,
+			&front_matter
+		)
+		.0,
+		"<p>&quot;Don't&quot; -- ~~a~~ $x$</p>\n<ul>\n<li>[ ] b</li>\n</ul>\n"
+	);
+	front_matter.smart_punctuation = Some(true);
+	assert_eq!(
+		render_markdown("\"a\"", &site_info, &front_matter).0,
+		"<p>\u{201c}a\u{201d}</p>\n"
+	);
+}
+
+#[test]
+fn test_headings() {
+	let mut site_info = make_site_info();
+	site_info.markdown_extensions.heading_anchors = true;
+	let (html_content, headings) = render_markdown(
+		"# Intro\n\n## Set-up & `cargo`\n\n### Details\n\n## Intro\n",
+		&site_info,
+		&FrontMatter::with_title("A".to_string()),
+	);
+	assert_eq!(
+		html_content,
+		"<h1 id=\"intro\">Intro <a class=\"heading-anchor\" href=\"#intro\" aria-hidden=\"true\">#</a></h1>\n\
+		<h2 id=\"set-up-cargo\">Set-up &amp; <code>cargo</code> <a class=\"heading-anchor\" href=\"#set-up-cargo\" aria-hidden=\"true\">#</a></h2>\n\
+		<h3 id=\"details\">Details <a class=\"heading-anchor\" href=\"#details\" aria-hidden=\"true\">#</a></h3>\n\
+		<h2 id=\"intro-1\">Intro <a class=\"heading-anchor\" href=\"#intro-1\" aria-hidden=\"true\">#</a></h2>\n"
+	);
+
+	let front_matter = make_front_matter("A", None);
+	let mut input_file = BufReader::new(Cursor::new(
+		(r#"{% for h in page.toc.entries %}{{ h.level }}:{{ h.id }} {% endfor %}{{ page.toc.html }}"#).as_bytes(),
+	));
+
+	let mut processed_content = BufWriter::new(Vec::new());
+	liquid::process(
+		&mut input_file,
+		&mut processed_content,
+		HashMap::new(),
+		&liquid::Context {
+			input_file_path: &PathBuf::from("./input/_layouts/default.html"),
+			output_file_path: &PathBuf::from("./output/a.html"),
+			front_matter: &front_matter,
+			html_content: Some(&html_content),
+			headings: &headings,
+			root_input_dir: &PathBuf::from("./input"),
+			root_output_dir: &PathBuf::from("./output"),
+			input_output_map: &HashMap::new(),
+			groups: &HashMap::new(),
+			site_info: &make_site_info(),
+		},
+	);
+
+	assert_eq!(
+		String::from_utf8_lossy(&processed_content.into_inner().unwrap()),
+		"1:intro 2:set-up-cargo 3:details 2:intro-1 \
+		<ul>\n<li><a href=\"#intro\">Intro</a>\n\
+		<ul>\n<li><a href=\"#set-up-cargo\">Set-up &amp; cargo</a>\n\
+		<ul>\n<li><a href=\"#details\">Details</a></li>\n</ul>\n\
+		</li>\n<li><a href=\"#intro-1\">Intro</a></li>\n</ul>\n\
+		</li>\n</ul>\n"
+	);
+}
+
+#[test]
+fn test_highlight() {
+	assert_eq!(
+		to_html(
+			"```rust\nfn main() { // Hi\n\tlet s = \"a<b\";\n}\n```\n"
+		),
+		"<pre class=\"highlight\"><code class=\"language-rust\">\
+		<span class=\"hl-keyword\">fn</span> <span class=\"hl-function\">main</span>() { <span class=\"hl-comment\">// Hi</span>\n\
+		\t<span class=\"hl-keyword\">let</span> s = <span class=\"hl-string\">&quot;a&lt;b&quot;</span>;\n\
+		}\n</code></pre>\n"
+	);
+
+	let front_matter = make_front_matter("A", None);
+	let mut input_file = BufReader::new(Cursor::new(
+		(r#"{% highlight python linenos %}
+# {{ page.title }}
+x = 1
+{% endhighlight %}"#)
+			.as_bytes(),
+	));
+
+	let site_info = make_site_info();
+	let mut processed_content = BufWriter::new(Vec::new());
+	liquid::process(
+		&mut input_file,
+		&mut processed_content,
+		HashMap::new(),
+		&liquid::Context {
+			input_file_path: &PathBuf::from("./input/a.html"),
+			output_file_path: &PathBuf::from("./output/a.html"),
+			front_matter: &front_matter,
+			html_content: None,
+			headings: &[],
+			root_input_dir: &PathBuf::from("./input"),
+			root_output_dir: &PathBuf::from("./output"),
+			input_output_map: &HashMap::new(),
+			groups: &HashMap::new(),
+			site_info: &site_info,
+		},
+	);
+
+	assert_eq!(
+		String::from_utf8_lossy(&processed_content.into_inner().unwrap()),
+		"<pre class=\"highlight\"><code class=\"language-python\">\
+		<span class=\"hl-lineno\">1</span><span class=\"hl-comment\"># A</span>\n\
+		<span class=\"hl-lineno\">2</span>x = <span class=\"hl-number\">1</span>\n\
+		</code></pre>\n"
+	);
+	assert!(site_info.highlighted.load(Ordering::Relaxed));
+
+	// The stylesheet is only written for sites with highlighted code.
+	let site_info = make_site_info();
+	let front_matter = FrontMatter::with_title("A".to_string());
+	render_markdown("```\nplain\n```\n", &site_info, &front_matter);
+	assert!(!site_info.highlighted.load(Ordering::Relaxed));
+	render_markdown("```rust\nfn f() {}\n```\n", &site_info, &front_matter);
+	assert!(site_info.highlighted.load(Ordering::Relaxed));
+}
+
+#[test]
+fn test_shortcodes() {
+	let root_input_dir = TempDir::new("shortcodes");
+	let shortcodes_dir = root_input_dir.join("_shortcodes");
+	std::fs::create_dir_all(&shortcodes_dir).unwrap();
+	std::fs::write(
+		shortcodes_dir.join("figure.html"),
+		"---\nsrc: \"\"\ncaption: \"\"\n---\n<figure><img src=\"{{ src }}\">{% if caption != \"\" %}<figcaption>{{ caption }}</figcaption>{% endif %}</figure>",
+	)
+	.unwrap();
+	std::fs::write(
+		shortcodes_dir.join("note.html"),
+		"---\nbody: \"\"\n---\n<aside class=\"note\">{% for a in args %}{{ a }}: {% endfor %}{{ body }}</aside>",
+	)
+	.unwrap();
+
+	let front_matter = make_front_matter("A", None);
+	let mut input_file = BufReader::new(Cursor::new(
+		(r#"{% figure src="a.png" caption="A cat" %}
+{% figure "b.png" %}
+{% note "Heads up" %}*Careful* with {% figure "c.png" %}{% endnote %}
+{% note %}{% note "Inner" %}x{% endnote %}{% endnote %}"#)
+			.as_bytes(),
+	));
+
+	let mut processed_content = BufWriter::new(Vec::new());
+	liquid::process(
+		&mut input_file,
+		&mut processed_content,
+		HashMap::new(),
+		&liquid::Context {
+			input_file_path: &root_input_dir.join("a.md"),
+			output_file_path: &PathBuf::from("./output/a.html"),
+			front_matter: &front_matter,
+			html_content: None,
+			headings: &[],
+			root_input_dir: &root_input_dir,
+			root_output_dir: &PathBuf::from("./output"),
+			input_output_map: &HashMap::new(),
+			groups: &HashMap::new(),
+			site_info: &make_site_info(),
+		},
+	);
+
+	assert_eq!(
+		String::from_utf8_lossy(&processed_content.into_inner().unwrap()),
+		"<figure><img src=\"a.png\"><figcaption>A cat</figcaption></figure>\n\
+		<figure><img src=\"b.png\"></figure>\n\
+		<aside class=\"note\">Heads up: <p><em>Careful</em> with <figure><img src=\"c.png\"></figure></p>\n</aside>\n\
+		<aside class=\"note\"><aside class=\"note\">Inner: <p>x</p>\n</aside></aside>"
+	);
+}
+
+#[test]
+fn test_admonitions() {
+	assert_eq!(
+		to_html("> [!WARNING]\n> Mind the *gap*.\n\n> [!TIP] Pro tip\n>\n> > Nested.\n"),
+		"<aside class=\"admonition warning\">\n<p class=\"admonition-title\">Warning</p>\n<p>Mind the <em>gap</em>.</p>\n</aside>\n\
+		<aside class=\"admonition tip\">\n<p class=\"admonition-title\">Pro tip</p>\n<blockquote>\n<p>Nested.</p>\n</blockquote>\n</aside>\n"
+	);
+
+	assert_eq!(
+		to_html(":::note\nSome **text**.\n\n```\n:::\n```\n:::\n\n:::unknown\n"),
+		"<aside class=\"admonition note\">\n<p class=\"admonition-title\">Note</p>\n\
+		<p>Some <strong>text</strong>.</p>\n<pre><code>:::\n</code></pre>\n</aside>\n\
+		<p>:::unknown</p>\n"
+	);
+
+	let mut site_info = make_site_info();
+	let mut classes = config::default_admonitions();
+	classes.insert("danger".to_string(), "warning".to_string());
+	site_info.admonitions = &classes;
+	assert_eq!(
+		render_markdown(
+			"> [!danger]\n",
+			&site_info,
+			&FrontMatter::with_title("A".to_string())
+		)
+		.0,
+		"<aside class=\"admonition warning\">\n<p class=\"admonition-title\">Danger</p>\n</aside>\n"
+	);
+}
+
+#[test]
+fn test_math() {
+	assert_eq!(
+		to_html("Costs $5 and $10, while $x_i^2 \\leq \\frac{a}{b}$ holds.\n"),
+		"<p>Costs $5 and $10, while <math xmlns=\"http://www.w3.org/1998/Math/MathML\"><semantics><mrow>\
+		<msubsup><mi>x</mi><mi>i</mi><mn>2</mn></msubsup><mo>≤</mo><mfrac><mrow><mi>a</mi></mrow><mrow><mi>b</mi></mrow></mfrac>\
+		</mrow><annotation encoding=\"application/x-tex\">x_i^2 \\leq \\frac{a}{b}</annotation></semantics></math> holds.</p>\n"
+	);
+
+	assert_eq!(
+		to_html("$$\n\\sum_{n=1}^\\infty \\left\\{ \\mathbb{R} \\right\\}\n$$\n\n`$a$`\n"),
+		"<p><math xmlns=\"http://www.w3.org/1998/Math/MathML\" display=\"block\"><semantics><mrow>\
+		<munderover><mo largeop=\"true\">∑</mo><mrow><mi>n</mi><mo>=</mo><mn>1</mn></mrow><mi>∞</mi></munderover>\
+		<mo fence=\"true\" stretchy=\"true\">{</mo><mrow><mi>ℝ</mi></mrow><mo fence=\"true\" stretchy=\"true\">}</mo>\
+		</mrow><annotation encoding=\"application/x-tex\">\\sum_{n=1}^\\infty \\left\\{ \\mathbb{R} \\right\\}</annotation></semantics></math></p>\n\
+		<p><code>$a$</code></p>\n"
+	);
+
+	let result = std::panic::catch_unwind(|| {
+		let mut front_matter = FrontMatter::with_title("A".to_string());
+		front_matter.subsequent_line = 4;
+		render_markdown(
+			":::note\nText.\n:::\n\nSee $\\foo{x}$.\n",
+			&make_site_info(),
+			&front_matter,
+		)
+	});
+	let message = result
+		.err()
+		.and_then(|e| e.downcast::<String>().ok())
+		.unwrap();
+	assert_eq!(
+		*message,
+		"Failed converting math \"\\foo{x}\": Unsupported command \\foo. Location: ./input/a.md:8."
+	);
+
+	// Includes expanding to more lines than they take up in the source don't
+	// shift the reported line.
+	let input_dir = TempDir::new("math");
+	let input_file_path = input_dir.join("a.md");
+	std::fs::write(
+		&input_file_path,
+		"---\ntitle: A\n---\n{% include list.md %}\n\nSee $\\foo{x}$.\n",
+	)
+	.unwrap();
+	let mut front_matter = FrontMatter::with_title("A".to_string());
+	front_matter.subsequent_line = 4;
+	let message = std::panic::catch_unwind(|| {
+		markdown::render(
+			"- 1\n- 2\n- 3\n\nSee $\\foo{x}$.\n",
+			&liquid::Context {
+				input_file_path: &input_file_path,
+				output_file_path: &PathBuf::from("./output/a.html"),
+				front_matter: &front_matter,
+				html_content: None,
+				headings: &[],
+				root_input_dir: &input_dir,
+				root_output_dir: &PathBuf::from("./output"),
+				input_output_map: &HashMap::new(),
+				groups: &HashMap::new(),
+				site_info: &make_site_info(),
+			},
+		)
+	})
+	.err()
+	.and_then(|e| e.downcast::<String>().ok())
+	.unwrap();
+	assert_eq!(
+		*message,
+		format!(
+			"Failed converting math \"\\foo{{x}}\": Unsupported command \\foo. Location: {}:6.",
+			input_file_path.display()
+		)
+	);
+}
+
+#[test]
+fn test_links() {
+	let mut input_output_map = HashMap::new();
+	for (input, output, title) in &[
+		("./input/posts/a.md", "./output/posts/a.html", "Current"),
+		("./input/posts/b.md", "./output/posts/b.html", "Second Post"),
+		("./input/about.md", "./output/about.html", "About Me"),
+	] {
+		input_output_map.insert(
+			PathBuf::from(input),
+			GroupedOptionOutputFile {
+				file: OptionOutputFile {
+					front_matter: Some(make_front_matter(title, None)),
+					path: PathBuf::from(output),
+				},
+				group: None,
+				backlinks: Vec::new(),
+				neighbours: Neighbours::default(),
+			},
+		);
+	}
+	let front_matter = make_front_matter("Current", None);
+	let site_info = make_site_info();
+	let context = liquid::Context {
+		input_file_path: &PathBuf::from("./input/posts/a.md"),
+		output_file_path: &PathBuf::from("./output/posts/a.html"),
+		front_matter: &front_matter,
+		html_content: None,
+		headings: &[],
+		root_input_dir: &PathBuf::from("./input"),
+		root_output_dir: &PathBuf::from("./output"),
+		input_output_map: &input_output_map,
+		groups: &HashMap::new(),
+		site_info: &site_info,
+	};
+
+	assert_eq!(
+		markdown::to_html(
+			"See [[Second Post]], [[/about.md|me]], [[about#Early years]] \
+			and [x](../about.md#top), [y](b.md) or [[#Intro]].\n\n`[[code]]`\n",
+			&context
+		),
+		"<p>See <a href=\"./b.html\">Second Post</a>, <a href=\"../about.html\">me</a>, \
+		<a href=\"../about.html#early-years\">About Me</a> \
+		and <a href=\"../about.html#top\">x</a>, <a href=\"./b.html\">y</a> or \
+		<a href=\"#intro\">Current</a>.</p>\n<p><code>[[code]]</code></p>\n"
+	);
+
+	let message = std::panic::catch_unwind(|| {
+		markdown::to_html("[[Secnod Post]]", &context)
+	})
+	.err()
+	.and_then(|e| e.downcast::<String>().ok())
+	.unwrap();
+	assert_eq!(
+		*message,
+		"Failed resolving link to \"Secnod Post\" in ./input/posts/a.md. Did you mean \"Second Post\"?"
+	);
+}
+
+#[test]
+fn test_check() {
+	let output_dir = TempDir::new("check");
+	std::fs::create_dir_all(output_dir.join("posts")).unwrap();
+	std::fs::create_dir_all(output_dir.join("img")).unwrap();
+	std::fs::write(output_dir.join("img/a.png"), "").unwrap();
+	std::fs::write(
+		output_dir.join("index.html"),
+		"<h1 id=\"top-1\">Home</h1><!-- <a href=\"gone.html\"> -->\n\
+		<a href=\"posts/a.html#intro\">A</a> <a href='./posts/a.html#nope'>A</a>\n\
+		<img src=\"img/a.png\" srcset=\"img/a.png 1x, img/b.png 2x\">\n\
+		<a href=\"https://example.com/x\">Ext</a> <a href=\"http://localhost:4000/posts/\">Own</a>\n\
+		<a href=\"mailto:a@b.c\">Mail</a> <a href=\"#top-1\">Up</a>\n\
+		<script>let s = '<a href=\"nowhere.html\">';</script>",
+	)
+	.unwrap();
+	std::fs::write(
+		output_dir.join("posts/a.html"),
+		"<h2 id=\"intro\">Intro</h2><a href=\"../missing.html\">X</a><a href=\"/\">Home</a>",
+	)
+	.unwrap();
+	std::fs::write(output_dir.join("posts/index.html"), "").unwrap();
+
+	let mut input_output_map = HashMap::new();
+	input_output_map.insert(
+		PathBuf::from("./input/posts/a.md"),
+		GroupedOptionOutputFile {
+			file: OptionOutputFile {
+				front_matter: Some(make_front_matter("A", None)),
+				path: output_dir.join("posts/a.html"),
+			},
+			group: None,
+			backlinks: Vec::new(),
+			neighbours: Neighbours::default(),
+		},
+	);
+
+	let report =
+		check::run(&output_dir, "http://localhost:4000/", &input_output_map);
+	let problems = report
+		.problems
+		.iter()
+		.map(|problem| problem.to_string())
+		.collect::<Vec<_>>();
+	assert_eq!(
+		problems,
+		vec![
+			format!(
+				"Missing anchor in {}: ./posts/a.html#nope",
+				output_dir.join("index.html").display()
+			),
+			format!(
+				"Missing asset in {}: img/b.png",
+				output_dir.join("index.html").display()
+			),
+			format!(
+				"Broken link in {} (from ./input/posts/a.md): ../missing.html",
+				output_dir.join("posts/a.html").display()
+			),
+		]
+	);
+	assert_eq!(
+		report.external.into_iter().collect::<Vec<_>>(),
+		vec!["https://example.com/x".to_string()]
+	);
+
+	// Stray multibyte characters inside tags don't end the scan.
+	let tags = check::scan_tags("<p =\"x\"é>Ä <a href=\"b.html\">");
+	assert_eq!(tags.len(), 2);
+	assert_eq!(
+		tags[1].attributes,
+		vec![("href".to_string(), "b.html".to_string())]
+	);
+}
+
+#[test]
+fn test_backlinks() {
+	let dir = TempDir::new("backlinks");
+	let input_dir = dir.join("input");
+	let output_dir = dir.join("output");
+	std::fs::create_dir_all(input_dir.join("_layouts")).unwrap();
+	std::fs::create_dir_all(input_dir.join("posts")).unwrap();
+	std::fs::create_dir_all(output_dir.join("posts")).unwrap();
+	for (name, content) in &[
+		("index.md", "[[Post A]] and [first](posts/a.md)\n"),
+		("about.md", "```\n[[Post B]]\n```\n"),
+		("lost.md", "[[lost]]\n"),
+		("legal.md", ""),
+		("posts/a.md", "[b](b.md#x), {% link \"/about.md\" %}\n"),
+		("posts/b.md", "[[Post A|back]]\n"),
+		(
+			"_layouts/default.html",
+			"<a href=\"{% link \"/legal.md\" %}\">\
+			{% for b in page.backlinks %}{{ b.title }};{% endfor %}",
+		),
+	] {
+		std::fs::write(input_dir.join(name), content).unwrap();
+	}
+
+	let mut input_output_map = HashMap::new();
+	for (input, output, title) in &[
+		("index.md", "index.html", "Home"),
+		("about.md", "about.html", "About"),
+		("lost.md", "lost.html", "Lost"),
+		("legal.md", "legal.html", "Legal"),
+		("posts/a.md", "posts/a.html", "Post A"),
+		("posts/b.md", "posts/b.html", "Post B"),
+	] {
+		input_output_map.insert(
+			input_dir.join(input),
+			GroupedOptionOutputFile {
+				file: OptionOutputFile {
+					front_matter: Some(make_front_matter(title, None)),
+					path: output_dir.join(output),
+				},
+				group: None,
+				backlinks: Vec::new(),
+				neighbours: Neighbours::default(),
+			},
+		);
+	}
+	let site_info = make_site_info();
+	let render = |pages: &[&str], map: &HashMap<PathBuf, _>| {
+		for page in pages {
+			let input_file_path = input_dir.join(page);
+			let entry: &GroupedOptionOutputFile = &map[&input_file_path];
+			markdown::process_file(
+				&input_file_path,
+				&entry.file.path,
+				entry.file.front_matter.as_ref().unwrap(),
+				&input_dir,
+				&output_dir,
+				map,
+				&HashMap::new(),
+				&site_info,
+			);
+		}
+	};
+	let pages = [
+		"index.md",
+		"about.md",
+		"lost.md",
+		"legal.md",
+		"posts/a.md",
+		"posts/b.md",
+	];
+	render(&pages, &input_output_map);
+	let stale = site_info.links.apply(&mut input_output_map);
+
+	let sources = |map: &HashMap<PathBuf, GroupedOptionOutputFile>, path| {
+		map[&input_dir.join(path)]
+			.backlinks
+			.iter()
+			.map(|source| source.strip_prefix(&input_dir).unwrap().to_owned())
+			.collect::<Vec<_>>()
+	};
+	assert_eq!(
+		sources(&input_output_map, "posts/a.md"),
+		vec![PathBuf::from("index.md"), PathBuf::from("posts/b.md")]
+	);
+	assert_eq!(
+		sources(&input_output_map, "posts/b.md"),
+		vec![PathBuf::from("posts/a.md")]
+	);
+	assert_eq!(
+		sources(&input_output_map, "about.md"),
+		vec![PathBuf::from("posts/a.md")]
+	);
+	assert!(sources(&input_output_map, "lost.md").is_empty());
+	assert_eq!(
+		site_info.links.find_orphans(&input_output_map, &input_dir),
+		vec![input_dir.join("lost.md")]
+	);
+	assert_eq!(
+		stale,
+		vec![
+			input_dir.join("about.md"),
+			input_dir.join("posts/a.md"),
+			input_dir.join("posts/b.md")
+		]
+	);
+
+	render(&["posts/a.md"], &input_output_map);
+	let read = |path: &str| std::fs::read_to_string(output_dir.join(path));
+	assert!(read("posts/a.html").unwrap().ends_with("Home;Post B;"));
+
+	// Links removed from a page disappear from the backlinks of the target.
+	std::fs::write(input_dir.join("posts/b.md"), "").unwrap();
+	render(&["posts/b.md"], &input_output_map);
+	assert_eq!(
+		site_info.links.apply(&mut input_output_map),
+		vec![input_dir.join("posts/a.md")]
+	);
+	assert_eq!(
+		sources(&input_output_map, "posts/a.md"),
+		vec![PathBuf::from("index.md")]
+	);
+
+	// Listing pages generated without a source, such as tag pages, don't count
+	// as linking pages.
+	std::fs::write(input_dir.join("tagged.md"), "").unwrap();
+	std::fs::write(
+		input_dir.join("_layouts/tag.html"),
+		"{% for e in entries %}{% link e.link %}{% endfor %}",
+	)
+	.unwrap();
+	std::fs::create_dir_all(output_dir.join("tags")).unwrap();
+	let tagged = InputFile {
+		front_matter: make_front_matter("Tagged", None),
+		path: input_dir.join("tagged.md"),
+	};
+	for (input, output, front_matter) in &[
+		("tagged.md", "tagged.html", tagged.front_matter.clone()),
+		(
+			"tags/x.html",
+			"tags/x.html",
+			make_front_matter("Tag: x", None),
+		),
+	] {
+		input_output_map.insert(
+			input_dir.join(input),
+			GroupedOptionOutputFile {
+				file: OptionOutputFile {
+					front_matter: Some(front_matter.clone()),
+					path: output_dir.join(output),
+				},
+				group: None,
+				backlinks: Vec::new(),
+				neighbours: Neighbours::default(),
+			},
+		);
+	}
+	render(&["tagged.md"], &input_output_map);
+	markdown::generate_tag_file(
+		&input_dir.join("tags/x.html"),
+		&[tagged],
+		&input_dir,
+		&output_dir,
+		&input_output_map,
+		&HashMap::new(),
+		&site_info,
+	);
+	assert_eq!(read("tags/x.html").unwrap(), "../tagged.html");
+	site_info.links.apply(&mut input_output_map);
+	assert!(sources(&input_output_map, "tagged.md").is_empty());
+	assert_eq!(
+		site_info.links.find_orphans(&input_output_map, &input_dir),
+		vec![input_dir.join("lost.md"), input_dir.join("tagged.md")]
+	);
+}
+
+#[test]
+fn test_images() {
+	let image = ::image::RgbaImage::from_fn(40, 30, |x, y| {
+		let alpha = if x < 4 { 0 } else { 255 };
+		::image::Rgba([(x * 6) as u8, (y * 8) as u8, 128, alpha])
+	});
+	let mut png_data = Vec::new();
+	image
+		.write_to(&mut Cursor::new(&mut png_data), ::image::ImageFormat::Png)
+		.unwrap();
+	let mut jpeg_data = Vec::new();
+	::image::DynamicImage::ImageRgba8(image)
+		.to_rgb8()
+		.write_to(&mut Cursor::new(&mut jpeg_data), ::image::ImageFormat::Jpeg)
+		.unwrap();
+	let mut gif_data = Vec::new();
+	::image::RgbaImage::new(5, 7)
+		.write_to(&mut Cursor::new(&mut gif_data), ::image::ImageFormat::Gif)
+		.unwrap();
+
+	let root_dir = TempDir::new("images");
+	let input_dir = root_dir.join("input");
+	let output_dir = root_dir.join("output");
+	std::fs::create_dir_all(input_dir.join("_static/img")).unwrap();
+	let png_path = input_dir.join("_static/img/a.png");
+	std::fs::write(&png_path, &png_data).unwrap();
+	std::fs::write(input_dir.join("_static/img/b.gif"), &gif_data).unwrap();
+
+	let mut site_info = make_site_info();
+	site_info.image_widths = &[20, 80];
+	site_info.image_sizes = "(max-width: 40em) 100vw, 40em";
+	image::write_variants(&png_path, &input_dir, &output_dir, &[20, 80]);
+	let variant =
+		::image::open(output_dir.join("static/img/a-20w.png")).unwrap();
+	assert_eq!(variant.color(), ::image::ColorType::Rgba8);
+	let variant = variant.to_rgba8();
+	assert_eq!(variant.dimensions(), (20, 15));
+	let pixel = variant.get_pixel(10, 7).0;
+	assert!(pixel[0].abs_diff(123) < 8 && pixel[1].abs_diff(116) < 8);
+	assert_eq!((pixel[2], pixel[3]), (128, 255));
+	assert_eq!(variant.get_pixel(0, 7).0[3], 0);
+	assert!(!output_dir.join("static/img/a-80w.png").exists());
+
+	let front_matter = FrontMatter::with_title("A".to_string());
+	let context = liquid::Context {
+		input_file_path: &input_dir.join("posts/a.md"),
+		output_file_path: &output_dir.join("posts/a.html"),
+		front_matter: &front_matter,
+		html_content: None,
+		headings: &[],
+		root_input_dir: &input_dir,
+		root_output_dir: &output_dir,
+		input_output_map: &HashMap::new(),
+		groups: &HashMap::new(),
+		site_info: &site_info,
+	};
+	assert_eq!(
+		markdown::to_html(
+			"![A *cat*](../static/img/a.png \"Cat\") ![](/static/img/b.gif) \
+			![](https://example.com/c.png)",
+			&context
+		),
+		"<p><img src=\"../static/img/a.png\" alt=\"A cat\" title=\"Cat\" \
+		width=\"40\" height=\"30\" srcset=\"../static/img/a-20w.png 20w, \
+		../static/img/a.png 40w\" sizes=\"(max-width: 40em) 100vw, 40em\" \
+		loading=\"lazy\" /> \
+		<img src=\"/static/img/b.gif\" alt=\"\" width=\"5\" height=\"7\" \
+		loading=\"lazy\" /> \
+		<img src=\"https://example.com/c.png\" alt=\"\" loading=\"lazy\" /></p>\n"
+	);
+
+	let mut input_file = BufReader::new(Cursor::new(
+		(r#"{{ "/static/img/b.gif" | image "Anim" | upcase }}"#).as_bytes(),
+	));
+	let mut output = BufWriter::new(Vec::new());
+	liquid::process(&mut input_file, &mut output, HashMap::new(), &context);
+	assert_eq!(
+		String::from_utf8_lossy(&output.into_inner().unwrap()),
+		"<IMG SRC=\"/STATIC/IMG/B.GIF\" ALT=\"ANIM\" WIDTH=\"5\" HEIGHT=\"7\" LOADING=\"LAZY\" />"
+	);
+
+	// Dimensions behind metadata larger than the first read are found too.
+	let mut comment = vec![0xFF, 0xFE, 0x27, 0x10];
+	comment.resize(0x2712, b' ');
+	let jpeg_path = input_dir.join("_static/img/c.jpg");
+	std::fs::write(
+		&jpeg_path,
+		[&jpeg_data[..2], &comment, &jpeg_data[2..]].concat(),
+	)
+	.unwrap();
+	// Undecodable images get no variants.
+	let truncated_path = input_dir.join("_static/img/d.png");
+	std::fs::write(&truncated_path, &png_data[..60]).unwrap();
+	for path in &[&jpeg_path, &truncated_path] {
+		image::write_variants(path, &input_dir, &output_dir, &[20]);
+	}
+	let variant = ::image::open(output_dir.join("static/img/c-20w.jpg"))
+		.unwrap()
+		.to_rgb8();
+	assert_eq!(variant.dimensions(), (20, 15));
+	let pixel = variant.get_pixel(10, 7).0;
+	assert!(pixel[0].abs_diff(123) < 12 && pixel[1].abs_diff(116) < 12);
+	assert!(!output_dir.join("static/img/d-20w.png").exists());
+	assert_eq!(
+		markdown::to_html(
+			"![](/static/img/c.jpg) ![](/static/img/d.png)",
+			&context
+		),
+		"<p><img src=\"/static/img/c.jpg\" alt=\"\" width=\"40\" \
+		height=\"30\" srcset=\"/static/img/c-20w.jpg 20w, \
+		/static/img/c.jpg 40w\" sizes=\"(max-width: 40em) 100vw, 40em\" \
+		loading=\"lazy\" /> <img src=\"/static/img/d.png\" alt=\"\" \
+		width=\"40\" height=\"30\" loading=\"lazy\" /></p>\n"
+	);
+
+	// Images rotated through Exif are resized upright.
+	let exif = b"\xFF\xE1\x00\x22Exif\x00\x00MM\x00\x2A\x00\x00\x00\x08\
+		\x00\x01\x01\x12\x00\x03\x00\x00\x00\x01\x00\x06\x00\x00\
+		\x00\x00\x00\x00";
+	let rotated = ::image::RgbImage::from_fn(40, 30, |x, _| {
+		if x < 20 {
+			::image::Rgb([255, 0, 0])
+		} else {
+			::image::Rgb([0, 0, 255])
+		}
+	});
+	let mut rotated_data = Vec::new();
+	rotated
+		.write_to(
+			&mut Cursor::new(&mut rotated_data),
+			::image::ImageFormat::Jpeg,
+		)
+		.unwrap();
+	let rotated_path = input_dir.join("_static/img/e.jpg");
+	std::fs::write(
+		&rotated_path,
+		[&rotated_data[..2], &exif[..], &rotated_data[2..]].concat(),
+	)
+	.unwrap();
+	image::write_variants(&rotated_path, &input_dir, &output_dir, &[20]);
+	assert_eq!(
+		markdown::to_html("![](/static/img/e.jpg)", &context),
+		"<p><img src=\"/static/img/e.jpg\" alt=\"\" width=\"30\" \
+		height=\"40\" srcset=\"/static/img/e-20w.jpg 20w, \
+		/static/img/e.jpg 30w\" sizes=\"(max-width: 40em) 100vw, 40em\" \
+		loading=\"lazy\" /></p>\n"
+	);
+	let variant = ::image::open(output_dir.join("static/img/e-20w.jpg"))
+		.unwrap()
+		.to_rgb8();
+	assert_eq!(variant.dimensions(), (20, 27));
+	// Turned clockwise, the left half ends up on top.
+	assert!(variant.get_pixel(10, 3).0[0] > 200);
+	assert!(variant.get_pixel(10, 23).0[2] > 200);
+}
+
+#[test]
+fn test_assets() {
+	let root_dir = TempDir::new("assets");
+	let input_dir = root_dir.join("input");
+	let output_dir = root_dir.join("output");
+	std::fs::create_dir_all(input_dir.join("_static")).unwrap();
+	std::fs::write(input_dir.join("style.css"), "body {}").unwrap();
+	std::fs::write(input_dir.join("_static/logo.png"), "png").unwrap();
+	let files = [
+		input_dir.join("style.css"),
+		input_dir.join("_static/logo.png"),
+	];
+	let bundles = css::bundle_all(&files, &input_dir, &output_dir);
+	assert_eq!(bundles.keys().collect::<Vec<_>>(), vec![&files[0]]);
+
+	let mut input_output_map = HashMap::new();
+	for (name, fingerprint_assets) in
+		&[("style.css", true), ("_static/logo.png", false)]
+	{
+		let input_file_path = input_dir.join(name);
+		input_output_map.insert(
+			input_file_path.clone(),
+			GroupedOptionOutputFile {
+				file: OptionOutputFile {
+					path: assets::make_output_path(
+						&input_file_path,
+						&input_dir,
+						&output_dir,
+						fingerprint_assets.then_some(&bundles),
+					),
+					front_matter: None,
+				},
+				group: None,
+				backlinks: Vec::new(),
+				neighbours: Neighbours::default(),
+			},
+		);
+	}
+	let fingerprint = assets::fingerprint(b"body{}");
+	assert_eq!(fingerprint.len(), 10);
+	assert_ne!(fingerprint, assets::fingerprint(b"body { }"));
+	let fingerprinted = format!("style.{}.css", fingerprint);
+	assert_eq!(
+		input_output_map[&input_dir.join("style.css")].file.path,
+		output_dir.join(&fingerprinted)
+	);
+
+	// Without bundling, CSS files are output as they are.
+	assert_eq!(
+		assets::make_output_path(
+			&files[0],
+			&input_dir,
+			&output_dir,
+			Some(&css::Bundles::new()),
+		),
+		output_dir
+			.join(format!("style.{}.css", assets::fingerprint(b"body {}")))
+	);
+
+	assets::write_fingerprinted(
+		&files,
+		&input_dir,
+		&output_dir,
+		&input_output_map,
+		&bundles,
+	);
+	assert_eq!(
+		std::fs::read_to_string(output_dir.join(&fingerprinted)).unwrap(),
+		"body{}"
+	);
+	assets::write_manifest(&input_dir, &output_dir, &input_output_map);
+	assert_eq!(
+		std::fs::read_to_string(output_dir.join(assets::MANIFEST_FILE_NAME))
+			.unwrap(),
+		format!("{{\n\t\"style.css\": \"{}\"\n}}\n", fingerprinted)
+	);
+
+	let front_matter = FrontMatter::with_title("A".to_string());
+	let mut input_file = BufReader::new(Cursor::new(
+		(r#"{% asset_url "/style.css" %} {{ "/static/logo.png" | asset_url }}"#)
+			.as_bytes(),
+	));
+	let mut output = BufWriter::new(Vec::new());
+	liquid::process(
+		&mut input_file,
+		&mut output,
+		HashMap::new(),
+		&liquid::Context {
+			input_file_path: &input_dir.join("posts/a.md"),
+			output_file_path: &output_dir.join("posts/a.html"),
+			front_matter: &front_matter,
+			html_content: None,
+			headings: &[],
+			root_input_dir: &input_dir,
+			root_output_dir: &output_dir,
+			input_output_map: &input_output_map,
+			groups: &HashMap::new(),
+			site_info: &make_site_info(),
+		},
+	);
+	assert_eq!(
+		String::from_utf8_lossy(&output.into_inner().unwrap()),
+		format!("../{} ../static/logo.png", fingerprinted)
+	);
+}
+
+#[test]
+fn test_css() {
+	assert_eq!(
+		css::minify(
+			"/* Header */\na :hover, b > i {\n\tcolor : red !important;\n\
+			content: \"a  /* b */\";\n}\n@media screen and (min-width: 1px) {\n\
+			p { margin: calc(1px + 2px) }\n}\n"
+		),
+		"a :hover,b>i{color :red!important;content:\"a  /* b */\"}\
+		@media screen and (min-width:1px){p{margin:calc(1px + 2px)}}"
+	);
+
+	let root_dir = TempDir::new("css");
+	let input_dir = root_dir.join("input");
+	let output_dir = root_dir.join("output");
+	std::fs::create_dir_all(input_dir.join("_css")).unwrap();
+	std::fs::create_dir_all(input_dir.join("_static/img")).unwrap();
+	std::fs::create_dir_all(input_dir.join("themes")).unwrap();
+	for (name, content) in &[
+		(
+			"themes/site.css",
+			"@import \"../_css/base.css\";\n\
+			@import url(_print.css) print;\n\
+			@import url(\"https://example.com/font.css\");\n\
+			h1 { background: url( 'img/h.png#x' ) }\n",
+		),
+		("themes/_print.css", "nav { display: none; }\n"),
+		(
+			"_css/base.css",
+			"body { background: url(../_static/img/bg.png) }\n\
+			a { background: url(data:image/png;base64,AA==) }\n",
+		),
+	] {
+		std::fs::write(input_dir.join(name), content).unwrap();
+	}
+
+	assert!(css::is_partial(
+		&input_dir.join("themes/_print.css"),
+		&input_dir
+	));
+	assert!(css::is_partial(
+		&input_dir.join("_css/base.css"),
+		&input_dir
+	));
+	assert!(!css::is_partial(
+		&input_dir.join("_static/a.css"),
+		&input_dir
+	));
+
+	let bundle = css::bundle(
+		&input_dir.join("themes/site.css"),
+		&input_dir,
+		&output_dir,
+	);
+	assert_eq!(
+		bundle.content,
+		"@import url(\"https://example.com/font.css\");\
+		body{background:url(../static/img/bg.png)}\
+		a{background:url(data:image/png;base64,AA==)}\
+		@media print{nav{display:none}}\
+		h1{background:url('img/h.png#x')}"
+	);
+	assert_eq!(
+		bundle.sources,
+		vec![
+			input_dir.join("themes/site.css"),
+			input_dir.join("_css/base.css"),
+			input_dir.join("themes/_print.css"),
+		]
+	);
+	let bundles = css::bundle_all(
+		&[input_dir.join("themes/site.css")],
+		&input_dir,
+		&output_dir,
+	);
+	assert_eq!(
+		css::find_importing(&bundles, &input_dir.join("_css/base.css")),
+		vec![input_dir.join("themes/site.css")]
+	);
+
+	// Unterminated imports are kept as they are.
+	std::fs::write(input_dir.join("broken.css"), "@import \"").unwrap();
+	assert_eq!(
+		css::bundle(&input_dir.join("broken.css"), &input_dir, &output_dir)
+			.content,
+		"@import \";"
+	);
+
+	std::fs::write(
+		input_dir.join("_css/base.css"),
+		"@import \"/themes/site.css\";",
+	)
+	.unwrap();
+	let result = std::panic::catch_unwind(|| {
+		css::bundle(&input_dir.join("themes/site.css"), &input_dir, &output_dir)
+	});
+	assert!(result.is_err());
+}
+
+#[test]
+fn test_minify_html() {
+	assert_eq!(
+		minify::minify_html(
+			"<!DOCTYPE html>\n<html>\n  <head>\n    <!-- Metadata -->\n\
+			<link rel=\"stylesheet\" href=\"style.css\" />\n\
+			<style>\n  p { color : red; }\n</style>\n  </head>\n\
+			<body class=\"a b\" data-x='1'>\n    <p>Some   <em>very</em>\n\
+			<a href=\"/\" title=\"\">home</a> </p>\n\
+			<pre><code>  keep\n    this</code></pre>\n\
+			<script>  var a = 1 <  2;  </script>\n\
+			<textarea>\n x  </textarea>\n  </body>\n</html>\n"
+		),
+		"<!DOCTYPE html><html><head>\
+		<link rel=stylesheet href=style.css /><style>p{color :red}</style>\
+		</head><body class=\"a b\" data-x=1><p>Some <em>very</em> \
+		<a href=\"/\" title=\"\">home</a></p>\
+		<pre><code>  keep\n    this</code></pre>\
+		<script>  var a = 1 <  2;  </script>\
+		<textarea>\n x  </textarea></body></html>"
+	);
+}
+
+#[test]
+fn test_precompress() {
+	// Checked against the reference decoders.
+	let data = b"hello hello hello hello".repeat(50);
+	let mut decoded = Vec::new();
+	flate2::read::GzDecoder::new(&precompress::gzip(&data)[..])
+		.read_to_end(&mut decoded)
+		.unwrap();
+	assert_eq!(decoded, data);
+	decoded.clear();
+	brotli::Decompressor::new(&precompress::brotli(&data)[..], 4096)
+		.read_to_end(&mut decoded)
+		.unwrap();
+	assert_eq!(decoded, data);
+
+	assert!(precompress::is_compressible(&PathBuf::from("a/index.html")));
+	assert!(precompress::is_compressible(&PathBuf::from("static/app.js")));
+	assert!(!precompress::is_compressible(&PathBuf::from("logo.png")));
+	assert!(!precompress::is_compressible(&PathBuf::from("index.html.gz")));
+	assert_eq!(
+		precompress::sibling_path(&PathBuf::from("a/style.css"), "br"),
+		PathBuf::from("a/style.css.br")
+	);
+
+	let output_dir = TempDir::new("precompress");
+	let page = output_dir.join("index.html");
+	std::fs::write(&page, &data).unwrap();
+	std::fs::write(output_dir.join("robots.txt.gz"), "stale").unwrap();
+	std::thread::sleep(Duration::from_millis(10));
+	std::fs::write(output_dir.join("robots.txt"), "User-agent: *\n").unwrap();
+	// Only written when smaller, removing stale variants otherwise.
+	let checked = precompress::Checked::default();
+	assert_eq!(precompress::run(&output_dir, &checked), 2);
+	assert!(output_dir.join("index.html.br").is_file());
+	assert!(!output_dir.join("robots.txt.gz").exists());
+	assert!(!output_dir.join("robots.txt.br").exists());
+	// Outputs without variants aren't compressed again until they change.
+	assert!(checked
+		.lock()
+		.unwrap()
+		.contains_key(&output_dir.join("robots.txt")));
+	// Up to date variants are kept, those of rewritten files replaced.
+	assert_eq!(precompress::run(&output_dir, &checked), 0);
+	std::fs::File::options()
+		.write(true)
+		.open(&page)
+		.unwrap()
+		.set_modified(SystemTime::now() + Duration::from_secs(10))
+		.unwrap();
+	assert_eq!(precompress::run(&output_dir, &checked), 2);
+
+	assert!(http::accepts_encoding("gzip, deflate, br", "br"));
+	assert!(http::accepts_encoding("GZIP;q=0.5", "gzip"));
+	assert!(http::accepts_encoding("*", "br"));
+	assert!(!http::accepts_encoding("gzip, br;q=0", "br"));
+	assert!(!http::accepts_encoding("", "gzip"));
+	assert!(!http::accepts_encoding("identity", "gzip"));
+}
+
+#[test]
+fn test_feeds() {
+	assert_eq!(
+		rss::format_date("2001-01-19T20:10:01Z"),
+		"Fri, 19 Jan 2001 20:10:01 +0000"
+	);
+	assert_eq!(
+		rss::format_date("2024-02-29T23:59:59Z"),
+		"Thu, 29 Feb 2024 23:59:59 +0000"
+	);
+	assert_eq!(
+		rss::format_date("2020-01-02T10:00:00+02:00"),
+		"Thu, 02 Jan 2020 10:00:00 +0200"
+	);
+	assert_eq!(
+		rss::format_date("2019-12-31T23:30:00-05:30"),
+		"Tue, 31 Dec 2019 23:30:00 -0530"
+	);
+
+	let output_dir = TempDir::new("feeds");
+	let mut config = config::Args::new().values();
+	config.output_dir = output_dir.clone();
+	config.base_url = String::from("https://example.com/");
+	config.author = String::from("Author");
+	config.email = String::from("author@example.com");
+	config.title = String::from("Site");
+	config.feeds.insert(
+		String::from("posts"),
+		vec![FeedFormat::Atom, FeedFormat::Rss, FeedFormat::Json],
+	);
+	config.feeds.insert(String::from("all"), vec![FeedFormat::Rss]);
+	let mut groups = HashMap::new();
+	groups.insert(
+		String::from("posts"),
+		vec![
+			feed::FeedEntry {
+				front_matter: make_front_matter(
+					"Old & \"quoted\"",
+					Some("2001-01-19T22:10:01+02:00"),
+				),
+				html_content: String::from("<p>Old</p>"),
+				summary: None,
+				permalink: PathBuf::from("posts/old.html"),
+				enclosure: None,
+			},
+			feed::FeedEntry {
+				front_matter: make_front_matter(
+					"New",
+					Some("2002-01-19T20:10:01Z"),
+				),
+				html_content: String::from("<p>New ]]> content</p>"),
+				summary: Some(String::from("<p>Summary</p>")),
+				permalink: PathBuf::from("posts/new.html"),
+				enclosure: None,
+			},
+		],
+	);
+	groups.insert(
+		String::from("notes"),
+		vec![feed::FeedEntry {
+			front_matter: make_front_matter(
+				"Note",
+				Some("2001-06-19T20:10:01Z"),
+			),
+			html_content: String::from("<p>Note</p>"),
+			summary: None,
+			permalink: PathBuf::from("notes/note.html"),
+			enclosure: None,
+		}],
+	);
+	let mut tags = HashMap::new();
+	tags.insert(String::from("rust"), Vec::new());
+	tags.insert(String::from("unused"), Vec::new());
+	let mut tagged = FrontMatter::with_title(String::from("Tagged"));
+	tagged.date = Some(String::from("2003-01-19T20:10:01Z"));
+	tagged.tags = vec![String::from("rust")];
+	groups.get_mut("notes").unwrap().push(feed::FeedEntry {
+		front_matter: Arc::new(tagged),
+		html_content: String::from("<p>Tagged</p>"),
+		summary: None,
+		permalink: PathBuf::from("notes/tagged.html"),
+		enclosure: None,
+	});
+	feed::generate(groups, &tags, &config);
+
+	let read = |name: &str| {
+		std::fs::read_to_string(output_dir.join("feeds").join(name)).unwrap()
+	};
+	assert!(output_dir.join("feeds/notes.xml").is_file());
+	assert!(!output_dir.join("feeds/notes.json").exists());
+	let rust = read("tags/rust.xml");
+	assert!(rust.contains("<title>Site - Tag: rust</title>"));
+	assert!(rust.contains("notes/tagged.html"));
+	assert!(!rust.contains("notes/note.html"));
+	assert!(!read("tags/unused.xml").contains("<entry>"));
+	let all = read("all.rss.xml");
+	assert!(!output_dir.join("feeds/all.xml").exists());
+	assert_eq!(all.matches("<item>").count(), 4);
+	assert!(all.find("notes/tagged.html") < all.find("posts/new.html"));
+	assert!(read("posts.xml").contains("<title>Site - Posts</title>"));
+
+	let rss = read("posts.rss.xml");
+	assert!(rss.contains("<rss version=\"2.0\""));
+	assert!(rss.contains(
+		"<atom:link href=\"https://example.com/feeds/posts.rss.xml\" \
+		rel=\"self\" type=\"application/rss+xml\"/>"
+	));
+	assert!(rss.contains(
+		"<lastBuildDate>Sat, 19 Jan 2002 20:10:01 +0000</lastBuildDate>"
+	));
+	assert!(rss.contains("<title>Old &amp; &quot;quoted&quot;</title>"));
+	assert!(rss
+		.contains("<pubDate>Fri, 19 Jan 2001 22:10:01 +0200</pubDate>"));
+	assert!(rss.contains(
+		"<author>author@example.com (Author)</author>\n\
+		\t\t<description><![CDATA[<p>Summary</p>]]></description>"
+	));
+	// Newest first.
+	assert!(rss.find("posts/new.html") < rss.find("posts/old.html"));
+
+	assert_eq!(
+		read("posts.json"),
+		"{\n\
+		\t\"version\": \"https://jsonfeed.org/version/1.1\",\n\
+		\t\"title\": \"Site - Posts\",\n\
+		\t\"home_page_url\": \"https://example.com/\",\n\
+		\t\"feed_url\": \"https://example.com/feeds/posts.json\",\n\
+		\t\"authors\": [{ \"name\": \"Author\", \"url\": \"mailto:author@example.com\" }],\n\
+		\t\"items\": [\n\
+		\t\t{\n\
+		\t\t\t\"id\": \"https://example.com/posts/new.html\",\n\
+		\t\t\t\"url\": \"https://example.com/posts/new.html\",\n\
+		\t\t\t\"title\": \"New\",\n\
+		\t\t\t\"date_published\": \"2002-01-19T20:10:01Z\",\n\
+		\t\t\t\"content_html\": \"<p>Summary</p>\"\n\
+		\t\t},\n\
+		\t\t{\n\
+		\t\t\t\"id\": \"https://example.com/posts/old.html\",\n\
+		\t\t\t\"url\": \"https://example.com/posts/old.html\",\n\
+		\t\t\t\"title\": \"Old & \\\"quoted\\\"\",\n\
+		\t\t\t\"date_published\": \"2001-01-19T22:10:01+02:00\",\n\
+		\t\t\t\"content_html\": \"<p>Old</p>\"\n\
+		\t\t}\n\
+		\t]\n\
+		}\n"
+	);
+
+	config.feed_limit = 2;
+	let mut groups = HashMap::new();
+	groups.insert(
+		String::from("notes"),
+		["a", "b", "c"]
+			.iter()
+			.map(|name| feed::FeedEntry {
+				front_matter: make_front_matter(
+					name,
+					Some(&format!("2001-01-0{}T00:00:00Z", name.len())),
+				),
+				html_content: String::new(),
+				summary: None,
+				permalink: PathBuf::from(format!("notes/{}.html", name)),
+				enclosure: None,
+			})
+			.collect(),
+	);
+	feed::generate(groups, &HashMap::new(), &config);
+	let notes = read("notes.xml");
+	assert_eq!(notes.matches("<entry>").count(), 2);
+	assert!(notes.contains("notes/a.html") && notes.contains("notes/b.html"));
+
+	let input_file_path = PathBuf::from("./input/posts/virtual_test.md");
+	let output_file_path = PathBuf::from("./output/posts/virtual_test.html");
+	let front_matter = make_front_matter("Title", None);
+	let mut feed_groups = HashMap::new();
+	feed_groups.insert(String::from("posts"), Vec::new());
+	feed_groups.insert(String::from("notes"), Vec::new());
+	let mut site_info = make_site_info();
+	site_info.feeds = Box::leak(Box::new(config.feeds));
+	for (template, expected) in [
+		(
+			"{% feed_meta \"notes\" %}",
+			"<link rel=\"alternate\" type=\"application/atom+xml\" \
+			title=\"Site - Notes\" href=\"../feeds/notes.xml\">\n",
+		),
+		(
+			"{% feed_meta %}",
+			"<link rel=\"alternate\" type=\"application/rss+xml\" \
+			title=\"Site\" href=\"../feeds/all.rss.xml\">\n\
+			<link rel=\"alternate\" type=\"application/atom+xml\" \
+			title=\"Site - Notes\" href=\"../feeds/notes.xml\">\n\
+			<link rel=\"alternate\" type=\"application/atom+xml\" \
+			title=\"Site - Posts\" href=\"../feeds/posts.xml\">\n\
+			<link rel=\"alternate\" type=\"application/rss+xml\" \
+			title=\"Site - Posts\" href=\"../feeds/posts.rss.xml\">\n\
+			<link rel=\"alternate\" type=\"application/feed+json\" \
+			title=\"Site - Posts\" href=\"../feeds/posts.json\">\n",
+		),
+	] {
+		let mut output = BufWriter::new(Vec::new());
+		liquid::process(
+			&mut BufReader::new(Cursor::new(template.as_bytes())),
+			&mut output,
+			HashMap::new(),
+			&liquid::Context {
+				input_file_path: &input_file_path,
+				output_file_path: &output_file_path,
+				front_matter: &front_matter,
+				html_content: None,
+				headings: &[],
+				root_input_dir: &PathBuf::from("./input"),
+				root_output_dir: &PathBuf::from("./output"),
+				input_output_map: &HashMap::new(),
+				groups: &feed_groups,
+				site_info: &site_info,
+			},
+		);
+		assert_eq!(
+			String::from_utf8_lossy(&output.into_inner().unwrap()),
+			expected
+		);
+	}
+}
+
+#[test]
+fn test_podcast() {
+	assert_eq!(feed::parse_duration("59"), Some(59));
+	assert_eq!(feed::parse_duration("90:05"), Some(5405));
+	assert_eq!(feed::parse_duration("1:02:03"), Some(3723));
+	assert_eq!(feed::parse_duration("1:60"), None);
+	assert_eq!(feed::parse_duration("1:2:3:4"), None);
+	assert_eq!(feed::audio_mime_type("mp3"), Some("audio/mpeg"));
+	assert_eq!(feed::audio_mime_type("exe"), None);
+
+	let root_dir = TempDir::new("podcast");
+	let input_dir = root_dir.join("input");
+	let output_dir = root_dir.join("output");
+	let audio_input_path = input_dir.join("_static/episodes/one.mp3");
+	std::fs::create_dir_all(audio_input_path.parent().unwrap()).unwrap();
+	std::fs::write(&audio_input_path, [0_u8; 1234]).unwrap();
+	let mut input_output_map = HashMap::new();
+	input_output_map.insert(
+		audio_input_path,
+		GroupedOptionOutputFile {
+			file: OptionOutputFile {
+				front_matter: None,
+				path: output_dir.join("episodes/one.mp3"),
+			},
+			group: None,
+			backlinks: Vec::new(),
+			neighbours: Neighbours::default(),
+		},
+	);
+
+	let mut front_matter = FrontMatter::with_title(String::from("One"));
+	front_matter.date = Some(String::from("2001-01-19T20:10:01Z"));
+	front_matter.audio = Some(String::from("../_static/episodes/one.mp3"));
+	front_matter.duration = Some(String::from("1:02:03"));
+	front_matter.episode = Some(1);
+	let enclosure = feed::make_enclosure(
+		&input_dir.join("posts/one.md"),
+		&front_matter,
+		&input_dir,
+		&output_dir,
+		&input_output_map,
+	)
+	.unwrap();
+	assert_eq!(enclosure.path, PathBuf::from("episodes/one.mp3"));
+	assert_eq!(enclosure.length, 1234);
+	assert_eq!(enclosure.mime_type, "audio/mpeg");
+	assert_eq!(enclosure.duration_seconds, Some(3723));
+
+	let mut config = config::Args::new().values();
+	config.output_dir = output_dir.clone();
+	config.base_url = String::from("https://example.com/");
+	config.author = String::from("Author");
+	config.email = String::from("author@example.com");
+	config.title = String::from("Site");
+	config.feeds.insert(
+		String::from("posts"),
+		vec![FeedFormat::Atom, FeedFormat::Json, FeedFormat::Podcast],
+	);
+	config
+		.podcast
+		.insert(String::from("image"), String::from("/cover.png"));
+	config
+		.podcast
+		.insert(String::from("category"), String::from("Technology"));
+	let mut groups = HashMap::new();
+	groups.insert(
+		String::from("posts"),
+		vec![
+			feed::FeedEntry {
+				front_matter: Arc::new(front_matter),
+				html_content: String::from("<p>One</p>"),
+				summary: None,
+				permalink: PathBuf::from("posts/one.html"),
+				enclosure: Some(enclosure),
+			},
+			feed::FeedEntry {
+				front_matter: make_front_matter(
+					"Announcement",
+					Some("2002-01-19T20:10:01Z"),
+				),
+				html_content: String::from("<p>Text only</p>"),
+				summary: None,
+				permalink: PathBuf::from("posts/announcement.html"),
+				enclosure: None,
+			},
+		],
+	);
+	feed::generate(groups, &HashMap::new(), &config);
+
+	let read = |name: &str| {
+		std::fs::read_to_string(output_dir.join("feeds").join(name)).unwrap()
+	};
+	let podcast = read("posts.podcast.xml");
+	assert!(podcast.contains(
+		"xmlns:itunes=\"http://www.itunes.com/dtds/podcast-1.0.dtd\""
+	));
+	assert!(podcast.contains("<itunes:explicit>false</itunes:explicit>"));
+	assert!(podcast.contains("<itunes:category text=\"Technology\"/>"));
+	assert!(podcast.contains(
+		"<itunes:image href=\"https://example.com/cover.png\"/>"
+	));
+	assert!(podcast.contains(
+		"\t\t<enclosure url=\"https://example.com/episodes/one.mp3\" \
+		length=\"1234\" type=\"audio/mpeg\"/>\n\
+		\t\t<itunes:duration>3723</itunes:duration>\n\
+		\t\t<itunes:episode>1</itunes:episode>\n"
+	));
+	// Only entries with audio are episodes.
+	assert_eq!(podcast.matches("<item>").count(), 1);
+	assert!(read("posts.xml").contains(
+		"<link rel=\"enclosure\" type=\"audio/mpeg\" length=\"1234\" \
+		href=\"https://example.com/episodes/one.mp3\"/>"
+	));
+	assert!(read("posts.json").contains(
+		"\"attachments\": [{ \"url\": \"https://example.com/episodes/one.mp3\", \
+		\"mime_type\": \"audio/mpeg\", \"size_in_bytes\": 1234, \
+		\"duration_in_seconds\": 3723 }]"
+	));
+}
+
+#[test]
+fn test_robots() {
+	assert_eq!(
+		robots::parse_rules("*", "allow /, disallow /drafts/,"),
+		vec![
+			Rule::Allow(String::from("/")),
+			Rule::Disallow(String::from("/drafts/"))
+		]
+	);
+
+	let output_dir = TempDir::new("robots");
+	let read = || {
+		std::fs::read_to_string(output_dir.join("robots.txt")).unwrap()
+	};
+	let sitemap_url = "https://example.com/sitemap.xml";
+	let mut rules = BTreeMap::new();
+	robots::write_robots_txt(&output_dir, sitemap_url, &rules, false);
+	assert_eq!(
+		read(),
+		"User-agent: *\nAllow: /\nSitemap: https://example.com/sitemap.xml\n"
+	);
+	rules.insert(String::from("*"), robots::parse_rules("*", "allow /"));
+	rules.insert(
+		String::from("GPTBot"),
+		robots::parse_rules("GPTBot", "disallow /"),
+	);
+	robots::write_robots_txt(&output_dir, sitemap_url, &rules, false);
+	assert_eq!(
+		read(),
+		"User-agent: *\nAllow: /\n\n\
+		User-agent: GPTBot\nDisallow: /\n\
+		Sitemap: https://example.com/sitemap.xml\n"
+	);
+	robots::write_robots_txt(&output_dir, sitemap_url, &rules, true);
+	assert_eq!(read(), "User-agent: *\nDisallow: /\n");
+
+	let mut input_output_map = HashMap::new();
+	for (name, noindex) in [("shown", false), ("hidden", true)] {
+		let mut front_matter = FrontMatter::with_title(name.to_string());
+		front_matter.noindex = noindex;
+		input_output_map.insert(
+			PathBuf::from(format!("input/{}.md", name)),
+			GroupedOptionOutputFile {
+				file: OptionOutputFile {
+					front_matter: Some(Arc::new(front_matter)),
+					path: output_dir.join(format!("{}.html", name)),
+				},
+				group: None,
+				backlinks: Vec::new(),
+				neighbours: Neighbours::default(),
+			},
+		);
+	}
+	robots::write_sitemap_xml(
+		&output_dir,
+		"https://example.com/",
+		&input_output_map,
+		&HashMap::new(),
+		&HashMap::new(),
+	);
+	let sitemap =
+		std::fs::read_to_string(output_dir.join("sitemap.xml")).unwrap();
+	assert!(sitemap.contains("<loc>https://example.com/shown.html</loc>"));
+	assert!(!sitemap.contains("hidden"));
+}
+
+#[test]
+fn test_sitemap() {
+	assert_eq!(
+		robots::escape_url("https://example.com/a b/ä?x=1&y='2'"),
+		"https://example.com/a%20b/%C3%A4?x=1&amp;y=&#39;2&#39;"
+	);
+	let urls = ["aaaa", "bbbb", "cccc", "dd"]
+		.iter()
+		.map(|url| url.to_string())
+		.collect::<Vec<_>>();
+	assert_eq!(robots::chunk_urls(&urls, 3, usize::MAX).len(), 2);
+	assert_eq!(robots::chunk_urls(&urls, 10, usize::MAX).len(), 1);
+	assert!(robots::chunk_urls(&[], 1, 0).is_empty());
+	// Each chunk keeps at least one URL even if over the byte limit.
+	let chunks = robots::chunk_urls(&urls, 10, 1);
+	assert_eq!(chunks.len(), 4);
+	assert_eq!(chunks[3], ["dd"]);
+
+	let output_dir = TempDir::new("sitemap");
+	std::fs::create_dir_all(output_dir.join("posts")).unwrap();
+	std::fs::create_dir_all(output_dir.join("tags")).unwrap();
+	std::fs::write(
+		output_dir.join("posts/a b.html"),
+		"<p><img src=\"../images/cat.png\"><img src=\"/logo.svg\">\
+		<img src=\"data:image/png;base64,AA\">\
+		<img src=\"https://cdn.example.org/x.jpg\"></p>",
+	)
+	.unwrap();
+
+	let mut input_output_map = HashMap::new();
+	let mut insert = |name: &str, front_matter: FrontMatter| {
+		input_output_map.insert(
+			PathBuf::from(format!("input/{}", name)),
+			GroupedOptionOutputFile {
+				file: OptionOutputFile {
+					front_matter: Some(Arc::new(front_matter)),
+					path: output_dir.join(name),
+				},
+				group: None,
+				backlinks: Vec::new(),
+				neighbours: Neighbours::default(),
+			},
+		);
+	};
+	let mut post = FrontMatter::with_title(String::from("A"));
+	post.priority = Some(0.8);
+	post.changefreq = Some(String::from("weekly"));
+	insert("posts/a b.html", post);
+	let mut opted_out = FrontMatter::with_title(String::from("Out"));
+	opted_out.sitemap = false;
+	insert("out.html", opted_out);
+	let mut draft = FrontMatter::with_title(String::from("Draft"));
+	draft.published = false;
+	insert("draft.html", draft);
+	insert("tags/rust.html", FrontMatter::with_title(String::from("Tag")));
+	insert(
+		"posts/2020/index.html",
+		FrontMatter::with_title(String::from("Archive")),
+	);
+	let mut tags = HashMap::new();
+	tags.insert(String::from("rust"), Vec::new());
+	let mut archives = HashMap::new();
+	archives.insert(PathBuf::from("posts/2020"), Vec::new());
+
+	let sitemap_url = robots::write_sitemap_xml(
+		&output_dir,
+		"https://example.com/blog/",
+		&input_output_map,
+		&tags,
+		&archives,
+	);
+	assert_eq!(sitemap_url, "https://example.com/blog/sitemap.xml");
+	let sitemap =
+		std::fs::read_to_string(output_dir.join("sitemap.xml")).unwrap();
+	assert!(sitemap.contains(
+		"\t<url>\n\
+		\t\t<loc>https://example.com/blog/posts/a%20b.html</loc>\n\
+		\t\t<changefreq>weekly</changefreq>\n\
+		\t\t<priority>0.8</priority>\n\
+		\t\t<image:image>\n\
+		\t\t\t<image:loc>https://example.com/blog/images/cat.png</image:loc>\n\
+		\t\t</image:image>\n\
+		\t\t<image:image>\n\
+		\t\t\t<image:loc>https://example.com/logo.svg</image:loc>\n\
+		\t\t</image:image>\n\
+		\t\t<image:image>\n\
+		\t\t\t<image:loc>https://cdn.example.org/x.jpg</image:loc>\n\
+		\t\t</image:image>\n\
+		\t</url>\n"
+	));
+	assert_eq!(sitemap.matches("<url>").count(), 1);
+
+	// Pages which were not generated have no images to scan.
+	let mut input_output_map = HashMap::new();
+	for i in 0..=robots::MAX_SITEMAP_URLS {
+		input_output_map.insert(
+			PathBuf::from(format!("input/{}.md", i)),
+			GroupedOptionOutputFile {
+				file: OptionOutputFile {
+					front_matter: None,
+					path: output_dir.join(format!("page-{}.html", i)),
+				},
+				group: None,
+				backlinks: Vec::new(),
+				neighbours: Neighbours::default(),
+			},
+		);
+	}
+	robots::write_sitemap_xml(
+		&output_dir,
+		"https://example.com/",
+		&input_output_map,
+		&HashMap::new(),
+		&HashMap::new(),
+	);
+	let index =
+		std::fs::read_to_string(output_dir.join("sitemap.xml")).unwrap();
+	assert!(index.contains("<sitemapindex"));
+	assert!(index.contains(
+		"<sitemap>\n\t\t<loc>https://example.com/sitemap-2.xml</loc>"
+	));
+	assert!(!index.contains("sitemap-3.xml"));
+	let second =
+		std::fs::read_to_string(output_dir.join("sitemap-2.xml")).unwrap();
+	assert_eq!(second.matches("<url>").count(), 1);
+}
+
+#[test]
+fn test_search_index() {
+	assert_eq!(
+		search::html_to_text(
+			"<p>Fish &amp; chips</p><!-- <p>hidden</p> -->\
+			<script>let x = \"<p>\";</script><td>a</td><td>b</td>"
+		),
+		" Fish & chips   a  b "
+	);
+	// Inline tags don't separate words, and the TeX of math isn't indexed.
+	assert_eq!(
+		search::html_to_text(
+			"<p>With <em>emph</em>asis.</p><p><math><semantics><mrow>\
+			<msup><mi>x</mi><mn>2</mn></msup></mrow><annotation \
+			encoding=\"application/x-tex\">x^2</annotation></semantics></math></p>"
+		),
+		" With emphasis.  x2 "
+	);
+
+	let mut rules = BTreeMap::new();
+	assert!(search::is_included(None, &rules));
+	rules.insert(String::from("*"), false);
+	rules.insert(String::from("posts"), true);
+	assert!(search::is_included(Some("posts"), &rules));
+	assert!(!search::is_included(Some("notes"), &rules));
+	assert!(!search::is_included(None, &rules));
+
+	let mut front_matter =
+		FrontMatter::with_title(String::from("Hello \"you\""));
+	front_matter.tags = vec![String::from("rust")];
+	let generated = GeneratedFile {
+		file: OutputFile {
+			front_matter: Arc::new(front_matter),
+			path: PathBuf::from("posts/hello/index.html"),
+		},
+		group: Some(String::from("posts")),
+		html_content: String::from(
+			"<h2 id=\"intro\">Intro</h2><p>The cat, and the dog. Of mice.</p>",
+		),
+		excerpt: String::new(),
+		headings: vec![Heading {
+			level: 2,
+			title: String::from("Intro"),
+			id: String::from("intro"),
+		}],
+	};
+	let stop_words = ["the", "and", "of"]
+		.iter()
+		.map(|word| word.to_string())
+		.collect();
+	let mut entries = vec![
+		search::make_entry(&generated, &rules, &stop_words, 0).unwrap(),
+		search::make_entry(&generated, &BTreeMap::new(), &stop_words, 3)
+			.unwrap(),
+	];
+	entries[1].url = String::from("about.html");
+	assert_eq!(entries[0].url, "posts/hello/");
+	assert_eq!(entries[0].body, "Intro cat, dog. mice.");
+	assert_eq!(entries[1].body, "Intro cat, dog.");
+
+	let output_dir = TempDir::new("search");
+	let index_path = output_dir.join("assets/search.json");
+	let index = search::Entries::new(
+		entries
+			.into_iter()
+			.map(|entry| (entry.url.clone(), entry))
+			.collect(),
+	);
+	search::write_index(&index, &index_path);
+	assert_eq!(
+		std::fs::read_to_string(&index_path).unwrap(),
+		"{\"version\":1,\"pages\":[\n\
+		{\"url\":\"about.html\",\"title\":\"Hello \\\"you\\\"\",\"tags\":[\"rust\"],\
+		\"headings\":[\"Intro\"],\"body\":\"Intro cat, dog.\"},\n\
+		{\"url\":\"posts/hello/\",\"title\":\"Hello \\\"you\\\"\",\"tags\":[\"rust\"],\
+		\"headings\":[\"Intro\"],\"body\":\"Intro cat, dog. mice.\"}\n\
+		]}\n"
+	);
+
+	// HTML template pages are indexed as they are processed, replacing their
+	// previous entry when regenerated.
+	let input_dir = output_dir.join("input");
+	std::fs::create_dir_all(&input_dir).unwrap();
+	let all = BTreeMap::new();
+	let mut site_info = make_site_info();
+	site_info.search = Some(search::Indexer {
+		rules: &all,
+		stop_words: &stop_words,
+		body_words: 0,
+		entries: &index,
+	});
+	let process = |content: &str| {
+		std::fs::write(input_dir.join("about.html"), content).unwrap();
+		markdown::process_template_file(
+			&input_dir.join("about.html"),
+			&output_dir.join("about.html"),
+			&make_front_matter("About", None),
+			&input_dir,
+			&output_dir,
+			&HashMap::new(),
+			&HashMap::new(),
+			&site_info,
+		);
+	};
+	let body = || index.lock().unwrap()["about.html"].body.clone();
+	process("<h1>About</h1><p>The cat</p>");
+	assert_eq!(body(), "About cat");
+	process("<h1>About</h1><p>The dog</p>");
+	assert_eq!(body(), "About dog");
+
+	let generated = GeneratedFile {
+		group: Some(String::from("notes")),
+		..generated
+	};
+	assert!(search::make_entry(&generated, &rules, &stop_words, 0).is_none());
+	let generated = GeneratedFile {
+		file: OutputFile {
+			front_matter: generated.file.front_matter,
+			path: PathBuf::from("404.html"),
+		},
+		group: None,
+		..generated
+	};
+	assert!(search::make_entry(&generated, &BTreeMap::new(), &stop_words, 0)
+		.is_none());
+}
+
+#[test]
+fn test_seo() {
+	let input_file_path = PathBuf::from("./input/posts/hello.md");
+	let output_file_path = PathBuf::from("./output/posts/hello.html");
+	let mut front_matter = FrontMatter::with_title(String::from("Hello"));
+	front_matter.date = Some(String::from("2001-01-19T20:10:01Z"));
+	// Inline markup next to punctuation doesn't leave a space before it.
+	front_matter.excerpt = Some(String::from("Fish & *chips*."));
+	front_matter
+		.custom_attributes
+		.insert(String::from("image"), String::from("cover.png"));
+	let front_matter = Arc::new(front_matter);
+	let mut input_output_map = HashMap::new();
+	input_output_map.insert(
+		input_file_path.clone(),
+		GroupedOptionOutputFile {
+			file: OptionOutputFile {
+				front_matter: Some(front_matter.clone()),
+				path: output_file_path.clone(),
+			},
+			group: Some(String::from("posts")),
+			backlinks: Vec::new(),
+			neighbours: Neighbours::default(),
+		},
+	);
+	let mut groups = HashMap::new();
+	groups.insert(
+		String::from("posts"),
+		vec![InputFile {
+			front_matter: front_matter.clone(),
+			path: input_file_path.clone(),
+		}],
+	);
+	let render = |output_file_path: &PathBuf,
+	              front_matter: &FrontMatter,
+	              email: Option<&str>| {
+		let site_info = SiteInfo {
+			email,
+			..make_site_info()
+		};
+		let mut output = BufWriter::new(Vec::new());
+		liquid::process(
+			&mut BufReader::new(Cursor::new(b"{% seo %}")),
+			&mut output,
+			HashMap::new(),
+			&liquid::Context {
+				input_file_path: &input_file_path,
+				output_file_path,
+				front_matter,
+				html_content: None,
+				headings: &[],
+				root_input_dir: &PathBuf::from("./input"),
+				root_output_dir: &PathBuf::from("./output"),
+				input_output_map: &input_output_map,
+				groups: &groups,
+				site_info: &site_info,
+			},
+		);
+		String::from_utf8(output.into_inner().unwrap()).unwrap()
+	};
+
+	assert_eq!(
+		render(&output_file_path, &front_matter, Some("author@example.com")),
+		"<title>Hello | Site</title>\n\
+		<link rel=\"canonical\" href=\"https://example.com/posts/hello.html\">\n\
+		<meta name=\"description\" content=\"Fish &amp; chips.\">\n\
+		<meta name=\"author\" content=\"Author\">\n\
+		<meta property=\"og:title\" content=\"Hello\">\n\
+		<meta property=\"og:site_name\" content=\"Site\">\n\
+		<meta property=\"og:url\" content=\"https://example.com/posts/hello.html\">\n\
+		<meta property=\"og:type\" content=\"article\">\n\
+		<meta property=\"og:description\" content=\"Fish &amp; chips.\">\n\
+		<meta property=\"og:image\" content=\"https://example.com/posts/cover.png\">\n\
+		<meta property=\"article:published_time\" content=\"2001-01-19T20:10:01Z\">\n\
+		<meta name=\"twitter:card\" content=\"summary_large_image\">\n\
+		<meta name=\"twitter:title\" content=\"Hello\">\n\
+		<meta name=\"twitter:description\" content=\"Fish &amp; chips.\">\n\
+		<meta name=\"twitter:image\" content=\"https://example.com/posts/cover.png\">\n\
+		<script type=\"application/ld+json\">{\"@context\":\"https://schema.org\",\
+		\"@type\":\"BlogPosting\",\"headline\":\"Hello\",\
+		\"url\":\"https://example.com/posts/hello.html\",\
+		\"description\":\"Fish & chips.\",\
+		\"image\":\"https://example.com/posts/cover.png\",\
+		\"datePublished\":\"2001-01-19T20:10:01Z\",\
+		\"author\":{\"@type\":\"Person\",\"name\":\"Author\",\"email\":\"author@example.com\"}}\
+		</script>\n"
+	);
+
+	// Pages outside groups, with overrides.
+	let mut about = FrontMatter::with_title(String::from("Site"));
+	for (key, value) in [
+		("description", "About </script> me"),
+		("canonical_url", "/about/"),
+		("author", "Someone Else"),
+	] {
+		about
+			.custom_attributes
+			.insert(key.to_string(), value.to_string());
+	}
+	let seo = render(
+		&PathBuf::from("./output/about/index.html"),
+		&about,
+		Some("author@example.com"),
+	);
+	assert!(seo.starts_with(
+		"<title>Site</title>\n\
+		<link rel=\"canonical\" href=\"https://example.com/about/\">\n\
+		<meta name=\"description\" content=\"About &lt;/script&gt; me\">\n\
+		<meta name=\"author\" content=\"Someone Else\">\n"
+	));
+	assert!(seo.contains("<meta property=\"og:type\" content=\"website\">"));
+	assert!(seo.contains("<meta name=\"twitter:card\" content=\"summary\">"));
+	assert!(seo.contains(
+		"\"@type\":\"WebPage\",\"name\":\"Site\",\
+		\"url\":\"https://example.com/about/\""
+	));
+	assert!(seo.contains("\"description\":\"About <\\/script> me\""));
+	assert!(seo.contains(
+		"\"author\":{\"@type\":\"Person\",\"name\":\"Someone Else\"}"
+	));
+
+	// The email is only published when opted into through seo_email.
+	assert!(render(&output_file_path, &front_matter, None)
+		.contains("\"author\":{\"@type\":\"Person\",\"name\":\"Author\"}}"));
+}
+
+#[test]
+fn test_not_found_page() {
+	let output_dir = TempDir::new("not_found");
+	let missing = PathBuf::from("posts/missing.html");
+	assert_eq!(
+		http::resolve_path(&missing, &output_dir),
+		(output_dir.join("posts/missing.html"), "200 OK")
+	);
+	std::fs::write(output_dir.join("index.html"), "<p>Home</p>").unwrap();
+	std::fs::write(output_dir.join("404.html"), "<p>Gone</p>").unwrap();
+	assert_eq!(
+		http::resolve_path(&missing, &output_dir),
+		(output_dir.join("404.html"), "404 Not Found")
+	);
+	assert_eq!(
+		http::resolve_path(&PathBuf::from(""), &output_dir),
+		(output_dir.join("index.html"), "200 OK")
+	);
+
+	// Links on the 404 page work at any depth.
+	let not_found = output_dir.join("404.html");
+	assert_eq!(
+		liquid::make_relative_link(
+			&not_found,
+			&output_dir.join("css/style.css"),
+			&output_dir,
+			"https://example.com/"
+		),
+		"/css/style.css"
+	);
+	assert_eq!(
+		liquid::make_relative_link(
+			&not_found,
+			&output_dir.join("posts/index.html"),
+			&output_dir,
+			"https://example.com/"
+		),
+		"/posts/"
+	);
+	// Sites served from a subpath keep their links within it.
+	assert_eq!(
+		liquid::make_relative_link(
+			&not_found,
+			&output_dir.join("posts/index.html"),
+			&output_dir,
+			"https://example.com/blog/"
+		),
+		"/blog/posts/"
+	);
+	let mut site_info = make_site_info();
+	site_info.base_url = "https://example.com/blog";
+	let front_matter = FrontMatter::with_title("Gone".to_string());
+	let input_output_map = HashMap::from([(
+		PathBuf::from("input/index.md"),
+		GroupedOptionOutputFile {
+			file: OptionOutputFile {
+				front_matter: None,
+				path: output_dir.join("index.html"),
+			},
+			group: None,
+			backlinks: Vec::new(),
+			neighbours: Neighbours::default(),
+		},
+	)]);
+	let context = liquid::Context {
+		input_file_path: &PathBuf::from("input/404.md"),
+		output_file_path: &not_found,
+		front_matter: &front_matter,
+		html_content: None,
+		headings: &[],
+		root_input_dir: &PathBuf::from("input"),
+		root_output_dir: &output_dir,
+		input_output_map: &input_output_map,
+		groups: &HashMap::new(),
+		site_info: &site_info,
+	};
+	assert_eq!(
+		markdown::to_html("[Home](index.md)", &context),
+		"<p><a href=\"/blog/\">Home</a></p>\n"
+	);
+
+	let mut input_output_map = HashMap::new();
+	for name in ["index", "404"] {
+		input_output_map.insert(
+			PathBuf::from(format!("input/{}.md", name)),
+			GroupedOptionOutputFile {
+				file: OptionOutputFile {
+					front_matter: Some(make_front_matter(name, None)),
+					path: output_dir.join(format!("{}.html", name)),
+				},
+				group: None,
+				backlinks: Vec::new(),
+				neighbours: Neighbours::default(),
+			},
+		);
+	}
+	robots::write_sitemap_xml(
+		&output_dir,
+		"https://example.com/",
+		&input_output_map,
+		&HashMap::new(),
+		&HashMap::new(),
+	);
+	let sitemap =
+		std::fs::read_to_string(output_dir.join("sitemap.xml")).unwrap();
+	assert!(sitemap.contains("<loc>https://example.com/</loc>"));
+	assert!(!sitemap.contains("404"));
+}
@@ -1,26 +1,39 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::ffi::OsStr;
 use std::fs;
 use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use std::time::UNIX_EPOCH;
 
+use crate::backlinks;
+use crate::css;
+use crate::feed::FeedFormat;
 use crate::front_matter;
-use crate::markdown::GroupedOptionOutputFile;
+use crate::markdown::{Extensions, GroupedOptionOutputFile};
+use crate::minify;
+use crate::precompress;
+use crate::search;
 
 pub const ASCII_EXTENSION: &str = "asc";
 pub const CSS_EXTENSION: &str = "css";
 pub const GIF_EXTENSION: &str = "gif";
 pub const HTML_EXTENSION: &str = "html";
+pub const JSON_EXTENSION: &str = "json";
 pub const JPEG_EXTENSION: &str = "jpeg";
 pub const JPG_EXTENSION: &str = "jpg";
+pub const JS_EXTENSION: &str = "js";
 pub const MARKDOWN_EXTENSION: &str = "md";
 pub const PNG_EXTENSION: &str = "png";
 pub const TXT_EXTENSION: &str = "txt";
 pub const XML_EXTENSION: &str = "xml";
 
+// Output of 404.md or 404.html in the input directory, served by hosts (and the
+// built-in HTTP server) for missing files.
+pub const NOT_FOUND_PAGE: &str = "404.html";
+
 // Special identifier making JavaScript reload the current page.
 pub const RELOAD_CURRENT: &str = "*";
 
@@ -29,12 +42,79 @@ pub struct Refresh {
 	pub file: Option<String>,
 }
 
-// Decided not to put email in there because I was worried it would drift away
-// from the public key file linked in my about.md.
-// Decided not to put base URL in there because I want to encourage paths that
-// don't depend on it in html/md files.
+// State gathered while generating pages, as opposed to the options in Config.
+// Created once per run and kept across rebuilds in watch mode.
+#[derive(Default)]
+pub struct BuildState {
+	// Raised once a code block has been highlighted, for writing highlight.css.
+	pub highlighted: AtomicBool,
+	// Links between pages found while rendering.
+	pub links: backlinks::Graph,
+	pub minified_bytes: minify::Savings,
+	// Outputs checked for precompressed variants.
+	pub precompressed: precompress::Checked,
+	// Pages indexed for search.
+	pub search_entries: search::Entries,
+}
+
 pub struct SiteInfo<'a> {
 	pub title: &'a str,
+	// Only used by {% seo %}, which needs absolute URLs and author details for
+	// metadata read by other sites. Paths in html/md files should not depend
+	// on the base URL, so it is not exposed as a variable.
+	pub base_url: &'a str,
+	pub author: &'a str,
+	// None unless the email is to be published through seo_email.
+	pub email: Option<&'a str>,
+	pub markdown_extensions: Extensions,
+	// Maps admonition kinds to CSS classes.
+	pub admonitions: &'a BTreeMap<String, String>,
+	// Sizes attribute of images with resized variants, unless empty.
+	pub image_sizes: &'a str,
+	// Widths of resized variants of PNG and JPEG images.
+	pub image_widths: &'a [u32],
+	// Counts bytes saved by minifying HTML outputs, None when not minifying.
+	pub minify: Option<&'a minify::Savings>,
+	// Maps groups to the formats of their feeds, if not only Atom.
+	pub feeds: &'a BTreeMap<String, Vec<FeedFormat>>,
+	// Raised once a code block has been highlighted, for writing highlight.css.
+	pub highlighted: &'a AtomicBool,
+	// Links between pages found while rendering.
+	pub links: &'a backlinks::Graph,
+	// Maximum number of entries in page.related, 0 for no limit.
+	pub related_limit: usize,
+	// None unless writing a search index.
+	pub search: Option<search::Indexer<'a>>,
+}
+
+pub fn escape_html(text: &str) -> String {
+	let mut result = String::with_capacity(text.len());
+	for c in text.chars() {
+		match c {
+			'&' => result.push_str("&amp;"),
+			'<' => result.push_str("&lt;"),
+			'>' => result.push_str("&gt;"),
+			'"' => result.push_str("&quot;"),
+			'\'' => result.push_str("&#39;"),
+			_ => result.push(c),
+		}
+	}
+	result
+}
+
+pub fn escape_json(text: &str) -> String {
+	let mut result = String::with_capacity(text.len());
+	for c in text.chars() {
+		match c {
+			'"' => result.push_str("\\\""),
+			'\\' => result.push_str("\\\\"),
+			c if (c as u32) < 0x20 => {
+				result.push_str(&format!("\\u{:04x}", c as u32))
+			}
+			_ => result.push(c),
+		}
+	}
+	result
 }
 
 pub fn write_to_stream<T: Write>(buffer: &[u8], stream: &mut T) {
@@ -52,6 +132,7 @@ pub fn copy_files_with_prefix(
 	files: &[PathBuf],
 	input_dir: &PathBuf,
 	output_dir: &Path,
+	bundles: &css::Bundles,
 ) {
 	let mut input_prefix = input_dir.clone();
 	if let Some(first) = files.first() {
@@ -76,6 +157,12 @@ pub fn copy_files_with_prefix(
 				)
 			});
 		}
+		if let Some(bundle) = bundles.get(file_name) {
+			fs::write(&target, &bundle.content).unwrap_or_else(|e| {
+				panic!("Failed writing {}: {}", target.display(), e)
+			});
+			continue;
+		}
 		fs::copy(file_name, &target).unwrap_or_else(|e| {
 			panic!(
 				"Failed copying {} to {}: {}",
@@ -133,6 +220,38 @@ pub fn make_relative(input_file_path: &Path, input_dir: &Path) -> PathBuf {
 	input_dir.join(strip_prefix(input_file_path, &absolute_input_dir))
 }
 
+// The candidate closest to the target by edit distance, if close enough to
+// plausibly be a typo.
+pub fn suggest_closest<I: Iterator<Item = String>>(
+	target: &str,
+	candidates: I,
+) -> Option<String> {
+	let target = target.to_lowercase();
+	let max_distance = (target.chars().count() / 3).max(2);
+	candidates
+		.map(|candidate| {
+			(edit_distance(&target, &candidate.to_lowercase()), candidate)
+		})
+		.filter(|(distance, _)| *distance <= max_distance)
+		.min()
+		.map(|(_, candidate)| candidate)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+	let b: Vec<char> = b.chars().collect();
+	let mut previous: Vec<usize> = (0..=b.len()).collect();
+	for (i, a_char) in a.chars().enumerate() {
+		let mut current = vec![i + 1; b.len() + 1];
+		for (j, b_char) in b.iter().enumerate() {
+			let substitution = previous[j] + usize::from(a_char != *b_char);
+			current[j + 1] =
+				substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+		}
+		previous = current;
+	}
+	previous[b.len()]
+}
+
 pub fn capitalize(input: &str) -> String {
 	let mut output = String::with_capacity(input.len());
 	let mut chars = input.chars();
@@ -167,8 +286,10 @@ pub fn find_newest_file<'a, T>(
 			continue;
 		};
 
+		// Virtual inputs such as archive pages have no file to check.
 		if !supported_extensions.iter().any(|e| e == &extension)
 			|| input_file.starts_with(&excluded_folder)
+			|| !input_file.exists()
 		{
 			continue;
 		}
@@ -3,29 +3,39 @@ use std::ffi::{OsStr, OsString};
 use std::fs;
 use std::io::ErrorKind;
 use std::path::PathBuf;
+use std::sync::atomic::Ordering;
 use std::sync::{mpsc::channel, Arc, Condvar, Mutex};
 use std::time::Duration;
 
 use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
 
+use crate::assets;
+use crate::backlinks;
 use crate::config::{make_site_info, Config};
+use crate::css;
+use crate::highlight;
 use crate::markdown;
 use crate::markdown::{
-	parse_fm_and_compute_output_path, GroupedOptionOutputFile, InputFile,
-	OptionOutputFile,
+	parse_fm_and_compute_output_path, GroupedOptionOutputFile, Indexes,
+	InputFile, OptionOutputFile,
 };
+use crate::neighbours::Neighbours;
+use crate::precompress;
+use crate::search;
+use crate::shortcode;
 use crate::util;
 use crate::util::{
 	find_newest_file, get_front_matter_and_output_path, make_relative,
-	strip_prefix, translate_input_to_output, Refresh,
+	strip_prefix, BuildState, Refresh,
 };
 
 pub fn run(
 	fs_cond: &Arc<(Mutex<Refresh>, Condvar)>,
 	input_output_map: &mut HashMap<PathBuf, GroupedOptionOutputFile>,
-	groups: &mut HashMap<String, Vec<InputFile>>,
-	tags: &mut HashMap<String, Vec<InputFile>>,
+	indexes: &mut Indexes,
+	bundles: &mut css::Bundles,
 	config: &Config,
+	state: &BuildState,
 ) -> ! {
 	let (tx, rx) = channel();
 	let mut watcher = watcher(tx, Duration::from_millis(200))
@@ -45,14 +55,49 @@ pub fn run(
 				let path_to_communicate = get_path_to_refresh(
 					&relative_path,
 					input_output_map,
-					groups,
-					tags,
+					indexes,
+					bundles,
 					config,
+					state,
 				);
+				let stale = state.links.apply(input_output_map);
+				backlinks::rerender(
+					&stale,
+					input_output_map,
+					&indexes.groups,
+					config,
+					state,
+				);
+				// The first highlighted code block of the site may have been
+				// added.
+				if state.highlighted.load(Ordering::Relaxed)
+					&& !config
+						.output_dir
+						.join(highlight::STYLESHEET_PATH)
+						.exists()
+				{
+					highlight::write_stylesheet(
+						&config.input_dir,
+						&config.output_dir,
+					);
+				}
+				if let Some(search_index) = &config.search_index {
+					search::write_index(
+						&state.search_entries,
+						&config.output_dir.join(search_index),
+					);
+				}
 				println!(
 					"Path to communicate in response to write/create of {}: {:?}",
 					relative_path.display(), path_to_communicate
 				);
+				if config.precompress {
+					let count = precompress::run(
+						&config.output_dir,
+						&state.precompressed,
+					);
+					println!("Precompressed {} files.", count);
+				}
 				if path_to_communicate.is_some() {
 					let (mutex, cvar) = &**fs_cond;
 
@@ -83,9 +128,10 @@ pub fn run(
 fn get_path_to_refresh(
 	input_file_path: &PathBuf,
 	input_output_map: &mut HashMap<PathBuf, GroupedOptionOutputFile>,
-	groups: &mut HashMap<String, Vec<InputFile>>,
-	tags: &mut HashMap<String, Vec<InputFile>>,
+	indexes: &mut Indexes,
+	bundles: &mut css::Bundles,
 	config: &Config,
+	state: &BuildState,
 ) -> Option<String> {
 	let css_extension = OsStr::new(util::CSS_EXTENSION);
 	let html_extension = OsStr::new(util::HTML_EXTENSION);
@@ -106,16 +152,16 @@ fn get_path_to_refresh(
 			input_file_path,
 			&config.input_dir,
 			&config.output_dir,
+			&config.excerpt_separator,
 		);
-		let site_info = make_site_info(config);
+		let site_info = make_site_info(config, state);
 		markdown::reindex(
 			input_file_path,
 			&grouped_file,
 			&config.input_dir,
 			&config.output_dir,
 			input_output_map,
-			groups,
-			tags,
+			indexes,
 			&site_info,
 		);
 		if config.deploy && !grouped_file.file.front_matter.published {
@@ -129,7 +175,7 @@ fn get_path_to_refresh(
 			&config.input_dir,
 			&config.output_dir,
 			input_output_map,
-			groups,
+			&indexes.groups,
 			&site_info,
 		);
 		if let Some(group) = generated_file.group {
@@ -148,7 +194,7 @@ fn get_path_to_refresh(
 						&config.input_dir,
 						&config.output_dir,
 						input_output_map,
-						groups,
+						&indexes.groups,
 						&site_info,
 					);
 				}
@@ -156,28 +202,85 @@ fn get_path_to_refresh(
 		}
 		Some(generated_file.file.path.to_string_lossy().to_string())
 	} else if input_file_path.extension() == Some(html_extension) {
-		handle_html_updated(input_file_path, input_output_map, groups, config)
+		handle_html_updated(
+			input_file_path,
+			input_output_map,
+			&mut indexes.groups,
+			config,
+			state,
+		)
 	} else if input_file_path.extension() == Some(css_extension) {
-		util::copy_files_with_prefix(
-			&[input_file_path.clone()],
-			&config.input_dir,
-			&config.output_dir,
-		);
+		// Rebuild the bundles importing the file, or the file itself.
+		let mut changed = css::find_importing(bundles, input_file_path);
+		let partial = config.bundle_css
+			&& css::is_partial(input_file_path, &config.input_dir);
+		if !changed.contains(input_file_path) && !partial {
+			changed.push(input_file_path.clone());
+		}
+		if config.bundle_css {
+			for path in &changed {
+				bundles.insert(
+					path.clone(),
+					css::bundle(path, &config.input_dir, &config.output_dir),
+				);
+			}
+		}
 
-		match input_output_map.entry(input_file_path.clone()) {
-			Entry::Occupied(..) => {}
-			Entry::Vacant(ve) => {
-				ve.insert(GroupedOptionOutputFile {
-					file: OptionOutputFile {
-						path: translate_input_to_output(
-							input_file_path,
-							&config.input_dir,
-							&config.output_dir,
-						),
-						front_matter: None,
-					},
-					group: None,
-				});
+		let mut renamed = false;
+		for path in &changed {
+			util::copy_files_with_prefix(
+				std::slice::from_ref(path),
+				&config.input_dir,
+				&config.output_dir,
+				bundles,
+			);
+
+			let output_file_path = assets::make_output_path(
+				path,
+				&config.input_dir,
+				&config.output_dir,
+				config.fingerprint_assets.then_some(&*bundles),
+			);
+			match input_output_map.entry(path.clone()) {
+				Entry::Occupied(mut oe) => {
+					renamed |= oe.get().file.path != output_file_path;
+					oe.get_mut().file.path = output_file_path;
+				}
+				Entry::Vacant(ve) => {
+					ve.insert(GroupedOptionOutputFile {
+						file: OptionOutputFile {
+							path: output_file_path,
+							front_matter: None,
+						},
+						group: None,
+						backlinks: Vec::new(),
+						neighbours: Neighbours::default(),
+					});
+				}
+			}
+		}
+
+		if config.fingerprint_assets {
+			assets::write_fingerprinted(
+				&changed,
+				&config.input_dir,
+				&config.output_dir,
+				input_output_map,
+				bundles,
+			);
+			assets::write_manifest(
+				&config.input_dir,
+				&config.output_dir,
+				input_output_map,
+			);
+			// Pages linking the bundles through asset_url need their new names.
+			if renamed {
+				reprocess_pages(
+					input_output_map,
+					&indexes.groups,
+					config,
+					state,
+				);
 			}
 		}
 
@@ -187,13 +290,62 @@ fn get_path_to_refresh(
 	}
 }
 
+fn reprocess_pages(
+	input_output_map: &HashMap<PathBuf, GroupedOptionOutputFile>,
+	groups: &HashMap<String, Vec<InputFile>>,
+	config: &Config,
+	state: &BuildState,
+) {
+	let site_info = make_site_info(config, state);
+	let files = markdown::get_files(&config.input_dir);
+	for file_name in &files.markdown {
+		if let Some((front_matter, output_file_path)) =
+			get_front_matter_and_output_path(
+				file_name,
+				input_output_map,
+				config.deploy,
+			) {
+			markdown::process_file(
+				file_name,
+				output_file_path,
+				front_matter,
+				&config.input_dir,
+				&config.output_dir,
+				input_output_map,
+				groups,
+				&site_info,
+			);
+		}
+	}
+	for file_name in &files.html {
+		if let Some((front_matter, output_file_path)) =
+			get_front_matter_and_output_path(
+				file_name,
+				input_output_map,
+				config.deploy,
+			) {
+			markdown::process_template_file(
+				file_name,
+				output_file_path,
+				front_matter,
+				&config.input_dir,
+				&config.output_dir,
+				input_output_map,
+				groups,
+				&site_info,
+			);
+		}
+	}
+}
+
 fn handle_html_updated(
 	input_file_path: &PathBuf,
 	input_output_map: &mut HashMap<PathBuf, GroupedOptionOutputFile>,
 	groups: &mut HashMap<String, Vec<InputFile>>,
 	config: &Config,
+	state: &BuildState,
 ) -> Option<String> {
-	let site_info = make_site_info(config);
+	let site_info = make_site_info(config, state);
 	let parent_path = input_file_path.parent().unwrap_or_else(|| {
 		panic!(
 			"Path without a parent directory?: {}",
@@ -310,8 +462,11 @@ fn handle_html_updated(
 			find_newest_file(&processed_files, &config.input_dir)
 				.map(|g| g.file.path.to_string_lossy().to_string())
 		}
-	} else if parent_path_file_name == "_includes" {
-		// Since we don't track what includes what, just do a full refresh.
+	} else if parent_path_file_name == "_includes"
+		|| parent_path_file_name == shortcode::DIRECTORY
+	{
+		// Since we don't track what includes or uses what, just do a full
+		// refresh.
 		let files = markdown::get_files(&config.input_dir);
 		for file_name in &files.markdown {
 			if let Some((front_matter, output_file_path)) =
@@ -341,6 +496,7 @@ fn handle_html_updated(
 			input_file_path,
 			&config.input_dir,
 			&config.output_dir,
+			&config.excerpt_separator,
 		);
 		input_output_map
 			.insert(input_file_path.clone(), grouped_file.clone_to_option());